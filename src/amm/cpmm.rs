@@ -1,51 +1,119 @@
-use crate::LiquidityPool;
+use crate::{LiquidityPool, Numeric, Rounding, Side};
 use rust_decimal::Decimal;
 
 #[derive(Debug, Default, Clone)]
-pub struct ConstantProductMarketMaker {
-    reserves_a: Decimal,
-    reserves_b: Decimal,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstantProductMarketMaker<N = Decimal> {
+    reserves_a: N,
+    reserves_b: N,
 }
 
+#[cfg(feature = "codec")]
+crate::scale::scale_codec!(ConstantProductMarketMaker<N> {
+    reserves_a: N,
+    reserves_b: N,
+});
+
 /// Constant product market maker
 ///
 /// The Constant Product Market Maker (CPMM) is a type of Automated Market Maker (AMM) used in decentralized exchanges.
 /// It maintains a constant product of two assets in a liquidity pool, meaning that as one asset is bought, the price of
 /// the other asset must decrease to maintain the constant product. The CPMM is used to determine the price of an asset
 /// in the pool.
-impl LiquidityPool for ConstantProductMarketMaker {
+impl<N: Numeric> LiquidityPool<N> for ConstantProductMarketMaker<N> {
     /// Sets the current reserves of the pool.
-    fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal) {
+    fn set_reserves(&mut self, reserves_a: N, reserves_b: N) {
         self.reserves_a = reserves_a;
         self.reserves_b = reserves_b;
     }
 
     /// Returns the current reserves of the pool.
-    fn reserves(&self) -> (Decimal, Decimal) {
+    fn reserves(&self) -> (N, N) {
         (self.reserves_a, self.reserves_b)
     }
 
     /// Computes the price of token A in terms of token B, given an amount of token A.
-    fn price_a(&self, amount_a: Decimal) -> Decimal {
-        if self.reserves_a == Decimal::ZERO {
-            Decimal::ZERO
+    fn price_a(&self, amount_a: N) -> N {
+        if self.reserves_a == N::ZERO {
+            N::ZERO
         } else {
-            self.reserves_b
-                .checked_div(self.reserves_a - amount_a)
-                .unwrap_or_else(|| Decimal::ZERO)
+            self.reserves_a
+                .checked_sub(amount_a)
+                .and_then(|remaining| self.reserves_b.div_round(remaining, Rounding::Up))
+                .unwrap_or(N::ZERO)
         }
     }
 
     /// Computes the price of token B in terms of token A, given an amount of token B.
-    fn price_b(&self, amount_b: Decimal) -> Decimal {
-        if self.reserves_b == Decimal::ZERO {
-            Decimal::ZERO
+    fn price_b(&self, amount_b: N) -> N {
+        if self.reserves_b == N::ZERO {
+            N::ZERO
         } else {
-            self.reserves_a
-                .checked_div(self.reserves_b - amount_b)
-                .unwrap_or_else(|| Decimal::ZERO)
+            self.reserves_b
+                .checked_sub(amount_b)
+                .and_then(|remaining| self.reserves_a.div_round(remaining, Rounding::Up))
+                .unwrap_or(N::ZERO)
         }
     }
+
+    /// Computes the amount taken out on the given side when paying exactly `amount_in` of the
+    /// other token.
+    ///
+    /// Along the constant product curve this is `reserves_out * amount_in / (reserves_in +
+    /// amount_in)`.
+    fn amount_out(&self, side: Side, amount_in: N) -> N {
+        let (reserves_out, reserves_in) = match side {
+            Side::A => (self.reserves_a, self.reserves_b),
+            Side::B => (self.reserves_b, self.reserves_a),
+        };
+        if amount_in <= N::ZERO {
+            return N::ZERO;
+        }
+        reserves_out
+            .mul_round(amount_in, Rounding::Down)
+            .and_then(|p| p.div_round(reserves_in.checked_add(amount_in)?, Rounding::Down))
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the amount to take out on the given side for the marginal price to reach
+    /// `price`.
+    ///
+    /// Along the constant product curve this is `reserves_out - sqrt(reserves_in * reserves_out
+    /// / price)`.
+    fn amount_to_marginal_price(&self, side: Side, price: N) -> N {
+        let (reserves_out, reserves_in) = match side {
+            Side::A => (self.reserves_a, self.reserves_b),
+            Side::B => (self.reserves_b, self.reserves_a),
+        };
+        reserves_in
+            .mul_round(reserves_out, Rounding::Nearest)
+            .and_then(|k| k.div_round(price, Rounding::Nearest))
+            .and_then(|p| p.sqrt())
+            .and_then(|remaining| reserves_out.checked_sub(remaining))
+            .map(|amount| amount.max(N::ZERO))
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the price of the next infinitesimal unit after taking `amount` out on the given
+    /// side.
+    ///
+    /// Along the constant product curve this is the spot price of the pool after the trade,
+    /// `reserves_in * reserves_out / (reserves_out - amount)^2`.
+    fn marginal_price_after(&self, side: Side, amount: N) -> N {
+        let (reserves_out, reserves_in) = match side {
+            Side::A => (self.reserves_a, self.reserves_b),
+            Side::B => (self.reserves_b, self.reserves_a),
+        };
+        let remaining = match reserves_out.checked_sub(amount) {
+            Some(remaining) if remaining > N::ZERO => remaining,
+            _ => return N::ZERO,
+        };
+        reserves_in
+            .mul_round(reserves_out, Rounding::Nearest)
+            .and_then(|p| p.div_round(remaining, Rounding::Nearest))
+            .and_then(|p| p.div_round(remaining, Rounding::Nearest))
+            .unwrap_or(N::ZERO)
+    }
 }
 
 #[cfg(test)]
@@ -149,4 +217,89 @@ mod tests {
         assert_eq!(pool.reserves_a.round_dp(2), dec!(90));
         assert_eq!(pool.reserves_b.round_dp(2), dec!(222.22));
     }
+
+    #[test]
+    fn test_spot_price() {
+        // Create a new liquidity pool with 100 units of token A and 200 units of token B.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Assert that the spot price is the ratio of the reserves.
+        assert_eq!(pool.spot_price(), dec!(2));
+        assert_eq!(pool.price(Side::B, Decimal::ZERO), dec!(0.5));
+    }
+
+    #[test]
+    fn test_marginal_price_after() {
+        // Create a new liquidity pool with 100 units of token A and 200 units of token B.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Compute the marginal price after taking 50 units of token A out of the pool.
+        let marginal_price = pool.marginal_price_after(Side::A, dec!(50));
+
+        // Assert that it matches the spot price of the pool once the trade is executed.
+        pool.swap_a(dec!(50));
+        assert_eq!(marginal_price, dec!(8));
+        assert_eq!(pool.spot_price(), dec!(8));
+    }
+
+    #[test]
+    fn test_price_impact() {
+        // Create a new liquidity pool with 100 units of token A and 200 units of token B.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Taking 50 units of token A out costs 200 units of token B, 4 per unit.
+        assert_eq!(pool.effective_price(Side::A, dec!(50)), dec!(4));
+
+        // Assert that the trade pays twice the spot price.
+        assert_eq!(pool.price_impact(Side::A, dec!(50)), dec!(1));
+        assert_eq!(pool.price_impact(Side::B, Decimal::ZERO), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_amount_out() {
+        // Create a new liquidity pool with 100 units of token A and 200 units of token B.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Paying exactly 200 units of token B takes 50 units of token A out.
+        assert_eq!(pool.amount_out(Side::A, dec!(200)), dec!(50));
+
+        // Assert that swapping the exact input leaves the product unchanged.
+        assert_eq!(pool.swap_exact_in(Side::A, dec!(200)), dec!(50));
+        assert_eq!(pool.reserves(), (dec!(50), dec!(400)));
+    }
+
+    #[test]
+    fn test_amount_to_marginal_price() {
+        // Create a new liquidity pool with 100 units of token A and 200 units of token B.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Taking 50 units of token A out moves its price from 2 to 8.
+        assert_eq!(pool.amount_to_marginal_price(Side::A, dec!(8)), dec!(50));
+
+        // Assert that prices already reached need no trade.
+        assert_eq!(
+            pool.amount_to_marginal_price(Side::A, dec!(1)),
+            Decimal::ZERO
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        // Create a new liquidity pool with 100 units of token A and 200 units of token B.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Assert that the deserialized pool quotes identically.
+        let json = serde_json::to_string(&pool).unwrap();
+        let restored: ConstantProductMarketMaker = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.reserves(), pool.reserves());
+        assert_eq!(restored.price_a(dec!(10)), pool.price_a(dec!(10)));
+        assert_eq!(restored.price_b(dec!(10)), pool.price_b(dec!(10)));
+    }
 }
@@ -1,57 +1,89 @@
-use crate::LiquidityPool;
+use crate::{LiquidityPool, Numeric, Rounding, Side};
 use rust_decimal::Decimal;
 
 #[derive(Debug, Default, Clone)]
-pub struct ConstantSumMarketMaker {
-    reserves_a: Decimal,
-    reserves_b: Decimal,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConstantSumMarketMaker<N = Decimal> {
+    reserves_a: N,
+    reserves_b: N,
 }
 
+#[cfg(feature = "codec")]
+crate::scale::scale_codec!(ConstantSumMarketMaker<N> {
+    reserves_a: N,
+    reserves_b: N,
+});
+
 /// Constant sum market maker
 ///
 /// Constant Sum Market Maker (CSMM) is an algorithm used in Automated Market Makers (AMMs) to
 /// maintain a fixed sum of two assets in a pool. When a user buys one asset, the price of the
 /// other asset in the pool increases to maintain the constant sum. CSMM is useful for stablecoin
 /// pools where the sum of the stablecoin prices should remain constant.
-impl LiquidityPool for ConstantSumMarketMaker {
+impl<N: Numeric> LiquidityPool<N> for ConstantSumMarketMaker<N> {
     /// Sets the current reserves of the pool.
-    fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal) {
+    fn set_reserves(&mut self, reserves_a: N, reserves_b: N) {
         self.reserves_a = reserves_a;
         self.reserves_b = reserves_b;
     }
 
     /// Returns the current reserves of the pool.
-    fn reserves(&self) -> (Decimal, Decimal) {
+    fn reserves(&self) -> (N, N) {
         (self.reserves_a, self.reserves_b)
     }
 
     /// Computes the price of token A in terms of token B, given an amount of token A.
-    fn price_a(&self, amount_a: Decimal) -> Decimal {
-        let total_reserves = self.reserves_a + self.reserves_b;
-        if total_reserves == Decimal::ZERO {
-            Decimal::ZERO
+    fn price_a(&self, amount_a: N) -> N {
+        let total_reserves = self.reserves_a.checked_add(self.reserves_b);
+        if total_reserves.unwrap_or(N::ZERO) == N::ZERO {
+            N::ZERO
         } else {
-            let new_reserves_a = self.reserves_a + amount_a;
-            let new_reserves_b = self.reserves_b - (amount_a * self.reserves_b / self.reserves_a);
-            new_reserves_b
-                .checked_div(new_reserves_a)
-                .unwrap_or_else(|| Decimal::ZERO)
+            let new_reserves_a = self.reserves_a.checked_add(amount_a);
+            let new_reserves_b = amount_a
+                .mul_round(self.reserves_b, Rounding::Nearest)
+                .and_then(|p| p.div_round(self.reserves_a, Rounding::Nearest))
+                .and_then(|p| self.reserves_b.checked_sub(p));
+            new_reserves_a
+                .zip(new_reserves_b)
+                .and_then(|(new_reserves_a, new_reserves_b)| {
+                    new_reserves_b.div_round(new_reserves_a, Rounding::Up)
+                })
+                .unwrap_or(N::ZERO)
         }
     }
 
     /// Computes the price of token B in terms of token A, given an amount of token B.
-    fn price_b(&self, amount_b: Decimal) -> Decimal {
-        let total_reserves = self.reserves_a + self.reserves_b;
-        if total_reserves == Decimal::ZERO {
-            Decimal::ZERO
+    fn price_b(&self, amount_b: N) -> N {
+        let total_reserves = self.reserves_a.checked_add(self.reserves_b);
+        if total_reserves.unwrap_or(N::ZERO) == N::ZERO {
+            N::ZERO
         } else {
-            let new_reserves_b = self.reserves_b + amount_b;
-            let new_reserves_a = self.reserves_a - (amount_b * self.reserves_a / self.reserves_b);
-            new_reserves_a
-                .checked_div(new_reserves_b)
-                .unwrap_or_else(|| Decimal::ZERO)
+            let new_reserves_b = self.reserves_b.checked_add(amount_b);
+            let new_reserves_a = amount_b
+                .mul_round(self.reserves_a, Rounding::Nearest)
+                .and_then(|p| p.div_round(self.reserves_b, Rounding::Nearest))
+                .and_then(|p| self.reserves_a.checked_sub(p));
+            new_reserves_b
+                .zip(new_reserves_a)
+                .and_then(|(new_reserves_b, new_reserves_a)| {
+                    new_reserves_a.div_round(new_reserves_b, Rounding::Up)
+                })
+                .unwrap_or(N::ZERO)
         }
     }
+
+    /// Computes the relative difference between the effective price of a trade and the spot
+    /// price of the same side, clamped at zero.
+    ///
+    /// Along this curve the price of a trade falls with its amount, so larger trades pay less
+    /// per unit than the spot price. That discount is not a price impact, and is reported as
+    /// zero rather than as a negative impact.
+    fn price_impact(&self, side: Side, amount: N) -> N {
+        self.effective_price(side, amount)
+            .div_round(self.price(side, N::ZERO), Rounding::Nearest)
+            .and_then(|ratio| ratio.checked_sub(N::ONE))
+            .map_or(N::ZERO, |impact| impact.max(N::ZERO))
+    }
 }
 
 #[cfg(test)]
@@ -144,4 +176,98 @@ mod tests {
         // Check that the reserves are now (10.82, 18).
         assert_eq!(pool.reserves_rounded(), (dec!(10.82), dec!(18)));
     }
+
+    #[test]
+    fn test_spot_price() {
+        // Initialize a new ConstantSumMarketMaker with reserves of (10, 20).
+        let pool = ConstantSumMarketMaker {
+            reserves_a: dec!(10),
+            reserves_b: dec!(20),
+        };
+
+        // Check that the spot price is the ratio of the reserves.
+        assert_eq!(pool.spot_price(), dec!(2));
+    }
+
+    #[test]
+    fn test_marginal_price_after() {
+        // Initialize a new ConstantSumMarketMaker with reserves of (10, 20).
+        let pool = ConstantSumMarketMaker {
+            reserves_a: dec!(10),
+            reserves_b: dec!(20),
+        };
+
+        // Check that the marginal price is the slope of the cost of 1 unit of token A.
+        let marginal_price = pool.marginal_price_after(Side::A, dec!(1)).round_dp(2);
+        assert_eq!(marginal_price, dec!(1.31));
+    }
+
+    #[test]
+    fn test_price_impact_is_never_negative() {
+        // Initialize a new ConstantSumMarketMaker with reserves of (10, 20).
+        let pool = ConstantSumMarketMaker {
+            reserves_a: dec!(10),
+            reserves_b: dec!(20),
+        };
+
+        // Check that trades on either side, which pay less per unit than the spot price along
+        // this curve, report no price impact.
+        for side in [Side::A, Side::B] {
+            for amount in [dec!(0), dec!(0.5), dec!(1), dec!(5)] {
+                assert!(pool.effective_price(side, amount) <= pool.price(side, Decimal::ZERO));
+                assert_eq!(pool.price_impact(side, amount), Decimal::ZERO);
+            }
+        }
+    }
+
+    #[test]
+    fn test_amount_out() {
+        // Initialize a new ConstantSumMarketMaker with reserves of (10, 20).
+        let pool = ConstantSumMarketMaker {
+            reserves_a: dec!(10),
+            reserves_b: dec!(20),
+        };
+
+        // Check that paying the cost of 1 unit of token A takes 1 unit out.
+        let cost_b = pool.cost(Side::A, dec!(1));
+        assert_eq!(pool.amount_out(Side::A, cost_b).round_dp(8), dec!(1));
+    }
+
+    #[test]
+    fn test_amount_to_marginal_price() {
+        // Initialize a new ConstantSumMarketMaker with reserves of (10, 20).
+        let pool = ConstantSumMarketMaker {
+            reserves_a: dec!(10),
+            reserves_b: dec!(20),
+        };
+
+        // Check that the marginal price of token A falls with the amount and never reaches 2.5.
+        assert_eq!(
+            pool.amount_to_marginal_price(Side::A, dec!(2.5)),
+            Decimal::ZERO
+        );
+
+        // Check that prices already reached need no trade.
+        assert_eq!(
+            pool.amount_to_marginal_price(Side::A, dec!(1.5)),
+            Decimal::ZERO
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        // Initialize a new ConstantSumMarketMaker with reserves of (10, 20).
+        let pool = ConstantSumMarketMaker {
+            reserves_a: dec!(10),
+            reserves_b: dec!(20),
+        };
+
+        // Check that the deserialized pool quotes identically.
+        let json = serde_json::to_string(&pool).unwrap();
+        let restored: ConstantSumMarketMaker = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.reserves(), pool.reserves());
+        assert_eq!(restored.price_a(dec!(5)), pool.price_a(dec!(5)));
+        assert_eq!(restored.price_b(dec!(5)), pool.price_b(dec!(5)));
+    }
 }
@@ -2,4 +2,4 @@
 
 pub mod cpmm;
 pub mod csmm;
-pub mod pidmm;
\ No newline at end of file
+pub mod pidmm;
@@ -1,144 +1,345 @@
-use crate::LiquidityPool;
-use rust_decimal::{Decimal, MathematicalOps};
-use rust_decimal_macros::dec;
+use crate::{ema::EmaOracle, LiquidityPool, Numeric, Rounding, Side};
+use alloc::{vec, vec::Vec};
+use rust_decimal::Decimal;
 
 #[derive(Debug, Clone)]
-pub struct PIDMarketMaker {
-    reserves_a: Decimal,
-    reserves_b: Decimal,
-    pub target: Decimal,
-    pub pid: PIDController,
-    cached_price: Decimal,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PIDMarketMaker<N = Decimal> {
+    reserves_a: N,
+    reserves_b: N,
+    pub target: N,
+    pub pid: PIDController<N>,
+    pub target_oracle: Option<EmaOracle>,
+    cached_price: N,
 }
 
+#[cfg(feature = "codec")]
+crate::scale::scale_codec!(PIDMarketMaker<N> {
+    reserves_a: N,
+    reserves_b: N,
+    target: N,
+    pid: PIDController<N>,
+    target_oracle: Option<EmaOracle>,
+    cached_price: N,
+});
+
 /// PID market maker
 ///
 /// A PID controller is used to maintain a target price for an asset pair by adjusting the ratio of
 /// the assets in the pool. The proportional, integral, and derivative terms of the controller are
 /// tuned based on historical data to optimize its performance in maintaining the target price.
-impl LiquidityPool for PIDMarketMaker {
+impl<N: Numeric> LiquidityPool<N> for PIDMarketMaker<N> {
     /// Sets the current reserves of the pool.
-    fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal) {
+    fn set_reserves(&mut self, reserves_a: N, reserves_b: N) {
         self.reserves_a = reserves_a;
         self.reserves_b = reserves_b;
 
-        let total_reserves = self.reserves_a + self.reserves_b;
-        if total_reserves == Decimal::ZERO {
-            self.cached_price = Decimal::ZERO;
-        } else {
-            let new_ratio = self.compute_ratio(total_reserves);
-            self.cached_price = new_ratio
-                .checked_mul(self.target)
-                .unwrap_or_else(|| Decimal::ZERO);
+        match self.reserves_a.checked_add(self.reserves_b) {
+            Some(total_reserves) if total_reserves != N::ZERO => {
+                let new_ratio = self.compute_ratio(total_reserves);
+                self.cached_price = new_ratio
+                    .mul_round(self.target, Rounding::Nearest)
+                    .unwrap_or(N::ZERO);
+            }
+            _ => self.cached_price = N::ZERO,
         }
     }
 
     /// Returns the current reserves of the pool.
-    fn reserves(&self) -> (Decimal, Decimal) {
+    fn reserves(&self) -> (N, N) {
         (self.reserves_a, self.reserves_b)
     }
 
     /// Computes the price of token A in terms of token B, given an amount of token A.
-    fn price_a(&self, amount_a: Decimal) -> Decimal {
-        if amount_a == Decimal::ZERO {
-            return Decimal::ZERO;
-        }
-        self.cached_price
-            .checked_div(amount_a)
-            .unwrap_or_else(|| Decimal::ZERO)
+    ///
+    /// The quote follows a virtual constant product curve anchored at the controller's mid
+    /// price: `mid_price * reserves_a / (reserves_a - amount_a)`. Small trades pay the mid
+    /// price and larger trades pay progressively more, without ever draining the pool.
+    fn price_a(&self, amount_a: N) -> N {
+        Self::curve_price(self.cached_price, self.reserves_a, amount_a)
     }
 
     /// Computes the price of token B in terms of token A, given an amount of token B.
-    fn price_b(&self, amount_b: Decimal) -> Decimal {
-        if amount_b == Decimal::ZERO {
-            return Decimal::ZERO;
+    ///
+    /// Mirrors [`price_a`](Self::price_a) around the inverse of the mid price:
+    /// `reserves_b / (mid_price * (reserves_b - amount_b))`.
+    fn price_b(&self, amount_b: N) -> N {
+        let mid_price_b = N::ONE
+            .div_round(self.cached_price, Rounding::Nearest)
+            .unwrap_or(N::ZERO);
+        Self::curve_price(mid_price_b, self.reserves_b, amount_b)
+    }
+
+    /// Computes the amount taken out on the given side when paying exactly `amount_in` of the
+    /// other token.
+    ///
+    /// Inverting the pricing curve gives `reserves * amount_in / (mid_price * reserves +
+    /// amount_in)`.
+    fn amount_out(&self, side: Side, amount_in: N) -> N {
+        let reserves = match side {
+            Side::A => self.reserves_a,
+            Side::B => self.reserves_b,
+        };
+        if amount_in <= N::ZERO {
+            return N::ZERO;
         }
-        self.cached_price
-            .checked_mul(amount_b)
-            .unwrap_or_else(|| Decimal::ZERO)
+        self.price(side, N::ZERO)
+            .mul_round(reserves, Rounding::Nearest)
+            .and_then(|p| {
+                reserves
+                    .mul_round(amount_in, Rounding::Down)?
+                    .div_round(p.checked_add(amount_in)?, Rounding::Down)
+            })
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the amount to take out on the given side for the marginal price to reach
+    /// `price`.
+    ///
+    /// Inverting the marginal price gives `reserves * (1 - sqrt(mid_price / price))`.
+    fn amount_to_marginal_price(&self, side: Side, price: N) -> N {
+        let reserves = match side {
+            Side::A => self.reserves_a,
+            Side::B => self.reserves_b,
+        };
+        self.price(side, N::ZERO)
+            .div_round(price, Rounding::Nearest)
+            .and_then(|ratio| ratio.sqrt())
+            .and_then(|root| reserves.mul_round(root, Rounding::Nearest))
+            .and_then(|remaining| reserves.checked_sub(remaining))
+            .map(|amount| amount.max(N::ZERO))
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the price of the next infinitesimal unit after taking `amount` out on the given
+    /// side.
+    ///
+    /// Along the pricing curve this is `mid_price * reserves^2 / (reserves - amount)^2`.
+    fn marginal_price_after(&self, side: Side, amount: N) -> N {
+        let remaining = match side {
+            Side::A => self.reserves_a.checked_sub(amount),
+            Side::B => self.reserves_b.checked_sub(amount),
+        };
+        if !matches!(remaining, Some(remaining) if remaining > N::ZERO) {
+            return N::ZERO;
+        }
+        let price = self.price(side, amount);
+        price
+            .mul_round(price, Rounding::Nearest)
+            .and_then(|p| p.div_round(self.price(side, N::ZERO), Rounding::Nearest))
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Returns the target and the controller gains.
+    fn parameters(&self) -> Vec<(&'static str, N)> {
+        vec![
+            ("target", self.target),
+            ("kp", self.pid.kp),
+            ("ki", self.pid.ki),
+            ("kd", self.pid.kd),
+        ]
+    }
+
+    /// Sets the target or one of the controller gains.
+    fn set_parameter(&mut self, name: &str, value: N) -> bool {
+        match name {
+            "target" => self.target = value,
+            "kp" => self.pid.kp = value,
+            "ki" => self.pid.ki = value,
+            "kd" => self.pid.kd = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns the terms of the last control signal computed by the controller.
+    fn pid_terms(&self) -> Option<PidTerms<N>> {
+        self.pid.last_terms()
     }
 }
 
-impl Default for PIDMarketMaker {
+impl<N: Numeric> Default for PIDMarketMaker<N> {
     fn default() -> Self {
         Self {
-            reserves_a: Decimal::ZERO,
-            reserves_b: Decimal::ZERO,
-            target: Decimal::ONE,
+            reserves_a: N::ZERO,
+            reserves_b: N::ZERO,
+            target: N::ONE,
             pid: PIDController::default(),
-            cached_price: Decimal::ZERO,
+            target_oracle: None,
+            cached_price: N::ZERO,
         }
     }
 }
 
-impl PIDMarketMaker {
+impl<N: Numeric> PIDMarketMaker<N> {
+    /// Returns the controller's mid price of token A in terms of token B.
+    ///
+    /// This is the price quoted for an infinitesimally small trade.
+    pub fn mid_price(&self) -> N {
+        self.cached_price
+    }
+
+    /// Sets the target to the value of the target oracle at the given timestamp.
+    ///
+    /// Does nothing if no target oracle is attached. The oracle is fed by the caller, typically
+    /// with a reference price observed on another pool or market.
+    pub fn sync_target(&mut self, timestamp: u64) {
+        if let Some(oracle) = &self.target_oracle {
+            if let Some(target) = N::from_decimal(oracle.value_at(timestamp)) {
+                self.target = target;
+            }
+        }
+    }
+
+    /// Prices `amount` of a token with `reserves` in the pool around the given mid price.
+    ///
+    /// Returns zero when the pool cannot fill the trade.
+    fn curve_price(mid_price: N, reserves: N, amount: N) -> N {
+        if amount < N::ZERO || amount >= reserves {
+            return N::ZERO;
+        }
+        mid_price
+            .mul_round(reserves, Rounding::Up)
+            .and_then(|p| p.div_round(reserves - amount, Rounding::Up))
+            .unwrap_or(N::ZERO)
+    }
+
     /// Computes the ratio of the two assets in the pool.
-    fn compute_ratio(&mut self, total_reserves: Decimal) -> Decimal {
+    fn compute_ratio(&mut self, total_reserves: N) -> N {
         let new_reserves_a = total_reserves
-            .checked_mul(self.target)
+            .mul_round(self.target, Rounding::Nearest)
             .and_then(|p| p.sqrt())
             .map(|p| {
                 self.reserves_a
-                    .checked_mul(p)
-                    .unwrap_or_else(|| Decimal::ZERO)
+                    .mul_round(p, Rounding::Nearest)
+                    .unwrap_or(N::ZERO)
             })
-            .unwrap_or_else(|| Decimal::ZERO);
-        let new_reserves_b = total_reserves - new_reserves_a;
-        let error = self.target
-            - new_reserves_b
-                .checked_div(new_reserves_a)
-                .unwrap_or_else(|| Decimal::ZERO);
+            .unwrap_or(N::ZERO);
+        let error = total_reserves
+            .checked_sub(new_reserves_a)
+            .and_then(|new_reserves_b| new_reserves_b.div_round(new_reserves_a, Rounding::Nearest))
+            .and_then(|ratio| self.target.checked_sub(ratio))
+            .unwrap_or(N::ZERO);
         let control_signal = self.pid.compute(error);
-        let new_ratio = self
-            .reserves_b
-            .checked_div(self.reserves_a + control_signal)
-            .unwrap_or_else(|| Decimal::ZERO);
-        new_ratio
+        self.reserves_a
+            .checked_add(control_signal)
+            .and_then(|p| self.reserves_b.div_round(p, Rounding::Nearest))
+            .unwrap_or(N::ZERO)
     }
 }
 
 /// A PID controller for controlling the asset ratio in a liquidity pool.
 #[derive(Debug, Clone)]
-pub struct PIDController {
-    pub kp: Decimal,     // Proportional gain
-    pub ki: Decimal,     // Integral gain
-    pub kd: Decimal,     // Derivative gain
-    integral: Decimal,   // Integral term accumulator
-    prev_error: Decimal, // Previous error value
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PIDController<N = Decimal> {
+    pub kp: N,     // Proportional gain
+    pub ki: N,     // Integral gain
+    pub kd: N,     // Derivative gain
+    integral: N,   // Integral term accumulator
+    prev_error: N, // Previous error value
+    last_terms: Option<PidTerms<N>>,
+}
+
+#[cfg(feature = "codec")]
+crate::scale::scale_codec!(PIDController<N> {
+    kp: N,
+    ki: N,
+    kd: N,
+    integral: N,
+    prev_error: N,
+    last_terms: Option<PidTerms<N>>,
+});
+
+/// The terms of a control signal computed by a [`PIDController`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PidTerms<N = Decimal> {
+    /// The error the signal was computed for.
+    pub error: N,
+    /// The proportional term.
+    pub proportional: N,
+    /// The integral term.
+    pub integral: N,
+    /// The derivative term.
+    pub derivative: N,
+    /// The control signal, the sum of the three terms.
+    pub output: N,
 }
 
-impl PIDController {
+#[cfg(feature = "codec")]
+crate::scale::scale_codec!(PidTerms<N> {
+    error: N,
+    proportional: N,
+    integral: N,
+    derivative: N,
+    output: N,
+});
+
+impl<N: Numeric> PIDController<N> {
     /// Creates a new PID controller with the given gains.
-    pub fn new(kp: Decimal, ki: Decimal, kd: Decimal) -> Self {
+    pub fn new(kp: N, ki: N, kd: N) -> Self {
         Self {
             kp,
             ki,
             kd,
-            integral: Decimal::ZERO,
-            prev_error: Decimal::ZERO,
+            integral: N::ZERO,
+            prev_error: N::ZERO,
+            last_terms: None,
         }
     }
 
+    /// Returns the terms of the last control signal computed, if any.
+    pub fn last_terms(&self) -> Option<PidTerms<N>> {
+        self.last_terms
+    }
+
     /// Computes the control signal for the given error.
-    pub fn compute(&mut self, error: Decimal) -> Decimal {
-        let proportional_term = self.kp * error;
-        self.integral += error;
-        let integral_term = self.ki * self.integral;
-        let derivative_term = self.kd * (error - self.prev_error);
+    ///
+    /// The integral saturates at the bounds of the number type. A signal that overflows is
+    /// dropped: the controller returns zero and records no terms for it.
+    pub fn compute(&mut self, error: N) -> N {
+        self.integral = self.integral.saturating_add(error);
+        let terms = self.terms(error);
         self.prev_error = error;
-        proportional_term + integral_term + derivative_term
+        self.last_terms = terms;
+        terms.map_or(N::ZERO, |terms| terms.output)
+    }
+
+    /// Computes the terms of the control signal for the given error, or `None` on overflow.
+    fn terms(&self, error: N) -> Option<PidTerms<N>> {
+        let proportional = self.kp.mul_round(error, Rounding::Nearest)?;
+        let integral = self.ki.mul_round(self.integral, Rounding::Nearest)?;
+        let derivative = self
+            .kd
+            .mul_round(error.checked_sub(self.prev_error)?, Rounding::Nearest)?;
+        let output = proportional
+            .checked_add(integral)?
+            .checked_add(derivative)?;
+        Some(PidTerms {
+            error,
+            proportional,
+            integral,
+            derivative,
+            output,
+        })
     }
 }
 
-impl Default for PIDController {
+impl<N: Numeric> Default for PIDController<N> {
     fn default() -> Self {
-        Self::new(dec!(0.1), dec!(0.01), dec!(0.001))
+        let ten = N::from_u64(10);
+        Self::new(
+            N::ONE / ten,
+            N::ONE / (ten * ten),
+            N::ONE / (ten * ten * ten),
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rust_decimal_macros::dec;
 
     #[test]
     fn test_add_liquidity() {
@@ -162,7 +363,7 @@ mod tests {
         pool.set_reserves(dec!(100), dec!(100));
         let price = pool.price_a(dec!(1)).round_dp(2);
         // Check price
-        assert_eq!(price, dec!(1));
+        assert_eq!(price, dec!(1.01));
     }
 
     #[test]
@@ -171,7 +372,7 @@ mod tests {
         pool.set_reserves(dec!(100), dec!(100));
         pool.swap_a(dec!(1));
         // Check reserves
-        assert_eq!(pool.reserves_rounded(), (dec!(99), dec!(101)));
+        assert_eq!(pool.reserves_rounded(), (dec!(99), dec!(101.01)));
     }
 
     #[test]
@@ -180,7 +381,7 @@ mod tests {
         pool.set_reserves(dec!(100), dec!(100));
         let price = pool.price_b(dec!(1)).round_dp(2);
         // Check price
-        assert_eq!(price, dec!(1));
+        assert_eq!(price, dec!(1.01));
     }
 
     #[test]
@@ -189,6 +390,153 @@ mod tests {
         pool.set_reserves(dec!(100), dec!(100));
         pool.swap_b(dec!(1));
         // Check reserves
-        assert_eq!(pool.reserves_rounded(), (dec!(101), dec!(99)));
+        assert_eq!(pool.reserves_rounded(), (dec!(101.01), dec!(99)));
+    }
+
+    #[test]
+    fn test_price_at_mid() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        // An empty trade is quoted at the mid price in both directions
+        assert_eq!(pool.price_a(Decimal::ZERO), pool.mid_price());
+        assert_eq!(
+            pool.price_b(Decimal::ZERO).round_dp(8),
+            (Decimal::ONE / pool.mid_price()).round_dp(8)
+        );
+    }
+
+    #[test]
+    fn test_price_grows_with_size() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        // Larger trades pay a higher average price on both sides
+        let amounts = [dec!(1), dec!(10), dec!(50), dec!(90), dec!(99)];
+        for pair in amounts.windows(2) {
+            assert!(pool.price_a(pair[0]) < pool.price_a(pair[1]));
+            assert!(pool.price_b(pair[0]) < pool.price_b(pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_large_swap_a() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        let mid_price = pool.mid_price();
+        // Buying half of the reserves costs twice the mid price per unit
+        let cost_b = pool.swap_a(dec!(50));
+        assert_eq!(cost_b.round_dp(8), (mid_price * dec!(100)).round_dp(8));
+        assert_eq!(pool.reserves().0, dec!(50));
+    }
+
+    #[test]
+    fn test_large_swap_b() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        let mid_price = pool.mid_price();
+        // Buying half of the reserves costs twice the inverse mid price per unit
+        let cost_a = pool.swap_b(dec!(50));
+        assert_eq!(cost_a.round_dp(8), (dec!(100) / mid_price).round_dp(8));
+        assert_eq!(pool.reserves().1, dec!(50));
+    }
+
+    #[test]
+    fn test_swap_cannot_drain_pool() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        // Trades for the whole reserve or more are not filled
+        assert_eq!(pool.swap_a(dec!(100)), Decimal::ZERO);
+        assert_eq!(pool.swap_b(dec!(150)), Decimal::ZERO);
+        assert_eq!(pool.reserves(), (dec!(100), dec!(100)));
+    }
+
+    #[test]
+    fn test_sync_target() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+
+        // Without an oracle the target is left alone
+        pool.sync_target(10);
+        assert_eq!(pool.target, Decimal::ONE);
+
+        // With an oracle the target follows its smoothed value
+        let mut oracle = EmaOracle::new(10);
+        oracle.update(0, dec!(1));
+        oracle.update(0, dec!(3));
+        pool.target_oracle = Some(oracle);
+        pool.sync_target(10);
+        assert_eq!(pool.target.round_dp(8), dec!(2));
+    }
+
+    #[test]
+    fn test_marginal_price_after() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        let mid_price = pool.mid_price();
+        // Halving the reserves quadruples the marginal price
+        let marginal_price = pool.marginal_price_after(Side::A, dec!(50));
+        assert_eq!(
+            marginal_price.round_dp(8),
+            (mid_price * dec!(4)).round_dp(8)
+        );
+        // Check against the slope of the cost
+        let step = dec!(0.0001);
+        let slope = (pool.cost(Side::A, dec!(50) + step) - pool.cost(Side::A, dec!(50) - step))
+            / (step * Decimal::TWO);
+        assert_eq!(marginal_price.round_dp(4), slope.round_dp(4));
+    }
+
+    #[test]
+    fn test_price_impact() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        // Taking half of the reserves pays twice the mid price on either side
+        assert_eq!(pool.price_impact(Side::A, dec!(50)).round_dp(8), dec!(1));
+        assert_eq!(pool.price_impact(Side::B, dec!(50)).round_dp(8), dec!(1));
+        assert_eq!(pool.spot_price(), pool.mid_price());
+    }
+
+    #[test]
+    fn test_amount_out() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        // Paying the cost of a trade takes the same amount out
+        let cost_b = pool.cost(Side::A, dec!(30));
+        assert_eq!(pool.amount_out(Side::A, cost_b).round_dp(8), dec!(30));
+        let cost_a = pool.cost(Side::B, dec!(70));
+        assert_eq!(pool.amount_out(Side::B, cost_a).round_dp(8), dec!(70));
+    }
+
+    #[test]
+    fn test_amount_to_marginal_price() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        // Halving the reserves quadruples the marginal price
+        let price = pool.mid_price() * dec!(4);
+        let amount = pool.amount_to_marginal_price(Side::A, price);
+        assert_eq!(amount.round_dp(8), dec!(50));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let mut pool = PIDMarketMaker {
+            target_oracle: Some(EmaOracle::new(60)),
+            ..Default::default()
+        };
+        pool.set_reserves(dec!(100), dec!(100));
+        pool.swap_a(dec!(10));
+        pool.swap_b(dec!(5));
+        // The deserialized pool quotes identically
+        let json = serde_json::to_string(&pool).unwrap();
+        let mut restored: PIDMarketMaker = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.reserves(), pool.reserves());
+        assert_eq!(restored.mid_price(), pool.mid_price());
+        assert_eq!(restored.price_a(dec!(10)), pool.price_a(dec!(10)));
+        assert_eq!(restored.price_b(dec!(10)), pool.price_b(dec!(10)));
+        // The controller state carries over, so both pools keep evolving identically
+        assert_eq!(restored.pid.last_terms(), pool.pid.last_terms());
+        assert_eq!(restored.swap_a(dec!(10)), pool.swap_a(dec!(10)));
+        assert_eq!(restored.pid.last_terms(), pool.pid.last_terms());
+        assert_eq!(restored.price_a(dec!(10)), pool.price_a(dec!(10)));
     }
 }
@@ -0,0 +1,280 @@
+// Arbitrage detection and execution
+
+use crate::{
+    router::{Hop, Quote, Router, RouterError},
+    LiquidityPool, Side,
+};
+use alloc::vec::Vec;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+
+/// Number of golden-section steps used to size a cyclic arbitrage.
+const SEARCH_STEPS: usize = 96;
+
+/// A profitable trade between a pool and an external market.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Arbitrage {
+    /// The side of the pool taken out by the trade.
+    pub side: Side,
+    /// The amount taken out of the pool.
+    pub amount: Decimal,
+    /// The amount of the other token paid into the pool.
+    pub cost: Decimal,
+    /// The profit in token B once the amount is sold at the reference price.
+    pub profit: Decimal,
+}
+
+/// A profitable cycle of swaps starting and ending with the same token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CycleArbitrage {
+    /// The quote of the cycle for the profit-maximizing input.
+    pub quote: Quote,
+    /// The profit in the token the cycle starts and ends with.
+    pub profit: Decimal,
+}
+
+/// Computes the profit-maximizing trade against a pool, given an external reference price of
+/// token A in terms of token B.
+///
+/// If the pool sells token A below the reference price, the trade buys token A from the pool
+/// until its marginal price reaches the reference price, and the other way around for token B.
+/// Returns `None` if no trade is profitable.
+pub fn against_price<P: LiquidityPool + ?Sized>(
+    pool: &P,
+    reference_price: Decimal,
+) -> Option<Arbitrage> {
+    if reference_price <= Decimal::ZERO {
+        return None;
+    }
+    let spot_price = pool.spot_price();
+    let (side, target_price) = if spot_price < reference_price {
+        (Side::A, reference_price)
+    } else if spot_price > reference_price {
+        (Side::B, Decimal::ONE / reference_price)
+    } else {
+        return None;
+    };
+    let amount = pool.amount_to_marginal_price(side, target_price);
+    let cost = pool.cost(side, amount);
+    let profit = match side {
+        Side::A => amount * reference_price - cost,
+        Side::B => amount - cost * reference_price,
+    };
+    if amount <= Decimal::ZERO || profit <= Decimal::ZERO {
+        return None;
+    }
+    Some(Arbitrage {
+        side,
+        amount,
+        cost,
+        profit,
+    })
+}
+
+/// Executes the profit-maximizing trade against a pool, bringing its price to the reference
+/// price.
+///
+/// Returns the trade executed, if any was profitable.
+pub fn close<P: LiquidityPool + ?Sized>(
+    pool: &mut P,
+    reference_price: Decimal,
+) -> Option<Arbitrage> {
+    let arbitrage = against_price(pool, reference_price)?;
+    pool.swap(arbitrage.side, arbitrage.amount);
+    Some(arbitrage)
+}
+
+/// Finds the cycles of at most `max_hops` swaps starting and ending with `token` whose profit
+/// exceeds `min_profit`.
+///
+/// Each cycle is sized to maximize its profit. Cycles are sorted from the most to the least
+/// profitable.
+pub fn find_cycles(
+    router: &Router,
+    token: &str,
+    max_hops: usize,
+    min_profit: Decimal,
+) -> Vec<CycleArbitrage> {
+    let mut cycles: Vec<CycleArbitrage> = router
+        .find_paths(token, token, max_hops)
+        .iter()
+        .filter_map(|path| best_cycle(router, path, min_profit))
+        .collect();
+    cycles.sort_by_key(|cycle| core::cmp::Reverse(cycle.profit));
+    cycles
+}
+
+/// Executes a cycle found by [`find_cycles`].
+///
+/// Fails without touching any pool if the cycle is no longer profitable.
+pub fn execute_cycle(router: &mut Router, cycle: &CycleArbitrage) -> Result<Quote, RouterError> {
+    let amount_in = cycle.quote.amount_in();
+    router.swap_path(&cycle.quote.path, amount_in, amount_in)
+}
+
+/// Sizes a cycle to maximize its profit, returning it if the profit exceeds `min_profit`.
+///
+/// The profit of a cycle grows then shrinks with its input, so a golden-section search over
+/// the reserves of the first pool finds the best input.
+fn best_cycle(router: &Router, path: &[Hop], min_profit: Decimal) -> Option<CycleArbitrage> {
+    let first = path.first()?;
+    let (reserves_a, reserves_b) = router.pool(first.pool)?.pool.reserves();
+    let upper = match first.side {
+        Side::A => reserves_b,
+        Side::B => reserves_a,
+    };
+    let profit = |amount_in: Decimal| {
+        router
+            .quote(path, amount_in)
+            .map(|quote| quote.amount_out() - amount_in)
+            .unwrap_or(Decimal::ZERO)
+    };
+    let ratio = dec!(0.6180339887498948482045868344);
+    let mut low = Decimal::ZERO;
+    let mut high = upper;
+    for _ in 0..SEARCH_STEPS {
+        let left = high - (high - low) * ratio;
+        let right = low + (high - low) * ratio;
+        if profit(left) < profit(right) {
+            low = left;
+        } else {
+            high = right;
+        }
+    }
+    let amount_in = (low + high) / Decimal::TWO;
+    let quote = router.quote(path, amount_in).ok()?;
+    let profit = quote.amount_out() - amount_in;
+    if profit <= min_profit.max(Decimal::ZERO) {
+        return None;
+    }
+    Some(CycleArbitrage { quote, profit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpmm::ConstantProductMarketMaker;
+
+    /// A constant product pool relying on the numeric default implementations.
+    #[derive(Clone)]
+    struct NumericPool(ConstantProductMarketMaker);
+
+    impl LiquidityPool for NumericPool {
+        fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal) {
+            self.0.set_reserves(reserves_a, reserves_b)
+        }
+
+        fn reserves(&self) -> (Decimal, Decimal) {
+            self.0.reserves()
+        }
+
+        fn price_a(&self, amount_a: Decimal) -> Decimal {
+            self.0.price_a(amount_a)
+        }
+
+        fn price_b(&self, amount_b: Decimal) -> Decimal {
+            self.0.price_b(amount_b)
+        }
+    }
+
+    fn cpmm(reserves_a: Decimal, reserves_b: Decimal) -> ConstantProductMarketMaker {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(reserves_a, reserves_b);
+        pool
+    }
+
+    #[test]
+    fn test_buy_below_reference_price() {
+        let pool = cpmm(dec!(100), dec!(200));
+
+        // Token A trades at 2 in the pool and 8 outside.
+        let arbitrage = against_price(&pool, dec!(8)).unwrap();
+        assert_eq!(arbitrage.side, Side::A);
+        assert_eq!(arbitrage.amount, dec!(50));
+        assert_eq!(arbitrage.cost, dec!(200));
+        assert_eq!(arbitrage.profit, dec!(200));
+    }
+
+    #[test]
+    fn test_sell_above_reference_price() {
+        let pool = cpmm(dec!(100), dec!(200));
+
+        // Token A trades at 2 in the pool and 0.5 outside, so token B is cheap in the pool.
+        let arbitrage = against_price(&pool, dec!(0.5)).unwrap();
+        assert_eq!(arbitrage.side, Side::B);
+        assert_eq!(arbitrage.amount, dec!(100));
+        assert_eq!(arbitrage.cost, dec!(100));
+        assert_eq!(arbitrage.profit, dec!(50));
+    }
+
+    #[test]
+    fn test_no_arbitrage_at_reference_price() {
+        let pool = cpmm(dec!(100), dec!(200));
+
+        // A pool at the reference price offers nothing.
+        assert_eq!(against_price(&pool, dec!(2)), None);
+        assert_eq!(against_price(&pool, Decimal::ZERO), None);
+    }
+
+    #[test]
+    fn test_numeric_matches_closed_form() {
+        let pool = NumericPool(cpmm(dec!(100), dec!(200)));
+
+        // The numeric search lands on the closed-form trade.
+        let arbitrage = against_price(&pool, dec!(8)).unwrap();
+        assert_eq!(arbitrage.side, Side::A);
+        assert_eq!(arbitrage.amount.round_dp(4), dec!(50));
+        assert_eq!(arbitrage.profit.round_dp(4), dec!(200));
+    }
+
+    #[test]
+    fn test_close() {
+        let mut pool = cpmm(dec!(100), dec!(200));
+
+        // Closing the arbitrage moves the pool to the reference price.
+        close(&mut pool, dec!(3)).unwrap();
+        assert_eq!(pool.spot_price().round_dp(8), dec!(3));
+        assert_eq!(close(&mut pool, dec!(3)), None);
+    }
+
+    #[test]
+    fn test_cycle_between_parallel_pools() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(200)));
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(200), dec!(100)));
+
+        // $UGAR is cheap in the first pool and expensive in the second.
+        let cycles = find_cycles(&router, "CUB$", 2, dec!(0.000001));
+        assert_eq!(cycles.len(), 1);
+        let cycle = &cycles[0];
+        assert_eq!(cycle.quote.path[0].pool, 1);
+        assert!(cycle.profit > Decimal::ZERO);
+
+        // Executing the cycle returns the expected profit and closes the gap.
+        let quote = execute_cycle(&mut router, cycle).unwrap();
+        assert_eq!(quote.amount_out() - quote.amount_in(), cycle.profit);
+        assert!(find_cycles(&router, "CUB$", 2, dec!(0.000001)).is_empty());
+    }
+
+    #[test]
+    fn test_triangular_cycle() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(1000), dec!(1000)));
+        router.add_pool("CUB$", "GEM", cpmm(dec!(1000), dec!(1000)));
+        router.add_pool("GEM", "$UGAR", cpmm(dec!(1000), dec!(1200)));
+
+        // Going around the triangle one way is profitable, the other way is not.
+        let cycles = find_cycles(&router, "$UGAR", 3, dec!(0.000001));
+        assert_eq!(cycles.len(), 1);
+        assert_eq!(cycles[0].quote.path.len(), 3);
+
+        // A cycle whose pools moved in the meantime is not executed.
+        let cycle = cycles[0].clone();
+        router
+            .pool_mut(2)
+            .unwrap()
+            .pool
+            .set_reserves(dec!(1000), dec!(1000));
+        assert!(execute_cycle(&mut router, &cycle).is_err());
+    }
+}
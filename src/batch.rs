@@ -0,0 +1,538 @@
+// Atomic batches of pool operations
+
+use crate::{
+    registry::PoolRegistry,
+    router::{PoolId, Router},
+    LiquidityPool, Numeric, Side,
+};
+use alloc::{
+    boxed::Box,
+    collections::{btree_map::Entry, BTreeMap},
+    vec::Vec,
+};
+use core::fmt;
+use rust_decimal::Decimal;
+
+/// Clones pools behind trait objects, so that batches can stage copies of them.
+///
+/// Implemented for every pool that is `Clone`, so that `Box<dyn CloneablePool>` can be cloned.
+pub trait CloneablePool<N: Numeric = Decimal>: LiquidityPool<N> {
+    /// Clones the pool into a new box.
+    fn clone_pool(&self) -> Box<dyn CloneablePool<N>>;
+}
+
+impl<N: Numeric, T: LiquidityPool<N> + Clone + 'static> CloneablePool<N> for T {
+    fn clone_pool(&self) -> Box<dyn CloneablePool<N>> {
+        Box::new(self.clone())
+    }
+}
+
+impl<N: Numeric> Clone for Box<dyn CloneablePool<N>> {
+    fn clone(&self) -> Self {
+        (**self).clone_pool()
+    }
+}
+
+/// A collection of pools addressed by identifier.
+pub trait PoolSet {
+    /// The type of the pools in the collection.
+    type Pool: LiquidityPool + Clone;
+
+    /// Returns the pool with the given identifier.
+    fn pool(&self, id: PoolId) -> Option<&Self::Pool>;
+
+    /// Returns the pool with the given identifier, mutably.
+    fn pool_mut(&mut self, id: PoolId) -> Option<&mut Self::Pool>;
+}
+
+impl<P: LiquidityPool + Clone> PoolSet for [P] {
+    type Pool = P;
+
+    fn pool(&self, id: PoolId) -> Option<&P> {
+        self.get(id)
+    }
+
+    fn pool_mut(&mut self, id: PoolId) -> Option<&mut P> {
+        self.get_mut(id)
+    }
+}
+
+impl<P: LiquidityPool + Clone> PoolSet for Vec<P> {
+    type Pool = P;
+
+    fn pool(&self, id: PoolId) -> Option<&P> {
+        self.get(id)
+    }
+
+    fn pool_mut(&mut self, id: PoolId) -> Option<&mut P> {
+        self.get_mut(id)
+    }
+}
+
+impl PoolSet for Router {
+    type Pool = Box<dyn CloneablePool>;
+
+    fn pool(&self, id: PoolId) -> Option<&Self::Pool> {
+        Router::pool(self, id).map(|routed| &routed.pool)
+    }
+
+    fn pool_mut(&mut self, id: PoolId) -> Option<&mut Self::Pool> {
+        Router::pool_mut(self, id).map(|routed| &mut routed.pool)
+    }
+}
+
+impl PoolSet for PoolRegistry {
+    type Pool = Box<dyn CloneablePool>;
+
+    fn pool(&self, id: PoolId) -> Option<&Self::Pool> {
+        PoolRegistry::pool(self, id).map(|registered| &registered.pool)
+    }
+
+    fn pool_mut(&mut self, id: PoolId) -> Option<&mut Self::Pool> {
+        PoolRegistry::pool_mut(self, id).map(|registered| &mut registered.pool)
+    }
+}
+
+/// A single step of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Operation {
+    /// Takes `amount` out on the given side, paying at most `max_cost` of the other token.
+    Swap {
+        pool: PoolId,
+        side: Side,
+        amount: Decimal,
+        max_cost: Decimal,
+    },
+    /// Pays exactly `amount_in` for the token on the given side, taking at least
+    /// `min_amount_out` out.
+    SwapExactIn {
+        pool: PoolId,
+        side: Side,
+        amount_in: Decimal,
+        min_amount_out: Decimal,
+    },
+    /// Deposits both tokens into the pool.
+    AddLiquidity {
+        pool: PoolId,
+        a: Decimal,
+        b: Decimal,
+    },
+    /// Withdraws both tokens from the pool.
+    RemoveLiquidity {
+        pool: PoolId,
+        a: Decimal,
+        b: Decimal,
+    },
+}
+
+impl Operation {
+    /// Returns the pool the operation applies to.
+    pub fn pool(&self) -> PoolId {
+        match *self {
+            Operation::Swap { pool, .. }
+            | Operation::SwapExactIn { pool, .. }
+            | Operation::AddLiquidity { pool, .. }
+            | Operation::RemoveLiquidity { pool, .. } => pool,
+        }
+    }
+
+    /// Applies the operation to a pool, checking its constraints.
+    ///
+    /// Returns the cost of a swap, the amount taken out by an exact input swap, and zero for
+    /// liquidity changes.
+    fn apply<P: LiquidityPool + ?Sized>(&self, pool: &mut P) -> Result<Decimal, BatchErrorKind> {
+        match *self {
+            Operation::Swap {
+                side,
+                amount,
+                max_cost,
+                ..
+            } => {
+                if amount <= Decimal::ZERO {
+                    return Err(BatchErrorKind::InvalidAmount);
+                }
+                let cost = pool.cost(side, amount);
+                if cost <= Decimal::ZERO {
+                    return Err(BatchErrorKind::Unfilled);
+                }
+                if cost > max_cost {
+                    return Err(BatchErrorKind::Slippage {
+                        amount: cost,
+                        limit: max_cost,
+                    });
+                }
+                // Pools can still refuse a quoted swap, such as an observed pool whose observer
+                // vetoes it, which returns zero.
+                match pool.swap(side, amount) {
+                    cost if cost > Decimal::ZERO => Ok(cost),
+                    _ => Err(BatchErrorKind::Unfilled),
+                }
+            }
+            Operation::SwapExactIn {
+                side,
+                amount_in,
+                min_amount_out,
+                ..
+            } => {
+                if amount_in <= Decimal::ZERO {
+                    return Err(BatchErrorKind::InvalidAmount);
+                }
+                let amount_out = pool.amount_out(side, amount_in);
+                if amount_out <= Decimal::ZERO {
+                    return Err(BatchErrorKind::Unfilled);
+                }
+                if amount_out < min_amount_out {
+                    return Err(BatchErrorKind::Slippage {
+                        amount: amount_out,
+                        limit: min_amount_out,
+                    });
+                }
+                match pool.swap_exact_in(side, amount_in) {
+                    amount_out if amount_out > Decimal::ZERO => Ok(amount_out),
+                    _ => Err(BatchErrorKind::Unfilled),
+                }
+            }
+            Operation::AddLiquidity { a, b, .. } => {
+                if a < Decimal::ZERO || b < Decimal::ZERO {
+                    return Err(BatchErrorKind::InvalidAmount);
+                }
+                pool.add_liquidity(a, b);
+                Ok(Decimal::ZERO)
+            }
+            Operation::RemoveLiquidity { a, b, .. } => {
+                if a < Decimal::ZERO || b < Decimal::ZERO {
+                    return Err(BatchErrorKind::InvalidAmount);
+                }
+                let (reserves_a, reserves_b) = pool.reserves();
+                if a > reserves_a || b > reserves_b {
+                    return Err(BatchErrorKind::InsufficientReserves);
+                }
+                pool.remove_liquidity(a, b);
+                Ok(Decimal::ZERO)
+            }
+        }
+    }
+}
+
+/// The reason a batch operation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchErrorKind {
+    /// The operation refers to an unknown pool.
+    UnknownPool,
+    /// The operation has a negative or empty amount.
+    InvalidAmount,
+    /// The pool cannot fill the swap.
+    Unfilled,
+    /// The swap costs more or returns less than its limit.
+    Slippage { amount: Decimal, limit: Decimal },
+    /// The pool does not hold enough reserves to withdraw.
+    InsufficientReserves,
+}
+
+/// Error returned when a batch fails, identifying the failed operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchError {
+    /// The index of the failed operation in the batch.
+    pub operation: usize,
+    /// The reason the operation failed.
+    pub kind: BatchErrorKind,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "operation {} failed: ", self.operation)?;
+        match self.kind {
+            BatchErrorKind::UnknownPool => write!(f, "unknown pool"),
+            BatchErrorKind::InvalidAmount => write!(f, "invalid amount"),
+            BatchErrorKind::Unfilled => write!(f, "the pool cannot fill the swap"),
+            BatchErrorKind::Slippage { amount, limit } => {
+                write!(f, "slippage: {} exceeds the limit of {}", amount, limit)
+            }
+            BatchErrorKind::InsufficientReserves => write!(f, "insufficient reserves"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for BatchError {}
+
+/// Atomic batch of pool operations
+///
+/// A batch stages its operations on copies of the pools they touch, checking the constraints
+/// of each step along the way. Only once every operation has succeeded are the copies committed
+/// back; if any fails, the pools are left exactly as they were, reserves and PID controller
+/// state included.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Batch {
+    operations: Vec<Operation>,
+}
+
+impl Batch {
+    /// Creates a new empty batch.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an operation to the batch.
+    pub fn push(&mut self, operation: Operation) -> &mut Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Appends a swap taking `amount` out on the given side for at most `max_cost`.
+    pub fn swap(
+        &mut self,
+        pool: PoolId,
+        side: Side,
+        amount: Decimal,
+        max_cost: Decimal,
+    ) -> &mut Self {
+        self.push(Operation::Swap {
+            pool,
+            side,
+            amount,
+            max_cost,
+        })
+    }
+
+    /// Appends a swap paying exactly `amount_in` for at least `min_amount_out`.
+    pub fn swap_exact_in(
+        &mut self,
+        pool: PoolId,
+        side: Side,
+        amount_in: Decimal,
+        min_amount_out: Decimal,
+    ) -> &mut Self {
+        self.push(Operation::SwapExactIn {
+            pool,
+            side,
+            amount_in,
+            min_amount_out,
+        })
+    }
+
+    /// Appends a deposit of both tokens.
+    pub fn add_liquidity(&mut self, pool: PoolId, a: Decimal, b: Decimal) -> &mut Self {
+        self.push(Operation::AddLiquidity { pool, a, b })
+    }
+
+    /// Appends a withdrawal of both tokens.
+    pub fn remove_liquidity(&mut self, pool: PoolId, a: Decimal, b: Decimal) -> &mut Self {
+        self.push(Operation::RemoveLiquidity { pool, a, b })
+    }
+
+    /// Returns the operations of the batch.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Executes every operation of the batch, or none of them.
+    ///
+    /// Returns the result of each operation: the cost of a swap, the amount taken out by an
+    /// exact input swap, and zero for liquidity changes.
+    pub fn execute<S: PoolSet + ?Sized>(&self, pools: &mut S) -> Result<Vec<Decimal>, BatchError> {
+        let mut staged: BTreeMap<PoolId, S::Pool> = BTreeMap::new();
+        let mut results = Vec::with_capacity(self.operations.len());
+        for (index, operation) in self.operations.iter().enumerate() {
+            let error = |kind| BatchError {
+                operation: index,
+                kind,
+            };
+            let id = operation.pool();
+            let pool = match staged.entry(id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let pool = pools.pool(id).ok_or(error(BatchErrorKind::UnknownPool))?;
+                    entry.insert(pool.clone())
+                }
+            };
+            results.push(operation.apply(pool).map_err(error)?);
+        }
+        for (id, pool) in staged {
+            if let Some(original) = pools.pool_mut(id) {
+                *original = pool;
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpmm::ConstantProductMarketMaker,
+        events::{Observed, Observer, PendingSwap},
+        pidmm::PIDMarketMaker,
+    };
+    use rust_decimal_macros::dec;
+
+    fn pools() -> Vec<Box<dyn CloneablePool>> {
+        let mut cpmm = ConstantProductMarketMaker::default();
+        cpmm.add_liquidity(dec!(100), dec!(200));
+        let mut pidmm = PIDMarketMaker::default();
+        pidmm.set_reserves(dec!(100), dec!(100));
+        vec![Box::new(cpmm), Box::new(pidmm)]
+    }
+
+    #[test]
+    fn test_commit() {
+        let mut pools = pools();
+
+        // Swap on one pool and add liquidity on the other.
+        let mut batch = Batch::new();
+        batch
+            .swap(0, Side::A, dec!(50), dec!(200))
+            .add_liquidity(1, dec!(10), dec!(10));
+        let results = batch.execute(&mut pools).unwrap();
+
+        // Both operations are applied.
+        assert_eq!(results, vec![dec!(200), dec!(0)]);
+        assert_eq!(pools[0].reserves(), (dec!(50), dec!(400)));
+        assert_eq!(pools[1].reserves(), (dec!(110), dec!(110)));
+    }
+
+    #[test]
+    fn test_rollback() {
+        let mut pools = pools();
+        let price = pools[1].price_a(dec!(10));
+
+        // The second swap asks for more than the pool can return after the first one.
+        let mut batch = Batch::new();
+        batch
+            .swap(1, Side::A, dec!(10), dec!(100))
+            .swap(1, Side::A, dec!(10), dec!(100))
+            .swap_exact_in(0, Side::A, dec!(10), dec!(50));
+        let error = batch.execute(&mut pools).unwrap_err();
+        assert_eq!(error.operation, 2);
+        assert!(matches!(error.kind, BatchErrorKind::Slippage { .. }));
+
+        // Neither the reserves nor the PID controller state moved.
+        assert_eq!(pools[0].reserves(), (dec!(100), dec!(200)));
+        assert_eq!(pools[1].reserves(), (dec!(100), dec!(100)));
+        assert_eq!(pools[1].price_a(dec!(10)), price);
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut pools = pools();
+
+        // Each kind of invalid operation fails the batch.
+        let cases = [
+            (
+                Operation::RemoveLiquidity {
+                    pool: 0,
+                    a: dec!(102),
+                    b: dec!(0),
+                },
+                BatchErrorKind::InsufficientReserves,
+            ),
+            (
+                Operation::Swap {
+                    pool: 1,
+                    side: Side::B,
+                    amount: dec!(100),
+                    max_cost: dec!(1000),
+                },
+                BatchErrorKind::Unfilled,
+            ),
+            (
+                Operation::AddLiquidity {
+                    pool: 2,
+                    a: dec!(1),
+                    b: dec!(1),
+                },
+                BatchErrorKind::UnknownPool,
+            ),
+            (
+                Operation::SwapExactIn {
+                    pool: 0,
+                    side: Side::B,
+                    amount_in: dec!(-1),
+                    min_amount_out: dec!(0),
+                },
+                BatchErrorKind::InvalidAmount,
+            ),
+        ];
+        for (operation, kind) in cases {
+            let mut batch = Batch::new();
+            batch.add_liquidity(0, dec!(1), dec!(1)).push(operation);
+            let error = batch.execute(&mut pools).unwrap_err();
+            assert_eq!(error, BatchError { operation: 1, kind });
+        }
+        assert_eq!(pools[0].reserves(), (dec!(100), dec!(200)));
+    }
+
+    #[test]
+    fn test_vetoed_swap_rolls_back() {
+        /// Quotes normally but refuses every swap.
+        #[derive(Clone)]
+        struct Frozen;
+
+        impl Observer for Frozen {
+            fn before_swap(&mut self, _swap: &PendingSwap) -> bool {
+                false
+            }
+        }
+
+        let mut pools = pools();
+        let mut frozen = ConstantProductMarketMaker::default();
+        frozen.add_liquidity(dec!(100), dec!(100));
+        let mut frozen = Observed::new(frozen);
+        frozen.observe(Frozen);
+        pools.push(Box::new(frozen));
+
+        // The vetoed swaps are quoted, but fill nothing, so the batch fails.
+        for operation in [
+            Operation::Swap {
+                pool: 2,
+                side: Side::A,
+                amount: dec!(10),
+                max_cost: dec!(100),
+            },
+            Operation::SwapExactIn {
+                pool: 2,
+                side: Side::A,
+                amount_in: dec!(10),
+                min_amount_out: dec!(0),
+            },
+        ] {
+            let mut batch = Batch::new();
+            batch
+                .swap(0, Side::A, dec!(50), dec!(200))
+                .add_liquidity(1, dec!(10), dec!(10))
+                .push(operation);
+            let error = batch.execute(&mut pools).unwrap_err();
+            assert_eq!(
+                error,
+                BatchError {
+                    operation: 2,
+                    kind: BatchErrorKind::Unfilled
+                }
+            );
+        }
+
+        // None of the pools changed.
+        assert_eq!(pools[0].reserves(), (dec!(100), dec!(200)));
+        assert_eq!(pools[1].reserves(), (dec!(100), dec!(100)));
+        assert_eq!(pools[2].reserves(), (dec!(100), dec!(100)));
+    }
+
+    #[test]
+    fn test_router() {
+        let mut router = Router::new();
+        let mut cpmm = ConstantProductMarketMaker::default();
+        cpmm.add_liquidity(dec!(100), dec!(100));
+        let id = router.add_pool("$UGAR", "CUB$", cpmm);
+
+        // Batches run directly against the pools of a router.
+        let mut batch = Batch::new();
+        batch.remove_liquidity(id, dec!(50), dec!(50));
+        batch.execute(&mut router).unwrap();
+        assert_eq!(
+            router.pool(id).unwrap().pool.reserves(),
+            (dec!(50), dec!(50))
+        );
+    }
+}
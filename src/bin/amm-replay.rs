@@ -0,0 +1,86 @@
+//! Replays a trade log against each curve of the crate.
+//!
+//! Usage: `amm-replay <log.csv|log.jsonl> <reserves_a> <reserves_b> [tolerance]`
+//!
+//! Each curve starts from the given reserves and executes the logged swaps and liquidity
+//! changes in order. For each, the volumes and average prices of the flow, the final state and
+//! the first entry diverging from the recorded outcome by more than `tolerance` (0.001 by
+//! default) are printed.
+
+use amm::{
+    cpmm::ConstantProductMarketMaker,
+    csmm::ConstantSumMarketMaker,
+    pidmm::PIDMarketMaker,
+    sim::replay::{self, Report},
+    LiquidityPool,
+};
+use rust_decimal::Decimal;
+use std::{env, error::Error, process, str::FromStr};
+
+const USAGE: &str = "usage: amm-replay <log.csv|log.jsonl> <reserves_a> <reserves_b> [tolerance]";
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let [path, reserves_a, reserves_b, rest @ ..] = args.as_slice() else {
+        return Err(USAGE.into());
+    };
+    let reserves_a = Decimal::from_str(reserves_a)?;
+    let reserves_b = Decimal::from_str(reserves_b)?;
+    let tolerance = match rest {
+        [] => Decimal::new(1, 3),
+        [tolerance] => Decimal::from_str(tolerance)?,
+        _ => return Err(USAGE.into()),
+    };
+    let entries = replay::load(path)?;
+    println!("{}: {} entries", path, entries.len());
+
+    let pools: [(&str, Box<dyn LiquidityPool>); 3] = [
+        ("cpmm", Box::<ConstantProductMarketMaker>::default()),
+        ("csmm", Box::<ConstantSumMarketMaker>::default()),
+        ("pidmm", Box::<PIDMarketMaker>::default()),
+    ];
+    for (name, mut pool) in pools {
+        pool.set_reserves(reserves_a, reserves_b);
+        let report = replay::replay(pool.as_mut(), &entries);
+        print_report(name, pool.as_ref(), &report, tolerance);
+    }
+    Ok(())
+}
+
+fn print_report(name: &str, pool: &dyn LiquidityPool, report: &Report, tolerance: Decimal) {
+    let price =
+        |price: Option<Decimal>| price.map_or("-".to_string(), |p| p.round_dp(6).to_string());
+    let (reserves_a, reserves_b) = pool.reserves_rounded();
+    println!("{}:", name);
+    println!(
+        "  bought {} A at {} B, {} B at {} A, {} swaps rejected",
+        report.volume_a.round_dp(6),
+        price(report.average_price_a()),
+        report.volume_b.round_dp(6),
+        price(report.average_price_b()),
+        report.rejected
+    );
+    println!(
+        "  final reserves {} / {}, spot price {}",
+        reserves_a,
+        reserves_b,
+        pool.spot_price().round_dp(6)
+    );
+    let mut divergences = report.divergences(tolerance);
+    match divergences.next() {
+        Some(outcome) => println!(
+            "  {} entries diverge, first at entry {} by {}",
+            1 + divergences.count(),
+            outcome.index,
+            price(outcome.divergence())
+        ),
+        None => println!("  no entry diverges by more than {}", tolerance),
+    }
+}
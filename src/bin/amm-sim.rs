@@ -0,0 +1,146 @@
+//! Runs a simulation scenario without a terminal interface.
+//!
+//! Usage: `amm-sim <scenario.toml|scenario.json> [output directory|records.jsonl]`
+//!
+//! Given an output directory, the time series of the pools and agents are written to it as
+//! `pools.csv`, `pools.jsonl`, `agents.csv` and `agents.jsonl`, along with the full record of
+//! each step in `records.jsonl`. Given a path ending in `.jsonl`, only the records are written
+//! to that file, which must not exist yet. Otherwise the records are written to standard
+//! output as lines of JSON. A summary of the final state is printed to standard error.
+
+use amm::{
+    sim::{export, metrics, scenario::Scenario, Simulation},
+    LiquidityPool,
+};
+use std::{
+    env,
+    error::Error,
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process,
+};
+
+const USAGE: &str = "usage: amm-sim <scenario.toml|scenario.json> [output directory|records.jsonl]";
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let Some(path) = args.next() else {
+        return Err(USAGE.into());
+    };
+    let output = args.next();
+    if let Some(output) = &output {
+        check_output(Path::new(output))?;
+    }
+    let scenario = Scenario::load(&path)?;
+    let simulation = scenario.run()?;
+
+    match output.as_deref().map(Path::new) {
+        Some(file) if is_jsonl(file) => {
+            let file = OpenOptions::new().write(true).create_new(true).open(file)?;
+            write_records(&simulation, file)?
+        }
+        Some(directory) => write_directory(&simulation, directory)?,
+        None => write_records(&simulation, io::stdout().lock())?,
+    }
+
+    eprintln!("{}: {} steps, seed {}", path, scenario.steps, scenario.seed);
+    let metrics = metrics::metrics(&simulation);
+    for (index, (pool, metrics)) in simulation.pools().iter().zip(&metrics).enumerate() {
+        let (reserves_a, reserves_b) = pool.reserves_rounded();
+        eprintln!(
+            "pool {}: reserves {} / {}, spot price {}",
+            index,
+            reserves_a,
+            reserves_b,
+            pool.spot_price().round_dp(6)
+        );
+        eprintln!(
+            "  impermanent loss {}, LVR {}, fees {} ({} APR), volatility {}",
+            metrics.impermanent_loss.round_dp(6),
+            metrics
+                .loss_versus_rebalancing
+                .map_or("-".to_string(), |lvr| lvr.round_dp(2).to_string()),
+            metrics.fees.round_dp(2),
+            metrics.fee_apr.round_dp(4),
+            metrics.volatility.round_dp(6)
+        );
+        if let Some(tracking) = metrics.tracking {
+            eprintln!(
+                "  tracking error {}, overshoot {}, settled at step {}",
+                tracking.rms_error.round_dp(6),
+                tracking.overshoot.round_dp(4),
+                tracking
+                    .settling_step
+                    .map_or("-".to_string(), |step| step.to_string())
+            );
+        }
+    }
+    for (agent, account) in simulation.agents().iter().zip(simulation.accounts()) {
+        eprintln!(
+            "{}: {} A, {} B",
+            agent.name(),
+            account.token_a.round_dp(2),
+            account.token_b.round_dp(2)
+        );
+    }
+    Ok(())
+}
+
+/// Returns whether the output is a file of records rather than a directory.
+fn is_jsonl(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("jsonl"))
+}
+
+/// Checks the output path before running, so that a long run is not lost to a bad path.
+///
+/// Paths ending in `.jsonl` must not exist yet, and any other path must not be a file.
+fn check_output(path: &Path) -> Result<(), Box<dyn Error>> {
+    if is_jsonl(path) && path.is_dir() {
+        return Err(format!("{} is a directory, not a records file", path.display()).into());
+    }
+    if is_jsonl(path) && path.exists() {
+        return Err(format!("{} already exists and would be overwritten", path.display()).into());
+    }
+    if !is_jsonl(path) && path.exists() && !path.is_dir() {
+        return Err(format!(
+            "{} is a file; give an output directory or a .jsonl file\n{}",
+            path.display(),
+            USAGE
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Writes the time series and records of a simulation to a directory.
+fn write_directory(simulation: &Simulation, directory: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(directory)?;
+    let create = |name: &str| File::create(directory.join(name)).map(BufWriter::new);
+    let pools = export::pool_rows(simulation.records());
+    export::write_csv(create("pools.csv")?, &pools)?;
+    export::write_jsonl(create("pools.jsonl")?, &pools)?;
+    let agents = export::agent_rows(simulation);
+    export::write_csv(create("agents.csv")?, &agents)?;
+    export::write_jsonl(create("agents.jsonl")?, &agents)?;
+    write_records(simulation, create("records.jsonl")?)
+}
+
+/// Writes the record of each step as a line of JSON.
+fn write_records<W: Write>(simulation: &Simulation, writer: W) -> Result<(), Box<dyn Error>> {
+    let mut writer = BufWriter::new(writer);
+    for record in simulation.records() {
+        serde_json::to_writer(&mut writer, record)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
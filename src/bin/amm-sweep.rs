@@ -0,0 +1,32 @@
+//! Runs a Monte Carlo parameter sweep over a simulation scenario.
+//!
+//! Usage: `amm-sweep <sweep.toml|sweep.json>`
+//!
+//! The sweep file names a scenario, the number of seeds to run each combination over and the
+//! axes to vary. A table summarizing the metrics of each combination and pool is printed to
+//! standard output.
+
+use amm::sim::sweep::{self, Sweep};
+use std::{env, error::Error, io, process};
+
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let Some(path) = env::args().nth(1) else {
+        return Err("usage: amm-sweep <sweep.toml|sweep.json>".into());
+    };
+    let sweep = Sweep::load(&path)?;
+    let combinations: usize = sweep.axes.iter().map(|axis| axis.len()).product();
+    eprintln!(
+        "{}: {} combinations over {} seeds on {} threads",
+        path, combinations, sweep.seeds, sweep.threads
+    );
+    let summaries = sweep.run()?;
+    sweep::write_table(io::stdout().lock(), &summaries)?;
+    Ok(())
+}
@@ -0,0 +1,645 @@
+// Simulator state and controls
+
+use amm::{
+    cpmm::ConstantProductMarketMaker,
+    csmm::ConstantSumMarketMaker,
+    events::Event,
+    fee::WithFee,
+    pidmm::PIDMarketMaker,
+    sim::{
+        agents::{Arbitrageur, PoissonNoise},
+        metrics::{self, PoolMetrics},
+        price::GeometricBrownian,
+        Account, Agent, Market, Simulation,
+    },
+    LiquidityPool, Side,
+};
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+/// The number of steps kept on the charts, and of records the metrics are computed over.
+pub const HISTORY: usize = 200;
+
+/// The number of trades kept on the tape, and of events kept in the log.
+pub const TAPE: usize = 50;
+
+/// The names of the PID terms, in the order of [`App::pid_terms`].
+pub const PID_TERMS: [&str; 5] = ["error", "P", "I", "D", "output"];
+
+/// The duration of a step, an hour, used to annualize the fee return.
+const STEP_SECONDS: u64 = 3600;
+
+/// The most steps run per tick.
+const MAX_SPEED: u32 = 64;
+
+/// The curves the simulator can run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    ConstantProduct,
+    ConstantSum,
+    Pid,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 3] = [Curve::ConstantProduct, Curve::ConstantSum, Curve::Pid];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Curve::ConstantProduct => "CPMM",
+            Curve::ConstantSum => "CSMM",
+            Curve::Pid => "PIDMM",
+        }
+    }
+
+    /// Creates a pool of this curve with the given reserves, targeting their ratio.
+    fn build(self, reserves_a: Decimal, reserves_b: Decimal) -> Box<dyn LiquidityPool> {
+        let mut pool: Box<dyn LiquidityPool> = match self {
+            Curve::ConstantProduct => Box::<ConstantProductMarketMaker>::default(),
+            Curve::ConstantSum => Box::<ConstantSumMarketMaker>::default(),
+            Curve::Pid => Box::<PIDMarketMaker>::default(),
+        };
+        pool.set_parameter("target", reserves_b / reserves_a);
+        pool.set_reserves(reserves_a, reserves_b);
+        pool
+    }
+}
+
+/// A noise trader whose rate the simulator changes while it runs.
+struct SharedNoise(Rc<RefCell<PoissonNoise>>);
+
+impl Agent for SharedNoise {
+    fn name(&self) -> &str {
+        "noise"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        self.0.borrow_mut().act(market);
+    }
+}
+
+/// An action entered by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderKind {
+    /// Pays the amount in token B for token A.
+    BuyA,
+    /// Pays the amount in token A for token B.
+    BuyB,
+    /// Deposits the amount of token A and token B in the ratio of the reserves.
+    Add,
+    /// Withdraws the amount of token A and token B in the ratio of the reserves.
+    Remove,
+}
+
+impl OrderKind {
+    pub const ALL: [OrderKind; 4] = [
+        OrderKind::BuyA,
+        OrderKind::BuyB,
+        OrderKind::Add,
+        OrderKind::Remove,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OrderKind::BuyA => "buy A",
+            OrderKind::BuyB => "buy B",
+            OrderKind::Add => "add liquidity",
+            OrderKind::Remove => "remove liquidity",
+        }
+    }
+}
+
+/// An order being entered, with its amount as typed.
+pub struct OrderEntry {
+    pub kind: OrderKind,
+    pub amount: String,
+}
+
+/// The outcome of an order at the current state of the pool.
+pub enum Quote {
+    Swap {
+        /// The side of the pool taken out by the trade.
+        side: Side,
+        /// The amount of the other token paid into the pool.
+        amount_in: Decimal,
+        /// The amount taken out of the pool.
+        amount_out: Decimal,
+        /// The price impact of the trade, as a fraction of the spot price.
+        price_impact: Decimal,
+        /// The part of the amount paid in kept by the pool as a fee.
+        fee: Decimal,
+    },
+    Liquidity {
+        amount_a: Decimal,
+        amount_b: Decimal,
+    },
+}
+
+/// An order confirmed by the user, filled at the next step.
+///
+/// Swaps pay in the amount of the token not bought.
+#[derive(Clone, Copy)]
+struct Order {
+    kind: OrderKind,
+    amount_a: Decimal,
+    amount_b: Decimal,
+}
+
+/// The user, filling the orders entered by hand before the other agents act.
+struct Manual(Rc<RefCell<VecDeque<Order>>>);
+
+impl Agent for Manual {
+    fn name(&self) -> &str {
+        "you"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        while let Some(order) = self.0.borrow_mut().pop_front() {
+            match order.kind {
+                OrderKind::BuyA => {
+                    market.swap_exact_in(0, Side::A, order.amount_b);
+                }
+                OrderKind::BuyB => {
+                    market.swap_exact_in(0, Side::B, order.amount_a);
+                }
+                OrderKind::Add => market.add_liquidity(0, order.amount_a, order.amount_b),
+                OrderKind::Remove => market.remove_liquidity(0, order.amount_a, order.amount_b),
+            }
+        }
+    }
+}
+
+/// A trade shown on the tape.
+pub struct TapeEntry {
+    pub step: u64,
+    pub agent: String,
+    pub event: Event,
+}
+
+/// Appends a point to a chart series, dropping the oldest beyond [`HISTORY`].
+fn plot(series: &mut Vec<(f64, f64)>, x: f64, y: Decimal) {
+    series.push((x, y.to_f64().unwrap_or_default()));
+    if series.len() > HISTORY {
+        series.remove(0);
+    }
+}
+
+/// The state of the simulator.
+pub struct App {
+    pub curve: Curve,
+    pub simulation: Simulation,
+    pub seed: u64,
+    /// The mean number of noise trades per step.
+    pub intensity: Decimal,
+    /// The index of the selected control in [`App::controls`].
+    pub selected: usize,
+    pub paused: bool,
+    /// The number of steps run per tick.
+    pub speed: u32,
+    /// The spot price of the pool at each of the last steps.
+    pub spot_prices: Vec<(f64, f64)>,
+    /// The reference price at each of the last steps.
+    pub reference_prices: Vec<(f64, f64)>,
+    pub reserves_a: Vec<(f64, f64)>,
+    pub reserves_b: Vec<(f64, f64)>,
+    /// Each of the [`PID_TERMS`] at each of the last steps, for pools with a controller.
+    pub pid_terms: [Vec<(f64, f64)>; 5],
+    /// The most recent trades, newest first.
+    pub tape: VecDeque<TapeEntry>,
+    /// The most recent events of the pool with their step, newest first.
+    pub events: VecDeque<(u64, Event)>,
+    /// The metrics of the pool over the last [`HISTORY`] steps.
+    pub metrics: Option<PoolMetrics>,
+    /// The order being entered, while the order entry is open.
+    pub order: Option<OrderEntry>,
+    noise: Vec<Rc<RefCell<PoissonNoise>>>,
+    orders: Rc<RefCell<VecDeque<Order>>>,
+}
+
+impl App {
+    pub fn new(curve: Curve) -> App {
+        let mut app = App {
+            curve,
+            simulation: Simulation::new(0),
+            seed: 0,
+            intensity: dec!(2),
+            selected: 0,
+            paused: false,
+            speed: 1,
+            spot_prices: Vec::new(),
+            reference_prices: Vec::new(),
+            reserves_a: Vec::new(),
+            reserves_b: Vec::new(),
+            pid_terms: Default::default(),
+            tape: VecDeque::new(),
+            events: VecDeque::new(),
+            metrics: None,
+            order: None,
+            noise: Vec::new(),
+            orders: Rc::default(),
+        };
+        app.restart(rand::random(), dec!(0.003));
+        app
+    }
+
+    /// Rebuilds the simulation with the given seed and fee, then fills the charts.
+    fn restart(&mut self, seed: u64, fee: Decimal) {
+        let (reserves_a, reserves_b) = (dec!(1000), dec!(1200));
+        let pool = WithFee::new(self.curve.build(reserves_a, reserves_b), fee);
+
+        let mut simulation = Simulation::new(seed);
+        simulation.set_step_seconds(STEP_SECONDS);
+        simulation.set_record_limit(Some(HISTORY));
+        simulation.set_price_process(GeometricBrownian::new(
+            reserves_b / reserves_a,
+            dec!(0),
+            dec!(0.006),
+        ));
+        let pool = simulation.add_pool(pool);
+        self.orders.borrow_mut().clear();
+        simulation.add_agent(Manual(self.orders.clone()));
+        simulation.add_agent(Arbitrageur::new(pool));
+        self.noise.clear();
+        for _ in 0..2 {
+            let noise = Rc::new(RefCell::new(PoissonNoise::new(
+                pool,
+                self.intensity / dec!(2),
+                dec!(15),
+            )));
+            simulation.add_agent(SharedNoise(noise.clone()));
+            self.noise.push(noise);
+        }
+
+        self.simulation = simulation;
+        self.seed = seed;
+        self.spot_prices.clear();
+        self.reference_prices.clear();
+        self.reserves_a.clear();
+        self.reserves_b.clear();
+        self.pid_terms.iter_mut().for_each(Vec::clear);
+        self.tape.clear();
+        self.events.clear();
+        for _ in 0..HISTORY {
+            self.advance();
+        }
+        self.update_metrics();
+    }
+
+    /// Switches to another curve, keeping the fee and trade intensity.
+    pub fn select_curve(&mut self, curve: Curve) {
+        let fee = self.fee();
+        self.curve = curve;
+        self.selected = 0;
+        self.restart(self.seed, fee);
+    }
+
+    /// Restarts the current curve with a new seed.
+    pub fn reseed(&mut self) {
+        self.restart(rand::random(), self.fee());
+    }
+
+    fn fee(&self) -> Decimal {
+        self.simulation.pools()[0]
+            .parameters()
+            .into_iter()
+            .find(|(name, _)| *name == "fee")
+            .map_or(Decimal::ZERO, |(_, fee)| fee)
+    }
+
+    /// Returns the controls adjustable while running: the pool's parameters, then the trade
+    /// intensity.
+    pub fn controls(&self) -> Vec<(&'static str, Decimal)> {
+        let mut controls = self.simulation.pools()[0].parameters();
+        controls.push(("intensity", self.intensity));
+        controls
+    }
+
+    /// Moves the selection to the previous or next control.
+    pub fn select(&mut self, next: bool) {
+        let count = self.controls().len();
+        self.selected = if next {
+            (self.selected + 1) % count
+        } else {
+            (self.selected + count - 1) % count
+        };
+    }
+
+    /// Raises or lowers the selected control by 10%, starting from a small value at zero.
+    pub fn adjust(&mut self, up: bool) {
+        let Some((name, value)) = self.controls().get(self.selected).copied() else {
+            return;
+        };
+        let value = match (value.is_zero(), up) {
+            (true, true) => dec!(0.001),
+            (true, false) => Decimal::ZERO,
+            (false, true) => value * dec!(1.1),
+            (false, false) => value / dec!(1.1),
+        }
+        .round_dp(6);
+        if name == "intensity" {
+            self.intensity = value;
+            for noise in &self.noise {
+                noise.borrow_mut().rate = value / dec!(2);
+            }
+        } else if let Some(pool) = self.simulation.pool_mut(0) {
+            pool.set_parameter(name, value);
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Doubles or halves the number of steps run per tick.
+    pub fn change_speed(&mut self, faster: bool) {
+        self.speed = if faster {
+            (self.speed * 2).min(MAX_SPEED)
+        } else {
+            (self.speed / 2).max(1)
+        };
+    }
+
+    /// Opens the order entry, or closes it discarding the order.
+    pub fn toggle_order(&mut self) {
+        self.order = match self.order {
+            Some(_) => None,
+            None => Some(OrderEntry {
+                kind: OrderKind::BuyA,
+                amount: String::new(),
+            }),
+        };
+    }
+
+    /// Switches the order being entered to the previous or next kind.
+    pub fn cycle_order(&mut self, next: bool) {
+        if let Some(order) = &mut self.order {
+            let count = OrderKind::ALL.len();
+            let index = OrderKind::ALL
+                .iter()
+                .position(|kind| *kind == order.kind)
+                .unwrap_or_default();
+            let index = if next {
+                (index + 1) % count
+            } else {
+                (index + count - 1) % count
+            };
+            order.kind = OrderKind::ALL[index];
+        }
+    }
+
+    /// Types a character of the amount of the order, ignoring all but digits and a decimal point.
+    pub fn type_order(&mut self, c: char) {
+        if let Some(order) = &mut self.order {
+            if c.is_ascii_digit() || (c == '.' && !order.amount.contains('.')) {
+                order.amount.push(c);
+            }
+        }
+    }
+
+    /// Erases the last character of the amount of the order.
+    pub fn erase_order(&mut self) {
+        if let Some(order) = &mut self.order {
+            order.amount.pop();
+        }
+    }
+
+    /// Quotes the order being entered against the pool as it stands.
+    ///
+    /// The price impact is that of the curve alone, net of the fee, which is shown on its own.
+    /// Returns `None` if no order is being entered, its amount is not a positive number, or
+    /// the pool could not fill it.
+    pub fn quote(&self) -> Option<Quote> {
+        let order = self.order.as_ref()?;
+        let amount = order.amount.parse::<Decimal>().ok()?;
+        if amount <= Decimal::ZERO {
+            return None;
+        }
+        let pool = &self.simulation.pools()[0];
+        let (reserves_a, reserves_b) = pool.reserves();
+        let (side, reserve) = match order.kind {
+            OrderKind::BuyA => (Side::A, reserves_a),
+            OrderKind::BuyB => (Side::B, reserves_b),
+            OrderKind::Add | OrderKind::Remove => {
+                if reserves_a.is_zero() || (order.kind == OrderKind::Remove && amount >= reserves_a)
+                {
+                    return None;
+                }
+                return Some(Quote::Liquidity {
+                    amount_a: amount,
+                    amount_b: amount * reserves_b / reserves_a,
+                });
+            }
+        };
+        let amount_out = pool.amount_out(side, amount);
+        if amount_out <= Decimal::ZERO || amount_out >= reserve {
+            return None;
+        }
+        // The spot price of the fee wrapper is the curve's, without the fee.
+        let spot_price = match side {
+            Side::A => pool.spot_price(),
+            Side::B => Decimal::ONE.checked_div(pool.spot_price())?,
+        };
+        let fee = amount - amount / (Decimal::ONE + self.fee());
+        let effective_price = (amount - fee) / amount_out;
+        Some(Quote::Swap {
+            side,
+            amount_in: amount,
+            amount_out,
+            price_impact: effective_price.checked_div(spot_price)? - Decimal::ONE,
+            fee,
+        })
+    }
+
+    /// Sends the order being entered to be filled at the next step, running that step at once
+    /// while paused.
+    ///
+    /// Orders the pool could not fill are kept in the entry.
+    pub fn confirm_order(&mut self) {
+        let (Some(quote), Some(entry)) = (self.quote(), &mut self.order) else {
+            return;
+        };
+        let (amount_a, amount_b) = match quote {
+            Quote::Swap {
+                side: Side::A,
+                amount_in,
+                ..
+            } => (Decimal::ZERO, amount_in),
+            Quote::Swap {
+                side: Side::B,
+                amount_in,
+                ..
+            } => (amount_in, Decimal::ZERO),
+            Quote::Liquidity { amount_a, amount_b } => (amount_a, amount_b),
+        };
+        self.orders.borrow_mut().push_back(Order {
+            kind: entry.kind,
+            amount_a,
+            amount_b,
+        });
+        entry.amount.clear();
+        if self.paused {
+            self.step_once();
+        }
+    }
+
+    /// Returns the balances of the user since the start of the run.
+    pub fn account(&self) -> Account {
+        self.simulation.accounts()[0]
+    }
+
+    /// Runs a single step, as while paused.
+    pub fn step_once(&mut self) {
+        self.advance();
+        self.update_metrics();
+    }
+
+    /// Runs one step of the simulation, plots it and puts its trades on the tape.
+    fn advance(&mut self) {
+        self.simulation.step();
+        let Some(record) = self.simulation.records().last() else {
+            return;
+        };
+        let x = record.step as f64;
+        let state = &record.pools[0];
+        plot(&mut self.spot_prices, x, state.spot_price);
+        plot(
+            &mut self.reference_prices,
+            x,
+            record.reference_price.unwrap_or(state.spot_price),
+        );
+        plot(&mut self.reserves_a, x, state.reserves_a);
+        plot(&mut self.reserves_b, x, state.reserves_b);
+        if let Some(terms) = state.pid_terms {
+            let values = [
+                terms.error,
+                terms.proportional,
+                terms.integral,
+                terms.derivative,
+                terms.output,
+            ];
+            for (series, value) in self.pid_terms.iter_mut().zip(values) {
+                plot(series, x, value);
+            }
+        }
+        for trade in &record.trades {
+            self.tape.push_front(TapeEntry {
+                step: record.step,
+                agent: self.simulation.agents()[trade.agent].name().to_string(),
+                event: trade.event.clone(),
+            });
+        }
+        self.tape.truncate(TAPE);
+        for event in &state.events {
+            self.events.push_front((record.step, event.clone()));
+        }
+        self.events.truncate(TAPE);
+    }
+
+    /// Recomputes the metrics of the pool over the records kept, the last [`HISTORY`] steps.
+    fn update_metrics(&mut self) {
+        self.metrics = metrics::pool_metrics(self.simulation.records(), 0);
+    }
+
+    pub fn on_tick(&mut self) {
+        if !self.paused {
+            for _ in 0..self.speed {
+                self.advance();
+            }
+            self.update_metrics();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a paused simulator whose pool has reserves of 1000 and 1000 and a 0.3% fee,
+    /// with an order entry holding the given order.
+    fn app(kind: OrderKind, amount: &str) -> App {
+        let mut app = App::new(Curve::ConstantProduct);
+        app.paused = true;
+        app.simulation
+            .pool_mut(0)
+            .unwrap()
+            .set_reserves(dec!(1000), dec!(1000));
+        app.order = Some(OrderEntry {
+            kind,
+            amount: amount.to_string(),
+        });
+        app
+    }
+
+    #[test]
+    fn test_quote_swap() {
+        // Paying 100.3 of token B is 100 along the curve and 0.3 of fee, for 1000 * 100 / 1100
+        // of token A at 1.1 each, 10% over the spot price of 1.
+        let app = app(OrderKind::BuyA, "100.3");
+        let Some(Quote::Swap {
+            side,
+            amount_in,
+            amount_out,
+            price_impact,
+            fee,
+        }) = app.quote()
+        else {
+            panic!("the order should be quoted as a swap");
+        };
+        assert_eq!(side, Side::A);
+        assert_eq!(amount_in, dec!(100.3));
+        assert_eq!(amount_out.round_dp(6), dec!(90.909091));
+        assert_eq!(fee.round_dp(6), dec!(0.3));
+        assert_eq!(price_impact.round_dp(6), dec!(0.1));
+    }
+
+    #[test]
+    fn test_quote_rejects_unfillable_orders() {
+        // Amounts that are not positive numbers are not quoted.
+        assert!(app(OrderKind::BuyA, "").quote().is_none());
+        assert!(app(OrderKind::BuyA, "0").quote().is_none());
+
+        // Withdrawals cannot take all of the reserves.
+        assert!(app(OrderKind::Remove, "1000").quote().is_none());
+        assert!(app(OrderKind::Remove, "10").quote().is_some());
+
+        // An empty pool fills no swap, and the order stays in the entry.
+        let mut app = app(OrderKind::BuyB, "10");
+        app.simulation
+            .pool_mut(0)
+            .unwrap()
+            .set_reserves(Decimal::ZERO, Decimal::ZERO);
+        assert!(app.quote().is_none());
+        let step = app.simulation.step_count();
+        app.confirm_order();
+        assert_eq!(app.simulation.step_count(), step);
+        assert_eq!(app.order.as_ref().unwrap().amount, "10");
+    }
+
+    #[test]
+    fn test_confirmed_order_on_tape() {
+        let mut app = app(OrderKind::BuyA, "100.3");
+        let step = app.simulation.step_count();
+        app.confirm_order();
+
+        // While paused, the order is filled by a step run at once, before the other agents.
+        assert_eq!(app.simulation.step_count(), step + 1);
+        assert!(app.order.as_ref().unwrap().amount.is_empty());
+        let entry = app.tape.iter().find(|entry| entry.agent == "you");
+        let Some(TapeEntry {
+            step: filled,
+            event: Event::Swap {
+                side, amount_in, ..
+            },
+            ..
+        }) = entry
+        else {
+            panic!("the order should be on the tape");
+        };
+        assert_eq!(
+            (*filled, *side, *amount_in),
+            (step + 1, Side::A, dec!(100.3))
+        );
+        assert_eq!(app.account().token_b, dec!(-100.3));
+        assert!(app.account().token_a > dec!(90.9));
+    }
+}
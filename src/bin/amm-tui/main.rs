@@ -0,0 +1,108 @@
+//! Interactive terminal simulator.
+//!
+//! Usage: `amm-tui`
+//!
+//! Runs a pool against an arbitrageur and noise traders following a random reference price.
+//! The pool's curve can be switched and its parameters, fee and the intensity of the noise
+//! trading tuned while the simulation runs. Orders entered by hand are quoted live and filled
+//! at the next step, before the other agents act.
+
+mod app;
+mod ui;
+
+use app::{App, Curve};
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use std::{
+    error::Error,
+    io,
+    time::{Duration, Instant},
+};
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    Terminal,
+};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // setup terminal
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    // create app and run it
+    let tick_rate = Duration::from_millis(250);
+    let app = App::new(Curve::ConstantProduct);
+    let res = run_app(&mut terminal, app, tick_rate);
+
+    // restore terminal
+    disable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
+    terminal.show_cursor()?;
+
+    if let Err(err) = res {
+        println!("{:?}", err)
+    }
+
+    Ok(())
+}
+
+fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    mut app: App,
+    tick_rate: Duration,
+) -> io::Result<()> {
+    let mut last_tick = Instant::now();
+    loop {
+        terminal.draw(|f| ui::draw(f, &app))?;
+
+        let timeout = tick_rate
+            .checked_sub(last_tick.elapsed())
+            .unwrap_or_else(|| Duration::from_secs(0));
+        if crossterm::event::poll(timeout)? {
+            if let Event::Key(key) = event::read()? {
+                if app.order.is_some() {
+                    match key.code {
+                        KeyCode::Esc => app.toggle_order(),
+                        KeyCode::Enter => app.confirm_order(),
+                        KeyCode::Tab | KeyCode::Right => app.cycle_order(true),
+                        KeyCode::BackTab | KeyCode::Left => app.cycle_order(false),
+                        KeyCode::Backspace => app.erase_order(),
+                        KeyCode::Char(c) => app.type_order(c),
+                        _ => {}
+                    }
+                } else {
+                    match key.code {
+                        KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                        KeyCode::Char(c @ '1'..='3') => {
+                            app.select_curve(Curve::ALL[c as usize - '1' as usize])
+                        }
+                        KeyCode::Char(' ') => app.toggle_pause(),
+                        KeyCode::Char('n') => app.step_once(),
+                        KeyCode::Char('+') | KeyCode::Char('=') => app.change_speed(true),
+                        KeyCode::Char('-') => app.change_speed(false),
+                        KeyCode::Char('r') => app.reseed(),
+                        KeyCode::Char('o') => app.toggle_order(),
+                        KeyCode::Up => app.select(false),
+                        KeyCode::Down => app.select(true),
+                        KeyCode::Left => app.adjust(false),
+                        KeyCode::Right => app.adjust(true),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        if last_tick.elapsed() >= tick_rate {
+            app.on_tick();
+            last_tick = Instant::now();
+        }
+    }
+}
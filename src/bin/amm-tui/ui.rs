@@ -0,0 +1,514 @@
+// Simulator rendering
+
+use crate::app::{App, Curve, OrderKind, Quote, PID_TERMS};
+use amm::{depth, events::Event, LiquidityPool, Side};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Color, Modifier, Style},
+    symbols,
+    text::{Span, Spans},
+    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph},
+    Frame,
+};
+
+/// The colors of the PID terms, in the order of [`PID_TERMS`].
+const PID_COLORS: [Color; 5] = [
+    Color::Red,
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Green,
+];
+
+/// The number of amounts the depth chart samples on each side.
+const DEPTH_SAMPLES: usize = 50;
+
+/// A named, colored line of a chart.
+type Series<'a> = (&'a str, Color, &'a [(f64, f64)]);
+
+/// Returns the smallest and largest of the values.
+fn extent<'a, I: IntoIterator<Item = &'a f64>>(values: I) -> [f64; 2] {
+    let (min, max) = values
+        .into_iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(*value), max.max(*value))
+        });
+    if !min.is_finite() || !max.is_finite() {
+        return [0.0, 1.0];
+    }
+    [min, max]
+}
+
+/// Returns the extent of the values, padded by 5% so the lines clear the chart's edges.
+fn padded<'a, I: IntoIterator<Item = &'a f64>>(values: I) -> [f64; 2] {
+    let [min, max] = extent(values);
+    let padding = ((max - min) * 0.05)
+        .max(max.abs() * 0.001)
+        .max(f64::EPSILON);
+    [min - padding, max + padding]
+}
+
+/// Labels the bounds of an axis and its midpoint.
+fn labels(bounds: [f64; 2], precision: usize) -> Vec<Span<'static>> {
+    let bold = Style::default().add_modifier(Modifier::BOLD);
+    vec![
+        Span::styled(format!("{:.*}", precision, bounds[0]), bold),
+        Span::raw(format!("{:.*}", precision, (bounds[0] + bounds[1]) / 2.0)),
+        Span::styled(format!("{:.*}", precision, bounds[1]), bold),
+    ]
+}
+
+fn block(title: String) -> Block<'static> {
+    Block::default()
+        .title(Span::styled(
+            title,
+            Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        ))
+        .borders(Borders::ALL)
+}
+
+/// Builds a line chart whose axes fit the series.
+fn chart<'a>(
+    title: String,
+    series: Vec<Series<'a>>,
+    x_title: &'a str,
+    y_precision: usize,
+) -> Chart<'a> {
+    let x_bounds = extent(
+        series
+            .iter()
+            .flat_map(|(_, _, data)| data.iter().map(|(x, _)| x)),
+    );
+    let y_bounds = padded(
+        series
+            .iter()
+            .flat_map(|(_, _, data)| data.iter().map(|(_, y)| y)),
+    );
+    let datasets = series
+        .into_iter()
+        .map(|(name, color, data)| {
+            Dataset::default()
+                .name(name)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(data)
+        })
+        .collect();
+    Chart::new(datasets)
+        .block(block(title))
+        .x_axis(
+            Axis::default()
+                .title(x_title)
+                .style(Style::default().fg(Color::Gray))
+                .labels(labels(x_bounds, 0))
+                .bounds(x_bounds),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .labels(labels(y_bounds, y_precision))
+                .bounds(y_bounds),
+        )
+}
+
+/// Formats a decimal for a panel.
+fn number(value: Decimal) -> String {
+    value.round_dp(4).normalize().to_string()
+}
+
+pub fn draw<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage(40),
+                Constraint::Percentage(30),
+                Constraint::Percentage(30),
+            ]
+            .as_ref(),
+        )
+        .split(f.size());
+
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(rows[0]);
+    draw_prices(f, app, top[0]);
+    draw_depth(f, app, top[1]);
+
+    let middle = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage(35),
+                Constraint::Percentage(35),
+                Constraint::Percentage(30),
+            ]
+            .as_ref(),
+        )
+        .split(rows[1]);
+    draw_reserves(f, app, middle[0]);
+    draw_pid_terms(f, app, middle[1]);
+    draw_events(f, app, middle[2]);
+
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage(34),
+                Constraint::Percentage(22),
+                Constraint::Percentage(22),
+                Constraint::Percentage(22),
+            ]
+            .as_ref(),
+        )
+        .split(rows[2]);
+    draw_tape(f, app, bottom[0]);
+    draw_metrics(f, app, bottom[1]);
+    draw_controls(f, app, bottom[2]);
+    if app.order.is_some() {
+        draw_order(f, app, bottom[3]);
+    } else {
+        draw_help(f, app, bottom[3]);
+    }
+}
+
+fn draw_prices<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let chart = chart(
+        format!("{} - Liquidity Pool", app.curve.name()),
+        vec![
+            ("spot price", Color::Cyan, &app.spot_prices),
+            ("reference price", Color::Yellow, &app.reference_prices),
+        ],
+        "step",
+        4,
+    );
+    f.render_widget(chart, area);
+}
+
+fn draw_depth<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let pool = &app.simulation.pools()[0];
+    let (reserves_a, reserves_b) = pool.reserves();
+    // The slippage, in percent, of taking up to half of each reserve out of the pool.
+    let slippage = |side: Side, reserve: Decimal| {
+        let spot_price = pool.price(side, Decimal::ZERO);
+        let points = depth::price_curve(pool, side, reserve / dec!(2), DEPTH_SAMPLES);
+        depth::to_series(&points, |point| {
+            if spot_price.is_zero() {
+                Decimal::ZERO
+            } else {
+                (point.effective_price / spot_price - Decimal::ONE) * dec!(100)
+            }
+        })
+    };
+    let buy_a = slippage(Side::A, reserves_a);
+    let buy_b = slippage(Side::B, reserves_b);
+    let depth =
+        |side| depth::amount_for_impact(pool, side, dec!(0.01)).map_or("-".to_string(), number);
+    let chart = chart(
+        format!(
+            "Slippage % (1% at {} A / {} B)",
+            depth(Side::A),
+            depth(Side::B)
+        ),
+        vec![
+            ("buy A", Color::Cyan, &buy_a),
+            ("buy B", Color::Yellow, &buy_b),
+        ],
+        "amount out",
+        2,
+    );
+    f.render_widget(chart, area);
+}
+
+fn draw_reserves<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let chart = chart(
+        "Reserves".to_string(),
+        vec![
+            ("$UGAR", Color::Cyan, &app.reserves_a),
+            ("CUB$", Color::Yellow, &app.reserves_b),
+        ],
+        "step",
+        0,
+    );
+    f.render_widget(chart, area);
+}
+
+fn draw_pid_terms<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    if app.pid_terms[0].is_empty() {
+        let paragraph = Paragraph::new("The pool has no controller.")
+            .alignment(Alignment::Center)
+            .block(block("PID terms".to_string()));
+        f.render_widget(paragraph, area);
+        return;
+    }
+    let series = PID_TERMS
+        .iter()
+        .zip(PID_COLORS)
+        .zip(&app.pid_terms)
+        .map(|((name, color), data)| (*name, color, data.as_slice()))
+        .collect();
+    f.render_widget(chart("PID terms".to_string(), series, "step", 4), area);
+}
+
+/// Describes an event in a line of the tape or the events log, with its color.
+fn describe(event: &Event) -> (Color, String) {
+    match event {
+        Event::Swap {
+            side,
+            amount_out,
+            amount_in,
+        } => {
+            let (out, paid, color) = match side {
+                Side::A => ("A", "B", Color::Green),
+                Side::B => ("B", "A", Color::Red),
+            };
+            let text = format!(
+                "buy {} {} for {} {}",
+                number(*amount_out),
+                out,
+                number(*amount_in),
+                paid
+            );
+            (color, text)
+        }
+        Event::Mint { amount_a, amount_b } => (
+            Color::Cyan,
+            format!("add {} A + {} B", number(*amount_a), number(*amount_b)),
+        ),
+        Event::Burn { amount_a, amount_b } => (
+            Color::Magenta,
+            format!("remove {} A + {} B", number(*amount_a), number(*amount_b)),
+        ),
+        Event::Sync {
+            reserves_a,
+            reserves_b,
+        } => (
+            Color::Gray,
+            format!("sync {} / {}", number(*reserves_a), number(*reserves_b)),
+        ),
+        Event::ParameterChanged {
+            name,
+            old_value,
+            new_value,
+        } => (
+            Color::Yellow,
+            format!(
+                "{} {} -> {}",
+                name,
+                old_value.normalize(),
+                new_value.normalize()
+            ),
+        ),
+        Event::PidUpdate(terms) => (Color::Blue, format!("pid output {}", number(terms.output))),
+    }
+}
+
+fn draw_tape<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let lines: Vec<Spans> = app
+        .tape
+        .iter()
+        .map(|entry| {
+            let (color, text) = describe(&entry.event);
+            // The user's own trades stand out from the agents'.
+            let style = if entry.agent == "you" {
+                Style::default().add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Spans::from(vec![
+                Span::styled(format!("{:>6} {:<12} ", entry.step, entry.agent), style),
+                Span::styled(text, style.fg(color)),
+            ])
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(lines).block(block("Trades".to_string())),
+        area,
+    );
+}
+
+fn draw_events<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let lines: Vec<Spans> = app
+        .events
+        .iter()
+        .map(|(step, event)| {
+            let (color, text) = describe(event);
+            Spans::from(vec![
+                Span::raw(format!("{:>6} ", step)),
+                Span::styled(text, Style::default().fg(color)),
+            ])
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(lines).block(block("Events".to_string())),
+        area,
+    );
+}
+
+fn draw_metrics<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let mut lines = Vec::new();
+    if let Some(metrics) = &app.metrics {
+        let percent = |value: Decimal| format!("{}%", number(value * dec!(100)));
+        lines.push(format!("imp. loss   {}", percent(metrics.impermanent_loss)));
+        if let Some(lvr) = metrics.loss_versus_rebalancing {
+            lines.push(format!("LVR         {}", number(lvr)));
+        }
+        lines.push(format!("fees        {}", number(metrics.fees)));
+        lines.push(format!("fee APR     {}", percent(metrics.fee_apr)));
+        lines.push(format!("volatility  {}", number(metrics.volatility)));
+        if let Some(tracking) = metrics.tracking {
+            lines.push(format!("track. err. {}", number(tracking.rms_error)));
+            lines.push(format!("overshoot   {}", percent(tracking.overshoot)));
+        }
+    }
+    let lines: Vec<Spans> = lines.into_iter().map(Spans::from).collect();
+    f.render_widget(
+        Paragraph::new(lines).block(block("Metrics".to_string())),
+        area,
+    );
+}
+
+fn draw_controls<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let lines: Vec<Spans> = app
+        .controls()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (name, value))| {
+            let text = format!("{:<10} {}", name, value.normalize());
+            if index == app.selected {
+                Spans::from(Span::styled(
+                    format!("> {}", text),
+                    Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                ))
+            } else {
+                Spans::from(format!("  {}", text))
+            }
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(lines).block(block("Parameters".to_string())),
+        area,
+    );
+}
+
+fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let curves: Vec<Span> = Curve::ALL
+        .iter()
+        .enumerate()
+        .flat_map(|(index, curve)| {
+            let style = if *curve == app.curve {
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            [
+                Span::styled(format!("{} {}", index + 1, curve.name()), style),
+                Span::raw(" "),
+            ]
+        })
+        .collect();
+    let state = if app.paused { "paused" } else { "running" };
+    let (reserves_a, reserves_b) = app.simulation.pools()[0].reserves_rounded();
+    let lines = vec![
+        Spans::from(curves),
+        Spans::from(format!(
+            "step {}, {} at {}x",
+            app.simulation.step_count(),
+            state,
+            app.speed
+        )),
+        Spans::from(format!("seed {}", app.seed)),
+        Spans::from(format!("reserves {} / {}", reserves_a, reserves_b)),
+        Spans::from("up/down select, left/right adjust"),
+        Spans::from("space pause, n step, +/- speed"),
+        Spans::from("o order, r reseed, q quit"),
+    ];
+    f.render_widget(
+        Paragraph::new(lines).block(block("Simulation".to_string())),
+        area,
+    );
+}
+
+fn draw_order<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let Some(order) = &app.order else {
+        return;
+    };
+    let paid = match order.kind {
+        OrderKind::BuyA => "B",
+        OrderKind::BuyB | OrderKind::Add | OrderKind::Remove => "A",
+    };
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            format!("< {} >", order.kind.name()),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(format!("amount {}_ {}", order.amount, paid)),
+    ];
+    match app.quote() {
+        Some(Quote::Swap {
+            side,
+            amount_in,
+            amount_out,
+            price_impact,
+            fee,
+        }) => {
+            let (out, paid) = match side {
+                Side::A => ("A", "B"),
+                Side::B => ("B", "A"),
+            };
+            lines.push(Spans::from(format!(
+                "receive {} {}",
+                number(amount_out),
+                out
+            )));
+            lines.push(Spans::from(format!(
+                "price {} {}, impact {}%",
+                number(amount_in / amount_out),
+                paid,
+                number(price_impact * dec!(100))
+            )));
+            lines.push(Spans::from(format!("fee {} {}", number(fee), paid)));
+        }
+        Some(Quote::Liquidity { amount_a, amount_b }) => {
+            let verb = match order.kind {
+                OrderKind::Remove => "withdraw",
+                _ => "deposit",
+            };
+            lines.push(Spans::from(format!(
+                "{} {} A + {} B",
+                verb,
+                number(amount_a),
+                number(amount_b)
+            )));
+        }
+        None if order.amount.is_empty() => {}
+        None => lines.push(Spans::from(Span::styled(
+            "the pool cannot fill this",
+            Style::default().fg(Color::Red),
+        ))),
+    }
+    let account = app.account();
+    lines.push(Spans::from(format!(
+        "you {} A, {} B",
+        number(account.token_a),
+        number(account.token_b)
+    )));
+    lines.push(Spans::from("tab kind, enter send, esc close"));
+    f.render_widget(
+        Paragraph::new(lines).block(block("Order".to_string())),
+        area,
+    );
+}
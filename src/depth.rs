@@ -0,0 +1,176 @@
+// Liquidity depth analysis
+
+use crate::{LiquidityPool, Side};
+use alloc::vec::Vec;
+use rust_decimal::prelude::*;
+use rust_decimal_macros::dec;
+
+/// Maximum number of bisection steps used to search for a trade size.
+const MAX_STEPS: usize = 128;
+
+/// The trade size needed to reach a price impact threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthLevel {
+    /// The price impact threshold.
+    pub price_impact: Decimal,
+    /// The amount taken out of the pool to reach the threshold, if it can be reached.
+    pub amount: Option<Decimal>,
+}
+
+/// A sample of the price curve of a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurvePoint {
+    /// The amount taken out of the pool.
+    pub amount: Decimal,
+    /// The average price paid per unit for the amount.
+    pub effective_price: Decimal,
+    /// The price of the next unit once the amount has been taken out.
+    pub marginal_price: Decimal,
+}
+
+/// Computes the trade sizes needed to reach each price impact threshold on the given side.
+///
+/// Price impacts are measured with [`LiquidityPool::price_impact`], and are assumed to grow
+/// with the trade size. A threshold that cannot be reached before the pool runs out of the
+/// token has no amount.
+pub fn depth<P: LiquidityPool + ?Sized>(
+    pool: &P,
+    side: Side,
+    price_impacts: &[Decimal],
+) -> Vec<DepthLevel> {
+    price_impacts
+        .iter()
+        .map(|&price_impact| DepthLevel {
+            price_impact,
+            amount: amount_for_impact(pool, side, price_impact),
+        })
+        .collect()
+}
+
+/// Finds the smallest amount taken out on the given side whose price impact reaches the
+/// threshold.
+///
+/// Returns `None` if the threshold cannot be reached before the pool runs out of the token.
+pub fn amount_for_impact<P: LiquidityPool + ?Sized>(
+    pool: &P,
+    side: Side,
+    price_impact: Decimal,
+) -> Option<Decimal> {
+    let reserves = match side {
+        Side::A => pool.reserves().0,
+        Side::B => pool.reserves().1,
+    };
+    if price_impact <= Decimal::ZERO {
+        return Some(Decimal::ZERO);
+    }
+    let tolerance = reserves * dec!(0.000000000001);
+    let mut low = Decimal::ZERO;
+    let mut high = reserves - tolerance;
+    if high <= low || pool.price_impact(side, high) < price_impact {
+        return None;
+    }
+    for _ in 0..MAX_STEPS {
+        if high - low <= tolerance {
+            break;
+        }
+        let middle = (low + high) / Decimal::TWO;
+        if pool.price_impact(side, middle) < price_impact {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    Some(high)
+}
+
+/// Samples the price curve on the given side at `samples` evenly spaced amounts up to
+/// `max_amount`, starting from an empty trade.
+pub fn price_curve<P: LiquidityPool + ?Sized>(
+    pool: &P,
+    side: Side,
+    max_amount: Decimal,
+    samples: usize,
+) -> Vec<CurvePoint> {
+    let samples = samples.max(1);
+    let step = max_amount / Decimal::from(samples);
+    (0..=samples)
+        .map(|i| {
+            let amount = step * Decimal::from(i);
+            CurvePoint {
+                amount,
+                effective_price: pool.effective_price(side, amount),
+                marginal_price: pool.marginal_price_after(side, amount),
+            }
+        })
+        .collect()
+}
+
+/// Converts curve points into `(amount, price)` pairs suitable for plotting.
+pub fn to_series<F>(points: &[CurvePoint], price: F) -> Vec<(f64, f64)>
+where
+    F: Fn(&CurvePoint) -> Decimal,
+{
+    points
+        .iter()
+        .map(|point| {
+            (
+                point.amount.to_f64().unwrap_or_default(),
+                price(point).to_f64().unwrap_or_default(),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpmm::ConstantProductMarketMaker, csmm::ConstantSumMarketMaker};
+
+    #[test]
+    fn test_depth_cpmm() {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // The constant product price impact is `amount / (reserves - amount)`.
+        let levels = depth(&pool, Side::A, &[dec!(0.01), dec!(0.1), dec!(1)]);
+        let amounts: Vec<Decimal> = levels
+            .iter()
+            .map(|l| l.amount.unwrap().round_dp(6))
+            .collect();
+        assert_eq!(amounts, vec![dec!(0.990099), dec!(9.090909), dec!(50)]);
+
+        // Token B has twice the reserves, so it takes twice as much to move.
+        let amount = amount_for_impact(&pool, Side::B, dec!(1)).unwrap();
+        assert_eq!(amount.round_dp(6), dec!(100));
+    }
+
+    #[test]
+    fn test_depth_unreachable() {
+        let pool = ConstantSumMarketMaker::default();
+
+        // An empty pool cannot be traded against.
+        assert_eq!(amount_for_impact(&pool, Side::A, dec!(0.01)), None);
+        assert_eq!(
+            amount_for_impact(&pool, Side::A, Decimal::ZERO),
+            Some(Decimal::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_price_curve() {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+
+        // Sample the curve at 0, 25 and 50 units of token A.
+        let points = price_curve(&pool, Side::A, dec!(50), 2);
+        let amounts: Vec<Decimal> = points.iter().map(|p| p.amount).collect();
+        assert_eq!(amounts, vec![dec!(0), dec!(25), dec!(50)]);
+        assert_eq!(points[0].effective_price, dec!(2));
+        assert_eq!(points[2].effective_price, dec!(4));
+        assert_eq!(points[2].marginal_price, dec!(8));
+
+        // Convert the marginal prices for plotting.
+        let series = to_series(&points, |p| p.marginal_price);
+        assert_eq!(series[2], (50.0, 8.0));
+    }
+}
@@ -0,0 +1,411 @@
+// Pool events and observers
+
+use crate::{pidmm::PidTerms, LiquidityPool, Side};
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use rust_decimal::Decimal;
+
+/// A change to the state of a pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Event {
+    /// Tokens were swapped.
+    Swap {
+        /// The side of the pool taken out by the trade.
+        side: Side,
+        /// The amount taken out of the pool.
+        amount_out: Decimal,
+        /// The amount of the other token paid into the pool.
+        amount_in: Decimal,
+    },
+    /// Liquidity was deposited.
+    Mint {
+        amount_a: Decimal,
+        amount_b: Decimal,
+    },
+    /// Liquidity was withdrawn.
+    Burn {
+        amount_a: Decimal,
+        amount_b: Decimal,
+    },
+    /// The reserves changed, following any other event or a direct update.
+    Sync {
+        reserves_a: Decimal,
+        reserves_b: Decimal,
+    },
+    /// A named parameter of the pool changed.
+    ParameterChanged {
+        name: String,
+        old_value: Decimal,
+        new_value: Decimal,
+    },
+    /// The pool's controller computed a new control signal.
+    PidUpdate(PidTerms),
+}
+
+/// A swap about to be executed, passed to [`Observer::before_swap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingSwap {
+    /// The side of the pool taken out by the trade.
+    pub side: Side,
+    /// The amount to take out of the pool.
+    pub amount_out: Decimal,
+    /// The amount of the other token to pay into the pool.
+    pub amount_in: Decimal,
+    /// The reserves of the pool before the trade.
+    pub reserves: (Decimal, Decimal),
+}
+
+/// Clones observers behind trait objects.
+///
+/// Implemented for every observer that is `Clone`, so that observed pools can be cloned.
+pub trait ObserverClone {
+    /// Clones the observer into a new box.
+    fn clone_observer(&self) -> Box<dyn Observer>;
+}
+
+impl<T: Observer + Clone + 'static> ObserverClone for T {
+    fn clone_observer(&self) -> Box<dyn Observer> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Observer> {
+    fn clone(&self) -> Self {
+        (**self).clone_observer()
+    }
+}
+
+/// Hooks called around the state changes of an [`Observed`] pool.
+pub trait Observer: ObserverClone {
+    /// Called before a swap is executed.
+    ///
+    /// Returning `false` vetoes the swap, which then leaves the pool untouched and returns zero
+    /// like an unfillable trade.
+    fn before_swap(&mut self, _swap: &PendingSwap) -> bool {
+        true
+    }
+
+    /// Called after each event, once the pool has changed.
+    fn after(&mut self, _event: &Event) {}
+}
+
+/// Observed pool
+///
+/// Wraps any pool to emit an [`Event`] for each change to its state, both into an event log
+/// and to the attached observers. Every swap is followed by a `Sync` of the new reserves and,
+/// for pools with a controller, the resulting `PidUpdate`. Quotes are forwarded to the wrapped
+/// pool, so its own pricing is used.
+#[derive(Clone)]
+pub struct Observed<P> {
+    pool: P,
+    observers: Vec<Box<dyn Observer>>,
+    events: Vec<Event>,
+}
+
+impl<P: LiquidityPool> Observed<P> {
+    /// Wraps a pool with an empty event log and no observers.
+    pub fn new(pool: P) -> Self {
+        Self {
+            pool,
+            observers: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Attaches an observer.
+    pub fn observe<O: Observer + 'static>(&mut self, observer: O) -> &mut Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Returns the wrapped pool.
+    pub fn inner(&self) -> &P {
+        &self.pool
+    }
+
+    /// Unwraps the pool, dropping its observers and event log.
+    pub fn into_inner(self) -> P {
+        self.pool
+    }
+
+    /// Returns the events logged since the log was last taken.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Takes the logged events, leaving the log empty.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        core::mem::take(&mut self.events)
+    }
+
+    /// Logs an event and passes it to the observers.
+    fn emit(&mut self, event: Event) {
+        for observer in &mut self.observers {
+            observer.after(&event);
+        }
+        self.events.push(event);
+    }
+
+    /// Emits the events that follow any change to the reserves.
+    fn sync(&mut self) {
+        let (reserves_a, reserves_b) = self.pool.reserves();
+        self.emit(Event::Sync {
+            reserves_a,
+            reserves_b,
+        });
+        if let Some(terms) = self.pool.pid_terms() {
+            self.emit(Event::PidUpdate(terms));
+        }
+    }
+
+    /// Executes a swap unless an observer vetoes it.
+    fn observed_swap(&mut self, side: Side, amount: Decimal) -> Decimal {
+        let pending = PendingSwap {
+            side,
+            amount_out: amount,
+            amount_in: self.pool.cost(side, amount),
+            reserves: self.pool.reserves(),
+        };
+        if !self
+            .observers
+            .iter_mut()
+            .all(|observer| observer.before_swap(&pending))
+        {
+            return Decimal::ZERO;
+        }
+        let amount_in = self.pool.swap(side, amount);
+        if amount_in == Decimal::ZERO {
+            return Decimal::ZERO;
+        }
+        self.emit(Event::Swap {
+            side,
+            amount_out: amount,
+            amount_in,
+        });
+        self.sync();
+        amount_in
+    }
+}
+
+impl<P: LiquidityPool> LiquidityPool for Observed<P> {
+    fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal) {
+        self.pool.set_reserves(reserves_a, reserves_b);
+        self.sync();
+    }
+
+    fn reserves(&self) -> (Decimal, Decimal) {
+        self.pool.reserves()
+    }
+
+    fn price_a(&self, amount_a: Decimal) -> Decimal {
+        self.pool.price_a(amount_a)
+    }
+
+    fn price_b(&self, amount_b: Decimal) -> Decimal {
+        self.pool.price_b(amount_b)
+    }
+
+    fn price(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.price(side, amount)
+    }
+
+    fn cost(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.cost(side, amount)
+    }
+
+    fn amount_out(&self, side: Side, amount_in: Decimal) -> Decimal {
+        self.pool.amount_out(side, amount_in)
+    }
+
+    fn spot_price(&self) -> Decimal {
+        self.pool.spot_price()
+    }
+
+    fn effective_price(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.effective_price(side, amount)
+    }
+
+    fn marginal_price_after(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.marginal_price_after(side, amount)
+    }
+
+    fn amount_to_marginal_price(&self, side: Side, price: Decimal) -> Decimal {
+        self.pool.amount_to_marginal_price(side, price)
+    }
+
+    fn price_impact(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.price_impact(side, amount)
+    }
+
+    fn add_liquidity(&mut self, a: Decimal, b: Decimal) {
+        self.pool.add_liquidity(a, b);
+        self.emit(Event::Mint {
+            amount_a: a,
+            amount_b: b,
+        });
+        self.sync();
+    }
+
+    fn remove_liquidity(&mut self, a: Decimal, b: Decimal) {
+        self.pool.remove_liquidity(a, b);
+        self.emit(Event::Burn {
+            amount_a: a,
+            amount_b: b,
+        });
+        self.sync();
+    }
+
+    fn swap_a(&mut self, amount_a: Decimal) -> Decimal {
+        self.observed_swap(Side::A, amount_a)
+    }
+
+    fn swap_b(&mut self, amount_b: Decimal) -> Decimal {
+        self.observed_swap(Side::B, amount_b)
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Decimal)> {
+        self.pool.parameters()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Decimal) -> bool {
+        let old_value = self
+            .pool
+            .parameters()
+            .into_iter()
+            .find(|(parameter, _)| *parameter == name)
+            .map(|(_, value)| value);
+        if !self.pool.set_parameter(name, value) {
+            return false;
+        }
+        self.emit(Event::ParameterChanged {
+            name: name.to_string(),
+            old_value: old_value.unwrap_or(Decimal::ZERO),
+            new_value: value,
+        });
+        true
+    }
+
+    fn pid_terms(&self) -> Option<PidTerms> {
+        self.pool.pid_terms()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpmm::ConstantProductMarketMaker, pidmm::PIDMarketMaker};
+    use rust_decimal_macros::dec;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Vetoes swaps taking out more than a limit and counts the events it sees.
+    #[derive(Clone)]
+    struct Limit {
+        max_amount: Decimal,
+        seen: Rc<RefCell<usize>>,
+    }
+
+    impl Observer for Limit {
+        fn before_swap(&mut self, swap: &PendingSwap) -> bool {
+            swap.amount_out <= self.max_amount
+        }
+
+        fn after(&mut self, _event: &Event) {
+            *self.seen.borrow_mut() += 1;
+        }
+    }
+
+    #[test]
+    fn test_swap_events() {
+        let mut pool = Observed::new(ConstantProductMarketMaker::default());
+        pool.add_liquidity(dec!(100), dec!(200));
+        pool.swap_a(dec!(50));
+        pool.remove_liquidity(dec!(10), dec!(40));
+
+        // Each change is logged, followed by the new reserves.
+        assert_eq!(
+            pool.take_events(),
+            vec![
+                Event::Mint {
+                    amount_a: dec!(100),
+                    amount_b: dec!(200)
+                },
+                Event::Sync {
+                    reserves_a: dec!(100),
+                    reserves_b: dec!(200)
+                },
+                Event::Swap {
+                    side: Side::A,
+                    amount_out: dec!(50),
+                    amount_in: dec!(200)
+                },
+                Event::Sync {
+                    reserves_a: dec!(50),
+                    reserves_b: dec!(400)
+                },
+                Event::Burn {
+                    amount_a: dec!(10),
+                    amount_b: dec!(40)
+                },
+                Event::Sync {
+                    reserves_a: dec!(40),
+                    reserves_b: dec!(360)
+                },
+            ]
+        );
+        assert!(pool.events().is_empty());
+    }
+
+    #[test]
+    fn test_observer_veto() {
+        let seen = Rc::new(RefCell::new(0));
+        let mut pool = Observed::new(ConstantProductMarketMaker::default());
+        pool.observe(Limit {
+            max_amount: dec!(10),
+            seen: seen.clone(),
+        });
+        pool.add_liquidity(dec!(100), dec!(100));
+
+        // Large swaps are vetoed and leave the pool untouched.
+        assert_eq!(pool.swap(Side::B, dec!(20)), Decimal::ZERO);
+        assert_eq!(pool.swap_exact_in(Side::A, dec!(50)), Decimal::ZERO);
+        assert_eq!(pool.reserves(), (dec!(100), dec!(100)));
+
+        // Small swaps go through.
+        assert!(pool.swap(Side::B, dec!(5)) > Decimal::ZERO);
+        assert_eq!(*seen.borrow(), 4);
+        assert_eq!(pool.events().len(), 4);
+    }
+
+    #[test]
+    fn test_pid_events() {
+        let mut pool = Observed::new(PIDMarketMaker::default());
+        pool.set_reserves(dec!(100), dec!(100));
+
+        // The controller's terms follow each change to the reserves.
+        let events = pool.take_events();
+        assert_eq!(events.len(), 2);
+        let terms = pool.pid_terms().unwrap();
+        assert_eq!(events[1], Event::PidUpdate(terms));
+        assert_eq!(
+            terms.output,
+            terms.proportional + terms.integral + terms.derivative
+        );
+
+        // Tuning the controller is logged with the previous value.
+        assert!(pool.set_parameter("kp", dec!(0.2)));
+        assert!(!pool.set_parameter("fee", dec!(0.003)));
+        assert_eq!(
+            pool.take_events(),
+            vec![Event::ParameterChanged {
+                name: "kp".to_string(),
+                old_value: dec!(0.1),
+                new_value: dec!(0.2)
+            }]
+        );
+        assert_eq!(pool.inner().pid.kp, dec!(0.2));
+    }
+}
@@ -0,0 +1,239 @@
+// Swap fees
+
+use crate::{pidmm::PidTerms, LiquidityPool, Numeric, Rounding, Side};
+use alloc::vec::Vec;
+use rust_decimal::Decimal;
+
+/// Pool with a swap fee
+///
+/// Wraps any pool to charge a fee on top of the cost of each swap, as a fraction of that cost.
+/// The fee is paid in the token paid into the pool and stays in its reserves, so that it
+/// accrues to the liquidity providers. Quotes for any trade include the fee, while the spot
+/// price is the wrapped pool's, without it. The fee is exposed as the `fee` parameter.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WithFee<P, N = Decimal> {
+    pool: P,
+    fee: N,
+    collected_a: N,
+    collected_b: N,
+}
+
+impl<N: Numeric, P: LiquidityPool<N>> WithFee<P, N> {
+    /// Wraps a pool, charging `fee` on the cost of each swap.
+    pub fn new(pool: P, fee: N) -> Self {
+        Self {
+            pool,
+            fee,
+            collected_a: N::ZERO,
+            collected_b: N::ZERO,
+        }
+    }
+
+    /// Returns the fee, as a fraction of the cost of a swap.
+    pub fn fee(&self) -> N {
+        self.fee
+    }
+
+    /// Returns the fees collected so far in each token.
+    pub fn collected(&self) -> (N, N) {
+        (self.collected_a, self.collected_b)
+    }
+
+    /// Returns the wrapped pool.
+    pub fn inner(&self) -> &P {
+        &self.pool
+    }
+
+    /// Unwraps the pool, keeping the fees collected in its reserves.
+    pub fn into_inner(self) -> P {
+        self.pool
+    }
+
+    /// Computes the fee charged on a cost.
+    fn fee_on(&self, cost: N) -> N {
+        cost.mul_round(self.fee, Rounding::Up).unwrap_or(N::ZERO)
+    }
+
+    /// Returns the multiplier applied to prices, `1 + fee`.
+    fn markup(&self) -> N {
+        N::ONE.saturating_add(self.fee)
+    }
+}
+
+impl<N: Numeric, P: LiquidityPool<N>> LiquidityPool<N> for WithFee<P, N> {
+    fn set_reserves(&mut self, reserves_a: N, reserves_b: N) {
+        self.pool.set_reserves(reserves_a, reserves_b)
+    }
+
+    fn reserves(&self) -> (N, N) {
+        self.pool.reserves()
+    }
+
+    fn price_a(&self, amount_a: N) -> N {
+        self.pool
+            .price_a(amount_a)
+            .mul_round(self.markup(), Rounding::Up)
+            .unwrap_or(N::ZERO)
+    }
+
+    fn price_b(&self, amount_b: N) -> N {
+        self.pool
+            .price_b(amount_b)
+            .mul_round(self.markup(), Rounding::Up)
+            .unwrap_or(N::ZERO)
+    }
+
+    fn cost(&self, side: Side, amount: N) -> N {
+        let cost = self.pool.cost(side, amount);
+        cost.checked_add(self.fee_on(cost)).unwrap_or(N::ZERO)
+    }
+
+    /// The amount paid in net of the fee is what the wrapped pool trades along its curve.
+    fn amount_out(&self, side: Side, amount_in: N) -> N {
+        let amount_in = amount_in
+            .div_round(self.markup(), Rounding::Down)
+            .unwrap_or(N::ZERO);
+        self.pool.amount_out(side, amount_in)
+    }
+
+    fn spot_price(&self) -> N {
+        self.pool.spot_price()
+    }
+
+    fn marginal_price_after(&self, side: Side, amount: N) -> N {
+        self.pool
+            .marginal_price_after(side, amount)
+            .mul_round(self.markup(), Rounding::Up)
+            .unwrap_or(N::ZERO)
+    }
+
+    fn amount_to_marginal_price(&self, side: Side, price: N) -> N {
+        let price = price
+            .div_round(self.markup(), Rounding::Down)
+            .unwrap_or(N::ZERO);
+        self.pool.amount_to_marginal_price(side, price)
+    }
+
+    fn add_liquidity(&mut self, a: N, b: N) {
+        self.pool.add_liquidity(a, b)
+    }
+
+    fn remove_liquidity(&mut self, a: N, b: N) {
+        self.pool.remove_liquidity(a, b)
+    }
+
+    /// Swaps a given amount of token A for token B, charging the fee on the cost.
+    ///
+    /// The reserves are set once, including the fee, so that pools reacting to each change of
+    /// their reserves see a single change.
+    fn swap_a(&mut self, amount_a: N) -> N {
+        let cost_b = self.pool.cost(Side::A, amount_a);
+        if cost_b == N::ZERO {
+            return N::ZERO;
+        }
+        let fee_b = self.fee_on(cost_b);
+        let Some(paid_b) = cost_b.checked_add(fee_b) else {
+            return N::ZERO;
+        };
+        let (reserves_a, reserves_b) = self.pool.reserves();
+        self.pool.set_reserves(
+            reserves_a.checked_sub(amount_a).unwrap_or(N::ZERO),
+            reserves_b.saturating_add(paid_b),
+        );
+        self.collected_b = self.collected_b.saturating_add(fee_b);
+        paid_b
+    }
+
+    /// Swaps a given amount of token B for token A, charging the fee on the cost.
+    fn swap_b(&mut self, amount_b: N) -> N {
+        let cost_a = self.pool.cost(Side::B, amount_b);
+        if cost_a == N::ZERO {
+            return N::ZERO;
+        }
+        let fee_a = self.fee_on(cost_a);
+        let Some(paid_a) = cost_a.checked_add(fee_a) else {
+            return N::ZERO;
+        };
+        let (reserves_a, reserves_b) = self.pool.reserves();
+        self.pool.set_reserves(
+            reserves_a.saturating_add(paid_a),
+            reserves_b.checked_sub(amount_b).unwrap_or(N::ZERO),
+        );
+        self.collected_a = self.collected_a.saturating_add(fee_a);
+        paid_a
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, N)> {
+        let mut parameters = self.pool.parameters();
+        parameters.push(("fee", self.fee));
+        parameters
+    }
+
+    fn set_parameter(&mut self, name: &str, value: N) -> bool {
+        if name == "fee" {
+            self.fee = value;
+            return true;
+        }
+        self.pool.set_parameter(name, value)
+    }
+
+    fn pid_terms(&self) -> Option<PidTerms<N>> {
+        self.pool.pid_terms()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpmm::ConstantProductMarketMaker, pidmm::PIDMarketMaker};
+    use rust_decimal_macros::dec;
+
+    fn pool() -> WithFee<ConstantProductMarketMaker> {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(200));
+        WithFee::new(pool, dec!(0.01))
+    }
+
+    #[test]
+    fn test_swap_charges_fee() {
+        let mut pool = pool();
+
+        // Taking 50 of token A out costs 200 along the curve, plus 1% kept by the pool.
+        assert_eq!(pool.cost(Side::A, dec!(50)), dec!(202));
+        assert_eq!(pool.swap_a(dec!(50)), dec!(202));
+        assert_eq!(pool.reserves(), (dec!(50), dec!(402)));
+        assert_eq!(pool.collected(), (dec!(0), dec!(2)));
+
+        // The spot price ignores the fee.
+        assert_eq!(pool.spot_price(), pool.inner().spot_price());
+    }
+
+    #[test]
+    fn test_amount_out_net_of_fee() {
+        let pool = pool();
+
+        // Paying the cost of a trade, fee included, takes the same amount out.
+        let cost = pool.cost(Side::B, dec!(10));
+        assert_eq!(pool.amount_out(Side::B, cost).round_dp(8), dec!(10));
+
+        // Arbitrage stops where the marginal price, fee included, reaches the target.
+        let amount = pool.amount_to_marginal_price(Side::A, dec!(3));
+        assert_eq!(
+            pool.marginal_price_after(Side::A, amount).round_dp(8),
+            dec!(3)
+        );
+    }
+
+    #[test]
+    fn test_fee_parameter() {
+        let mut pool = WithFee::new(PIDMarketMaker::default(), dec!(0.003));
+
+        // The fee is tuned alongside the parameters of the wrapped pool.
+        assert!(pool.set_parameter("fee", dec!(0.005)));
+        assert!(pool.set_parameter("kp", dec!(0.2)));
+        assert_eq!(pool.fee(), dec!(0.005));
+        assert!(pool.parameters().contains(&("fee", dec!(0.005))));
+        assert!(pool.parameters().contains(&("kp", dec!(0.2))));
+    }
+}
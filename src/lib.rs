@@ -1,5 +1,24 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 mod amm;
+pub mod arbitrage;
+pub mod batch;
+pub mod depth;
+pub mod events;
+pub mod fee;
 mod liquidity_pool;
+mod numeric;
+mod oracle;
+pub mod registry;
+pub mod router;
+#[cfg(feature = "codec")]
+mod scale;
+#[cfg(feature = "sim")]
+pub mod sim;
 
 pub use self::amm::{cpmm, csmm, pidmm};
-pub use liquidity_pool::LiquidityPool;
+pub use self::numeric::{fixed, Numeric, Rounding};
+pub use self::oracle::{ema, twap, Oracle, WithOracle};
+pub use liquidity_pool::{LiquidityPool, Side};
@@ -1,26 +1,190 @@
+use crate::{pidmm::PidTerms, Numeric, Rounding};
+use alloc::{boxed::Box, vec::Vec};
 use rust_decimal::Decimal;
 
-pub trait LiquidityPool {
+/// The side of a trade, named after the token taken out of the pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "codec",
+    derive(codec::Encode, codec::Decode, scale_info::TypeInfo)
+)]
+pub enum Side {
+    /// Takes token A out of the pool in exchange for token B.
+    A,
+    /// Takes token B out of the pool in exchange for token A.
+    B,
+}
+
+impl Side {
+    /// Returns the other side of the pool.
+    pub fn opposite(self) -> Self {
+        match self {
+            Side::A => Side::B,
+            Side::B => Side::A,
+        }
+    }
+}
+
+pub trait LiquidityPool<N: Numeric = Decimal> {
     /// Sets the current reserves of the pool.
-    fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal);
+    fn set_reserves(&mut self, reserves_a: N, reserves_b: N);
 
     /// Returns the current reserves of the pool.
-    fn reserves(&self) -> (Decimal, Decimal);
+    fn reserves(&self) -> (N, N);
 
     /// Returns the current reserves of the pool rounded to 2 decimal places.
-    fn reserves_rounded(&self) -> (Decimal, Decimal) {
+    fn reserves_rounded(&self) -> (N, N) {
         let (reserves_a, reserves_b) = self.reserves();
         (reserves_a.round_dp(2), reserves_b.round_dp(2))
     }
 
     /// Computes the price of token A in terms of token B, given an amount of token A.
-    fn price_a(&self, amount_a: Decimal) -> Decimal;
+    fn price_a(&self, amount_a: N) -> N;
 
     /// Computes the price of token B in terms of token A, given an amount of token B.
-    fn price_b(&self, amount_b: Decimal) -> Decimal;
+    fn price_b(&self, amount_b: N) -> N;
+
+    /// Computes the price of the token taken out on the given side, in terms of the other token.
+    fn price(&self, side: Side, amount: N) -> N {
+        match side {
+            Side::A => self.price_a(amount),
+            Side::B => self.price_b(amount),
+        }
+    }
+
+    /// Computes the amount of the other token paid to take `amount` out on the given side.
+    fn cost(&self, side: Side, amount: N) -> N {
+        self.price(side, amount)
+            .mul_round(amount, Rounding::Up)
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the amount taken out on the given side when paying exactly `amount_in` of the
+    /// other token.
+    ///
+    /// The default implementation searches the largest amount whose cost does not exceed
+    /// `amount_in`, assuming the cost grows with the amount.
+    fn amount_out(&self, side: Side, amount_in: N) -> N {
+        let (reserves_a, reserves_b) = self.reserves();
+        let reserves_out = match side {
+            Side::A => reserves_a,
+            Side::B => reserves_b,
+        };
+        if amount_in <= N::ZERO || reserves_out <= N::ZERO {
+            return N::ZERO;
+        }
+        let mut low = N::ZERO;
+        let mut high = reserves_out;
+        for _ in 0..128 {
+            let middle = midpoint(low, high);
+            if middle == low || middle == high {
+                break;
+            }
+            let cost = self.cost(side, middle);
+            if cost > N::ZERO && cost <= amount_in {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        low
+    }
+
+    /// Returns the price of token A in terms of token B for an infinitesimally small trade.
+    fn spot_price(&self) -> N {
+        self.price_a(N::ZERO)
+    }
+
+    /// Computes the average price paid per unit when taking `amount` out on the given side.
+    ///
+    /// This is the execution price of the trade, `cost / amount`, so that every pool reports
+    /// what a trade actually pays. An empty trade pays the spot price of the side.
+    fn effective_price(&self, side: Side, amount: N) -> N {
+        if amount <= N::ZERO {
+            return self.price(side, N::ZERO);
+        }
+        self.cost(side, amount)
+            .div_round(amount, Rounding::Up)
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the price of the next infinitesimal unit after taking `amount` out on the given
+    /// side.
+    ///
+    /// This is the derivative of the cost with respect to the amount. The default
+    /// implementation approximates it numerically.
+    fn marginal_price_after(&self, side: Side, amount: N) -> N {
+        let step = amount
+            .max(N::ONE)
+            .div_round(N::from_u64(1_000_000), Rounding::Nearest)
+            .unwrap_or(N::ZERO);
+        let (low, high) = match (amount.checked_sub(step), amount.checked_add(step)) {
+            (Some(low), Some(high)) if amount >= step => (low, high),
+            (_, Some(high)) => (amount, high),
+            _ => return N::ZERO,
+        };
+        self.cost(side, high)
+            .checked_sub(self.cost(side, low))
+            .and_then(|difference| difference.div_round(high - low, Rounding::Nearest))
+            .unwrap_or(N::ZERO)
+    }
+
+    /// Computes the amount to take out on the given side for the marginal price to reach
+    /// `price`.
+    ///
+    /// Returns zero if the marginal price is already at or above `price`, or if it cannot reach
+    /// it. The default implementation searches the amount numerically, assuming the marginal
+    /// price grows with the amount.
+    fn amount_to_marginal_price(&self, side: Side, price: N) -> N {
+        let (reserves_a, reserves_b) = self.reserves();
+        let reserves_out = match side {
+            Side::A => reserves_a,
+            Side::B => reserves_b,
+        };
+        if self.marginal_price_after(side, N::ZERO) >= price {
+            return N::ZERO;
+        }
+        let mut low = N::ZERO;
+        let mut high = reserves_out;
+        for _ in 0..128 {
+            let middle = midpoint(low, high);
+            if middle == low || middle == high {
+                break;
+            }
+            let marginal_price = self.marginal_price_after(side, middle);
+            if marginal_price > N::ZERO && marginal_price < price {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        // Curves whose marginal price falls with the amount never reach the price.
+        let error = self
+            .marginal_price_after(side, low)
+            .checked_sub(price)
+            .map(N::abs);
+        let tolerance = price.div_round(N::from_u64(1_000_000), Rounding::Nearest);
+        match (error, tolerance) {
+            (Some(error), Some(tolerance)) if error <= tolerance => low,
+            _ => N::ZERO,
+        }
+    }
+
+    /// Computes the relative difference between the effective price of a trade and the spot
+    /// price of the same side.
+    ///
+    /// A price impact of `0.01` means the trade pays 1% more per unit than the spot price.
+    fn price_impact(&self, side: Side, amount: N) -> N {
+        let spot_price = self.price(side, N::ZERO);
+        self.effective_price(side, amount)
+            .div_round(spot_price, Rounding::Nearest)
+            .and_then(|ratio| ratio.checked_sub(N::ONE))
+            .unwrap_or(N::ZERO)
+    }
 
     /// Adds liquidity to the pool by depositing given amounts of two tokens.
-    fn add_liquidity(&mut self, a: Decimal, b: Decimal) {
+    fn add_liquidity(&mut self, a: N, b: N) {
         let (reserves_a, reserves_b) = self.reserves();
         let reserves_a = reserves_a.saturating_add(a);
         let reserves_b = reserves_b.saturating_add(b);
@@ -28,7 +192,7 @@ pub trait LiquidityPool {
     }
 
     /// Removes liquidity from the pool by withdrawing given amounts of two tokens.
-    fn remove_liquidity(&mut self, a: Decimal, b: Decimal) {
+    fn remove_liquidity(&mut self, a: N, b: N) {
         let (reserves_a, reserves_b) = self.reserves();
         let reserves_a = reserves_a.saturating_sub(a);
         let reserves_b = reserves_b.saturating_sub(b);
@@ -38,20 +202,17 @@ pub trait LiquidityPool {
     /// Swaps a given amount of token A for token B.
     ///
     /// Returns the amount of token B received.
-    fn swap_a(&mut self, amount_a: Decimal) -> Decimal {
-        let cost_b = self.price_a(amount_a) * amount_a;
-        if cost_b == Decimal::ZERO {
-            return Decimal::ZERO;
+    fn swap_a(&mut self, amount_a: N) -> N {
+        let cost_b = self
+            .price_a(amount_a)
+            .mul_round(amount_a, Rounding::Up)
+            .unwrap_or(N::ZERO);
+        if cost_b == N::ZERO {
+            return N::ZERO;
         }
         let reserves = self.reserves();
-        let reserves_a = reserves
-            .0
-            .checked_sub(amount_a)
-            .unwrap_or_else(|| Decimal::ZERO);
-        let reserves_b = reserves
-            .1
-            .checked_add(cost_b)
-            .unwrap_or_else(|| Decimal::ZERO);
+        let reserves_a = reserves.0.checked_sub(amount_a).unwrap_or(N::ZERO);
+        let reserves_b = reserves.1.checked_add(cost_b).unwrap_or(N::ZERO);
         self.set_reserves(reserves_a, reserves_b);
         cost_b
     }
@@ -59,21 +220,158 @@ pub trait LiquidityPool {
     /// Swaps a given amount of token B for token A.
     ///
     /// Returns the amount of token A received.
-    fn swap_b(&mut self, amount_b: Decimal) -> Decimal {
-        let cost_a = self.price_b(amount_b) * amount_b;
-        if cost_a == Decimal::ZERO {
-            return Decimal::ZERO;
+    fn swap_b(&mut self, amount_b: N) -> N {
+        let cost_a = self
+            .price_b(amount_b)
+            .mul_round(amount_b, Rounding::Up)
+            .unwrap_or(N::ZERO);
+        if cost_a == N::ZERO {
+            return N::ZERO;
         }
         let reserves = self.reserves();
-        let reserves_b = reserves
-            .1
-            .checked_sub(amount_b)
-            .unwrap_or_else(|| Decimal::ZERO);
-        let reserves_a = reserves
-            .0
-            .checked_add(cost_a)
-            .unwrap_or_else(|| Decimal::ZERO);
+        let reserves_b = reserves.1.checked_sub(amount_b).unwrap_or(N::ZERO);
+        let reserves_a = reserves.0.checked_add(cost_a).unwrap_or(N::ZERO);
         self.set_reserves(reserves_a, reserves_b);
         cost_a
     }
+
+    /// Swaps on the given side, taking `amount` of that side's token out of the pool.
+    ///
+    /// Returns the amount of the other token paid into the pool.
+    fn swap(&mut self, side: Side, amount: N) -> N {
+        match side {
+            Side::A => self.swap_a(amount),
+            Side::B => self.swap_b(amount),
+        }
+    }
+
+    /// Swaps exactly `amount_in` of the other token for the token on the given side.
+    ///
+    /// Returns the amount of the side's token taken out of the pool.
+    fn swap_exact_in(&mut self, side: Side, amount_in: N) -> N {
+        let amount_out = self.amount_out(side, amount_in);
+        if amount_out == N::ZERO || self.swap(side, amount_out) == N::ZERO {
+            return N::ZERO;
+        }
+        amount_out
+    }
+
+    /// Returns the named parameters of the pool that can be changed while it runs.
+    ///
+    /// Pools without tunable parameters return an empty list.
+    fn parameters(&self) -> Vec<(&'static str, N)> {
+        Vec::new()
+    }
+
+    /// Sets a named parameter of the pool.
+    ///
+    /// Returns `false` if the pool has no parameter with that name.
+    fn set_parameter(&mut self, _name: &str, _value: N) -> bool {
+        false
+    }
+
+    /// Returns the terms of the last control signal computed by the pool's controller, if it
+    /// has one.
+    fn pid_terms(&self) -> Option<PidTerms<N>> {
+        None
+    }
+}
+
+/// Returns the middle of two amounts, rounded down, without overflowing.
+fn midpoint<N: Numeric>(low: N, high: N) -> N {
+    (high - low)
+        .div_round(N::TWO, Rounding::Down)
+        .map(|half| low + half)
+        .unwrap_or(low)
+}
+
+/// Forwards every method to the boxed pool, so that its own implementations are used.
+impl<N: Numeric, P: LiquidityPool<N> + ?Sized> LiquidityPool<N> for Box<P> {
+    fn set_reserves(&mut self, reserves_a: N, reserves_b: N) {
+        (**self).set_reserves(reserves_a, reserves_b)
+    }
+
+    fn reserves(&self) -> (N, N) {
+        (**self).reserves()
+    }
+
+    fn reserves_rounded(&self) -> (N, N) {
+        (**self).reserves_rounded()
+    }
+
+    fn price_a(&self, amount_a: N) -> N {
+        (**self).price_a(amount_a)
+    }
+
+    fn price_b(&self, amount_b: N) -> N {
+        (**self).price_b(amount_b)
+    }
+
+    fn price(&self, side: Side, amount: N) -> N {
+        (**self).price(side, amount)
+    }
+
+    fn cost(&self, side: Side, amount: N) -> N {
+        (**self).cost(side, amount)
+    }
+
+    fn amount_out(&self, side: Side, amount_in: N) -> N {
+        (**self).amount_out(side, amount_in)
+    }
+
+    fn spot_price(&self) -> N {
+        (**self).spot_price()
+    }
+
+    fn effective_price(&self, side: Side, amount: N) -> N {
+        (**self).effective_price(side, amount)
+    }
+
+    fn marginal_price_after(&self, side: Side, amount: N) -> N {
+        (**self).marginal_price_after(side, amount)
+    }
+
+    fn amount_to_marginal_price(&self, side: Side, price: N) -> N {
+        (**self).amount_to_marginal_price(side, price)
+    }
+
+    fn price_impact(&self, side: Side, amount: N) -> N {
+        (**self).price_impact(side, amount)
+    }
+
+    fn add_liquidity(&mut self, a: N, b: N) {
+        (**self).add_liquidity(a, b)
+    }
+
+    fn remove_liquidity(&mut self, a: N, b: N) {
+        (**self).remove_liquidity(a, b)
+    }
+
+    fn swap_a(&mut self, amount_a: N) -> N {
+        (**self).swap_a(amount_a)
+    }
+
+    fn swap_b(&mut self, amount_b: N) -> N {
+        (**self).swap_b(amount_b)
+    }
+
+    fn swap(&mut self, side: Side, amount: N) -> N {
+        (**self).swap(side, amount)
+    }
+
+    fn swap_exact_in(&mut self, side: Side, amount_in: N) -> N {
+        (**self).swap_exact_in(side, amount_in)
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, N)> {
+        (**self).parameters()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: N) -> bool {
+        (**self).set_parameter(name, value)
+    }
+
+    fn pid_terms(&self) -> Option<PidTerms<N>> {
+        (**self).pid_terms()
+    }
 }
@@ -0,0 +1,450 @@
+use super::{Numeric, Rounding};
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+use primitive_types::U256;
+use rust_decimal::Decimal;
+
+/// Number of decimal places of a [`Fixed`].
+pub const DECIMALS: u32 = 18;
+
+/// The raw value of one.
+const SCALE: i128 = 1_000_000_000_000_000_000;
+
+/// Integer fixed-point backend
+///
+/// A number with 18 decimal places stored as an `i128` count of `10^-18` units, for
+/// environments that need every node to compute bit-identical results. Products and quotients
+/// are computed exactly on 256-bit intermediates and rounded once, in the requested direction,
+/// so the only error of an operation is its final rounding of at most `10^-18`. The type is
+/// signed because the errors fed to a PID controller can be negative.
+///
+/// Pools running on this backend agree with their [`Decimal`] counterparts to a relative
+/// tolerance of `10^-12` over long runs of trades, the residual coming from the 18 rather than
+/// 28 significant digits kept along the way.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "codec",
+    derive(codec::Encode, codec::Decode, scale_info::TypeInfo)
+)]
+pub struct Fixed(i128);
+
+impl Fixed {
+    /// The smallest representable number.
+    pub const MIN: Self = Self(i128::MIN);
+
+    /// The largest representable number.
+    pub const MAX: Self = Self(i128::MAX);
+
+    /// Creates a number from its raw count of `10^-18` units.
+    pub const fn from_raw(raw: i128) -> Self {
+        Self(raw)
+    }
+
+    /// Returns the raw count of `10^-18` units.
+    pub const fn raw(self) -> i128 {
+        self.0
+    }
+
+    /// Converts a magnitude and sign back into a number, if it fits.
+    fn from_magnitude(magnitude: U256, negative: bool) -> Option<Self> {
+        let magnitude = i128::try_from(u128::try_from(magnitude).ok()?).ok()?;
+        Some(Self(if negative { -magnitude } else { magnitude }))
+    }
+}
+
+/// Divides a magnitude, rounding the signed result in the given direction.
+fn div_magnitude(numerator: U256, divisor: U256, rounding: Rounding, negative: bool) -> U256 {
+    let (quotient, remainder) = numerator.div_mod(divisor);
+    if remainder.is_zero() {
+        return quotient;
+    }
+    let increment = match (rounding, negative) {
+        (Rounding::Nearest, _) => {
+            let twice = remainder << 1;
+            twice > divisor || (twice == divisor && quotient.bit(0))
+        }
+        (Rounding::Up, false) | (Rounding::Down, true) => true,
+        (Rounding::Down, false) | (Rounding::Up, true) => false,
+    };
+    if increment {
+        quotient + 1
+    } else {
+        quotient
+    }
+}
+
+/// Returns the magnitude of a raw value.
+fn magnitude(raw: i128) -> U256 {
+    U256::from(raw.unsigned_abs())
+}
+
+impl Numeric for Fixed {
+    const ZERO: Self = Self(0);
+    const ONE: Self = Self(SCALE);
+    const TWO: Self = Self(2 * SCALE);
+
+    fn from_u64(value: u64) -> Self {
+        Self(value as i128 * SCALE)
+    }
+
+    fn from_decimal(value: Decimal) -> Option<Self> {
+        let mantissa = value.mantissa();
+        let scale = value.scale();
+        if scale <= DECIMALS {
+            return mantissa.checked_mul(10i128.pow(DECIMALS - scale)).map(Self);
+        }
+        let divisor = U256::from(10u128.pow(scale - DECIMALS));
+        let negative = mantissa < 0;
+        let quotient = div_magnitude(magnitude(mantissa), divisor, Rounding::Nearest, negative);
+        Self::from_magnitude(quotient, negative)
+    }
+
+    fn to_decimal(self) -> Decimal {
+        let mut raw = self.0;
+        let mut scale = DECIMALS;
+        loop {
+            if let Ok(decimal) = Decimal::try_from_i128_with_scale(raw, scale) {
+                return decimal.normalize();
+            }
+            // Drop a digit until the mantissa fits in 96 bits.
+            let negative = raw < 0;
+            let quotient =
+                div_magnitude(magnitude(raw), U256::from(10), Rounding::Nearest, negative);
+            raw = quotient.as_u128() as i128;
+            if negative {
+                raw = -raw;
+            }
+            scale -= 1;
+        }
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    fn mul_round(self, rhs: Self, rounding: Rounding) -> Option<Self> {
+        let negative = (self.0 < 0) != (rhs.0 < 0);
+        let product = magnitude(self.0) * magnitude(rhs.0);
+        let quotient = div_magnitude(product, U256::from(SCALE), rounding, negative);
+        Self::from_magnitude(quotient, negative)
+    }
+
+    fn div_round(self, rhs: Self, rounding: Rounding) -> Option<Self> {
+        if rhs.0 == 0 {
+            return None;
+        }
+        let negative = (self.0 < 0) != (rhs.0 < 0);
+        let numerator = magnitude(self.0) * U256::from(SCALE);
+        let quotient = div_magnitude(numerator, magnitude(rhs.0), rounding, negative);
+        Self::from_magnitude(quotient, negative)
+    }
+
+    fn sqrt(self) -> Option<Self> {
+        if self.0 < 0 {
+            return None;
+        }
+        let root = (magnitude(self.0) * U256::from(SCALE)).integer_sqrt();
+        Self::from_magnitude(root, false)
+    }
+
+    fn round_dp(self, dp: u32) -> Self {
+        if dp >= DECIMALS {
+            return self;
+        }
+        let unit = 10i128.pow(DECIMALS - dp);
+        let negative = self.0 < 0;
+        let quotient = div_magnitude(
+            magnitude(self.0),
+            U256::from(unit),
+            Rounding::Nearest,
+            negative,
+        );
+        // Rounding up past the range falls back to the multiple toward zero, which always fits.
+        let unit = U256::from(unit);
+        Self::from_magnitude(quotient * unit, negative)
+            .or_else(|| Self::from_magnitude((quotient - 1) * unit, negative))
+            .expect("truncated magnitude fits")
+    }
+
+    fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Add for Fixed {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.checked_add(rhs).expect("fixed-point overflow")
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.checked_sub(rhs).expect("fixed-point overflow")
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        self.mul_round(rhs, Rounding::Nearest)
+            .expect("fixed-point overflow")
+    }
+}
+
+impl Div for Fixed {
+    type Output = Self;
+
+    fn div(self, rhs: Self) -> Self {
+        self.div_round(rhs, Rounding::Nearest)
+            .expect("fixed-point overflow or division by zero")
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Self;
+
+    /// Negates the number, saturating [`Fixed::MIN`] to [`Fixed::MAX`].
+    fn neg(self) -> Self {
+        Self(self.0.saturating_neg())
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Self) {
+        *self = *self - rhs;
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.to_decimal(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpmm::ConstantProductMarketMaker, csmm::ConstantSumMarketMaker, pidmm::PIDMarketMaker,
+        LiquidityPool, Side,
+    };
+    use rust_decimal_macros::dec;
+
+    fn fixed(value: Decimal) -> Fixed {
+        Fixed::from_decimal(value).unwrap()
+    }
+
+    #[test]
+    fn test_decimal_conversion() {
+        // Decimals with up to 18 places convert exactly.
+        assert_eq!(fixed(dec!(1.5)).raw(), 1_500_000_000_000_000_000);
+        assert_eq!(fixed(dec!(-0.25)).to_decimal(), dec!(-0.25));
+
+        // Extra places are rounded to the nearest, ties to even.
+        assert_eq!(fixed(dec!(0.0000000000000000015)).raw(), 2);
+        assert_eq!(fixed(dec!(0.0000000000000000025)).raw(), 2);
+        assert_eq!(fixed(dec!(-0.0000000000000000016)).raw(), -2);
+
+        // Numbers beyond the 96 bits of a decimal lose their last digits.
+        let large = Fixed::from_raw(i128::MAX);
+        assert_eq!(large.to_decimal().round(), dec!(170141183460469231732));
+    }
+
+    #[test]
+    fn test_explicit_rounding() {
+        let one = Fixed::ONE;
+        let three = Fixed::from_u64(3);
+
+        // One third is rounded in the requested direction.
+        let down = one.div_round(three, Rounding::Down).unwrap();
+        let up = one.div_round(three, Rounding::Up).unwrap();
+        assert_eq!(down.raw(), 333_333_333_333_333_333);
+        assert_eq!(up.raw(), 333_333_333_333_333_334);
+        assert_eq!(one / three, down);
+
+        // Negative results round toward the requested infinity.
+        let down = (-one).div_round(three, Rounding::Down).unwrap();
+        assert_eq!(down.raw(), -333_333_333_333_333_334);
+
+        // Products are exact before their single rounding.
+        let third = Fixed::from_raw(333_333_333_333_333_333);
+        assert_eq!(
+            third.mul_round(three, Rounding::Up).unwrap().raw(),
+            999_999_999_999_999_999
+        );
+        assert_eq!(one.div_round(Fixed::ZERO, Rounding::Down), None);
+    }
+
+    #[test]
+    fn test_sqrt_and_overflow() {
+        // Square roots are rounded down.
+        assert_eq!(Fixed::from_u64(4).sqrt(), Some(Fixed::TWO));
+        assert_eq!(Fixed::TWO.sqrt().unwrap().raw(), 1_414_213_562_373_095_048);
+        assert_eq!((-Fixed::ONE).sqrt(), None);
+
+        // Overflow is reported by the named methods.
+        let large = Fixed::from_raw(i128::MAX / 2);
+        assert_eq!(large.mul_round(Fixed::from_u64(3), Rounding::Down), None);
+        assert_eq!(large.checked_add(large).unwrap().raw(), i128::MAX - 1);
+        assert_eq!(
+            large.saturating_add(large + large),
+            Fixed::from_raw(i128::MAX)
+        );
+    }
+
+    #[test]
+    fn test_round_dp() {
+        assert_eq!(fixed(dec!(1.005)).round_dp(2), fixed(dec!(1)));
+        assert_eq!(fixed(dec!(1.015)).round_dp(2), fixed(dec!(1.02)));
+        assert_eq!(fixed(dec!(-1.016)).round_dp(2), fixed(dec!(-1.02)));
+        assert_eq!(fixed(dec!(1.016)).to_string(), "1.016");
+    }
+
+    #[test]
+    fn test_extremes_saturate() {
+        assert_eq!(-Fixed::MIN, Fixed::MAX);
+        assert_eq!(-Fixed::MAX, Fixed::from_raw(i128::MIN + 1));
+
+        // Rounding keeps the extremes in range, falling back toward zero.
+        let unit = 10i128.pow(DECIMALS - 2);
+        assert_eq!(
+            Fixed::MAX.round_dp(2),
+            Fixed::from_raw(i128::MAX / unit * unit)
+        );
+        assert_eq!(
+            Fixed::MIN.round_dp(2),
+            Fixed::from_raw(i128::MIN / unit * unit)
+        );
+        assert_eq!(Fixed::MAX.round_dp(0).raw() % SCALE, 0);
+        assert_eq!(Fixed::MIN.round_dp(0).raw() % SCALE, 0);
+        assert_eq!(Fixed::MAX.round_dp(DECIMALS), Fixed::MAX);
+    }
+
+    /// Returns the relative difference between a fixed-point and a decimal result.
+    fn relative_error(fixed: Fixed, decimal: Decimal) -> Decimal {
+        let difference = (fixed.to_decimal() - decimal).abs();
+        if decimal.is_zero() {
+            difference
+        } else {
+            difference / decimal.abs()
+        }
+    }
+
+    /// Runs the same trades against a decimal and a fixed-point pool, checking that their
+    /// reserves and quotes stay within the documented tolerance.
+    fn assert_pools_match<P, Q>(mut decimal_pool: P, mut fixed_pool: Q)
+    where
+        P: LiquidityPool<Decimal>,
+        Q: LiquidityPool<Fixed>,
+    {
+        let tolerance = dec!(0.000000000001);
+        decimal_pool.add_liquidity(dec!(1000), dec!(2000));
+        fixed_pool.add_liquidity(Fixed::from_u64(1000), Fixed::from_u64(2000));
+        for i in 0..200u64 {
+            let side = if i % 3 == 0 { Side::B } else { Side::A };
+            let amount = Decimal::from(i % 7 + 1) / dec!(4);
+            let cost = decimal_pool.swap(side, amount);
+            let fixed_cost = fixed_pool.swap(side, fixed(amount));
+            assert!(relative_error(fixed_cost, cost) <= tolerance);
+            let (reserves_a, reserves_b) = decimal_pool.reserves();
+            let (fixed_a, fixed_b) = fixed_pool.reserves();
+            assert!(relative_error(fixed_a, reserves_a) <= tolerance);
+            assert!(relative_error(fixed_b, reserves_b) <= tolerance);
+            assert!(
+                relative_error(fixed_pool.spot_price(), decimal_pool.spot_price()) <= tolerance
+            );
+        }
+        let amount_in = dec!(25);
+        let amount_out = decimal_pool.amount_out(Side::A, amount_in);
+        let fixed_out = fixed_pool.amount_out(Side::A, fixed(amount_in));
+        assert!(relative_error(fixed_out, amount_out) <= tolerance);
+    }
+
+    #[test]
+    fn test_pools_match_decimal() {
+        assert_pools_match(
+            ConstantProductMarketMaker::<Decimal>::default(),
+            ConstantProductMarketMaker::<Fixed>::default(),
+        );
+        assert_pools_match(
+            ConstantSumMarketMaker::<Decimal>::default(),
+            ConstantSumMarketMaker::<Fixed>::default(),
+        );
+        assert_pools_match(
+            PIDMarketMaker::<Decimal>::default(),
+            PIDMarketMaker::<Fixed>::default(),
+        );
+    }
+
+    #[test]
+    fn test_extreme_reserves_do_not_panic() {
+        let large = Fixed::from_raw(i128::MAX - 1);
+        let mut pool = ConstantSumMarketMaker::<Fixed>::default();
+        pool.set_reserves(Fixed::ZERO, large);
+
+        // An empty side quotes nothing and cannot be traded.
+        assert_eq!(pool.spot_price(), Fixed::ZERO);
+        assert_eq!(pool.price_a(Fixed::ONE), Fixed::ZERO);
+        assert_eq!(pool.swap_a(Fixed::ONE), Fixed::ZERO);
+        assert_eq!(pool.amount_out(Side::A, large), Fixed::ZERO);
+
+        // Quotes on the full side overflow to zero rather than panicking.
+        for amount in [Fixed::ONE, large] {
+            pool.price_b(amount);
+            pool.cost(Side::B, amount);
+            pool.effective_price(Side::B, amount);
+            pool.price_impact(Side::B, amount);
+            pool.marginal_price_after(Side::B, amount);
+            pool.amount_out(Side::B, amount);
+        }
+        pool.amount_to_marginal_price(Side::B, large);
+        pool.swap_exact_in(Side::B, large);
+        pool.add_liquidity(large, large);
+
+        // The controller saturates instead of overflowing on the same reserves.
+        let mut pool = PIDMarketMaker::<Fixed>::default();
+        pool.set_reserves(Fixed::ZERO, large);
+        pool.set_reserves(large, large);
+        pool.swap_a(Fixed::ONE);
+        pool.swap_b(large);
+    }
+
+    #[test]
+    fn test_pools_round_in_their_favor() {
+        let mut pool = ConstantProductMarketMaker::<Fixed>::default();
+        pool.add_liquidity(Fixed::from_u64(4), Fixed::ONE);
+
+        // Taking one unit out costs a third, rounded up.
+        assert_eq!(
+            pool.cost(Side::A, Fixed::ONE).raw(),
+            333_333_333_333_333_334
+        );
+
+        // Paying one unit takes two thirds out, rounded down.
+        pool.set_reserves(Fixed::TWO, Fixed::TWO);
+        let amount_out = pool.amount_out(Side::A, Fixed::ONE);
+        assert_eq!(amount_out.raw(), 666_666_666_666_666_666);
+    }
+}
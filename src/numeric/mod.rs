@@ -0,0 +1,152 @@
+// Numeric backends
+
+pub mod fixed;
+
+use core::{
+    fmt::Debug,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+use rust_decimal::{Decimal, MathematicalOps, RoundingStrategy};
+
+/// The direction in which an inexact result is rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rounding {
+    /// Rounds toward negative infinity.
+    Down,
+    /// Rounds toward positive infinity.
+    Up,
+    /// Rounds to the nearest value, ties to even.
+    Nearest,
+}
+
+/// A number the pools can compute with.
+///
+/// The operators round inexact results to the nearest and panic on overflow, while the named
+/// methods report overflow and round in an explicit direction. Pools round in their own favor:
+/// prices and costs up, amounts taken out down.
+pub trait Numeric:
+    Copy
+    + Debug
+    + Default
+    + Ord
+    + Send
+    + Sync
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+{
+    /// Zero.
+    const ZERO: Self;
+    /// One.
+    const ONE: Self;
+    /// Two.
+    const TWO: Self;
+
+    /// Converts an integer.
+    fn from_u64(value: u64) -> Self;
+
+    /// Converts a decimal, rounding to the nearest representable value.
+    ///
+    /// Returns `None` if the decimal is out of range.
+    fn from_decimal(value: Decimal) -> Option<Self>;
+
+    /// Converts the number to a decimal, rounding to the nearest decimal if needed.
+    fn to_decimal(self) -> Decimal;
+
+    /// Adds, returning `None` on overflow.
+    fn checked_add(self, rhs: Self) -> Option<Self>;
+
+    /// Subtracts, returning `None` on overflow.
+    fn checked_sub(self, rhs: Self) -> Option<Self>;
+
+    /// Multiplies, rounding in the given direction and returning `None` on overflow.
+    fn mul_round(self, rhs: Self, rounding: Rounding) -> Option<Self>;
+
+    /// Divides, rounding in the given direction and returning `None` on overflow or division by
+    /// zero.
+    fn div_round(self, rhs: Self, rounding: Rounding) -> Option<Self>;
+
+    /// Computes the square root, rounded down. Returns `None` for negative numbers.
+    fn sqrt(self) -> Option<Self>;
+
+    /// Rounds to the given number of decimal places, ties to even.
+    fn round_dp(self, dp: u32) -> Self;
+
+    /// Adds, saturating at the bounds of the type.
+    fn saturating_add(self, rhs: Self) -> Self;
+
+    /// Subtracts, saturating at the bounds of the type.
+    fn saturating_sub(self, rhs: Self) -> Self;
+
+    /// Returns the absolute value.
+    fn abs(self) -> Self {
+        if self < Self::ZERO {
+            -self
+        } else {
+            self
+        }
+    }
+}
+
+/// Decimal backend
+///
+/// Results are exact up to 28 significant digits and rounded to the nearest beyond that,
+/// regardless of the requested direction.
+impl Numeric for Decimal {
+    const ZERO: Self = Decimal::ZERO;
+    const ONE: Self = Decimal::ONE;
+    const TWO: Self = Decimal::TWO;
+
+    fn from_u64(value: u64) -> Self {
+        Decimal::from(value)
+    }
+
+    fn from_decimal(value: Decimal) -> Option<Self> {
+        Some(value)
+    }
+
+    fn to_decimal(self) -> Decimal {
+        self
+    }
+
+    fn checked_add(self, rhs: Self) -> Option<Self> {
+        Decimal::checked_add(self, rhs)
+    }
+
+    fn checked_sub(self, rhs: Self) -> Option<Self> {
+        Decimal::checked_sub(self, rhs)
+    }
+
+    fn mul_round(self, rhs: Self, _rounding: Rounding) -> Option<Self> {
+        Decimal::checked_mul(self, rhs)
+    }
+
+    fn div_round(self, rhs: Self, _rounding: Rounding) -> Option<Self> {
+        Decimal::checked_div(self, rhs)
+    }
+
+    fn sqrt(self) -> Option<Self> {
+        MathematicalOps::sqrt(&self)
+    }
+
+    fn round_dp(self, dp: u32) -> Self {
+        Decimal::round_dp_with_strategy(&self, dp, RoundingStrategy::MidpointNearestEven)
+    }
+
+    fn saturating_add(self, rhs: Self) -> Self {
+        Decimal::saturating_add(self, rhs)
+    }
+
+    fn saturating_sub(self, rhs: Self) -> Self {
+        Decimal::saturating_sub(self, rhs)
+    }
+
+    fn abs(self) -> Self {
+        Decimal::abs(&self)
+    }
+}
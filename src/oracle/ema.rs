@@ -0,0 +1,206 @@
+use super::Oracle;
+use crate::LiquidityPool;
+use rust_decimal::{Decimal, MathematicalOps};
+use rust_decimal_macros::dec;
+
+/// Exponential moving average price oracle
+///
+/// The oracle smooths the spot price of token A in terms of token B with an exponential moving
+/// average whose weight halves every `half_life` time units, in the style of Curve v2. The
+/// average moves toward the last recorded price as time passes, so the price set by a trade
+/// only starts to count once it has held for a while. Wrapping the pool in a
+/// [`WithOracle`](super::WithOracle) updates the oracle on every trade.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EmaOracle {
+    pub half_life: u64,
+    value: Decimal,
+    last_price: Decimal,
+    last_timestamp: u64,
+    initialized: bool,
+}
+
+#[cfg(feature = "codec")]
+crate::scale::scale_codec!(EmaOracle {
+    half_life: u64,
+    value: Decimal,
+    last_price: Decimal,
+    last_timestamp: u64,
+    initialized: bool,
+});
+
+impl EmaOracle {
+    /// Creates a new oracle with the given half-life.
+    pub fn new(half_life: u64) -> Self {
+        Self {
+            half_life,
+            value: Decimal::ZERO,
+            last_price: Decimal::ZERO,
+            last_timestamp: 0,
+            initialized: false,
+        }
+    }
+
+    /// Returns the oracle value as of the last update.
+    pub fn value(&self) -> Decimal {
+        self.value
+    }
+
+    /// Returns the last recorded price.
+    pub fn last_price(&self) -> Decimal {
+        self.last_price
+    }
+
+    /// Returns the timestamp of the last update.
+    pub fn last_timestamp(&self) -> u64 {
+        self.last_timestamp
+    }
+
+    /// Records the current spot price of a pool at the given timestamp.
+    pub fn observe<P: LiquidityPool + ?Sized>(&mut self, pool: &P, timestamp: u64) {
+        self.update(timestamp, pool.spot_price());
+    }
+
+    /// Records a new price taking effect at the given timestamp.
+    ///
+    /// The first update initializes the oracle to `price`. Updates older than the last one are
+    /// ignored.
+    pub fn update(&mut self, timestamp: u64, price: Decimal) {
+        if !self.initialized {
+            self.value = price;
+            self.initialized = true;
+        } else if timestamp < self.last_timestamp {
+            return;
+        } else {
+            self.value = self.value_at(timestamp);
+        }
+        self.last_price = price;
+        self.last_timestamp = timestamp;
+    }
+
+    /// Computes the oracle value at the given timestamp, assuming no trades happen until then.
+    pub fn value_at(&self, timestamp: u64) -> Decimal {
+        if timestamp <= self.last_timestamp {
+            return self.value;
+        }
+        let weight = self.decay(timestamp - self.last_timestamp);
+        self.value * weight + self.last_price * (Decimal::ONE - weight)
+    }
+
+    /// Computes the weight `2^(-elapsed / half_life)` left to the previous value.
+    fn decay(&self, elapsed: u64) -> Decimal {
+        if self.half_life == 0 {
+            return Decimal::ZERO;
+        }
+        // Whole half-lives are applied exactly, only the remainder goes through `exp`.
+        let half_lives = elapsed / self.half_life;
+        if half_lives > 96 {
+            return Decimal::ZERO;
+        }
+        let ln_2 = dec!(0.6931471805599453094172321215);
+        let remainder = Decimal::from(elapsed % self.half_life) / Decimal::from(self.half_life);
+        let weight = (-ln_2 * remainder).checked_exp().unwrap_or(Decimal::ZERO);
+        weight * dec!(0.5).powu(half_lives)
+    }
+}
+
+impl Oracle for EmaOracle {
+    fn observe<P: LiquidityPool + ?Sized>(&mut self, pool: &P, timestamp: u64) {
+        EmaOracle::observe(self, pool, timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpmm::ConstantProductMarketMaker, oracle::WithOracle, Side};
+
+    #[test]
+    fn test_first_update_initializes() {
+        let mut oracle = EmaOracle::new(10);
+        oracle.update(100, dec!(2));
+
+        // The first price is taken as is.
+        assert_eq!(oracle.value(), dec!(2));
+        assert_eq!(oracle.value_at(1000), dec!(2));
+    }
+
+    #[test]
+    fn test_value_after_half_life() {
+        let mut oracle = EmaOracle::new(10);
+        oracle.update(0, dec!(1));
+        oracle.update(0, dec!(3));
+
+        // After one half-life the oracle has moved halfway to the new price.
+        assert_eq!(oracle.value_at(10).round_dp(8), dec!(2));
+        // After two half-lives it has moved three quarters of the way.
+        assert_eq!(oracle.value_at(20).round_dp(8), dec!(2.5));
+    }
+
+    #[test]
+    fn test_value_at_converges() {
+        let mut oracle = EmaOracle::new(10);
+        oracle.update(0, dec!(1));
+        oracle.update(5, dec!(5));
+
+        // Without trades the oracle converges to the last price.
+        assert_eq!(oracle.value_at(5), dec!(1));
+        assert!(oracle.value_at(10) < oracle.value_at(20));
+        assert_eq!(oracle.value_at(100_000).round_dp(8), dec!(5));
+    }
+
+    #[test]
+    fn test_update_uses_previous_price() {
+        let mut oracle = EmaOracle::new(10);
+        oracle.update(0, dec!(1));
+        oracle.update(10, dec!(3));
+        oracle.update(20, dec!(100));
+
+        // The price recorded at 20 has not had any time to count yet.
+        assert_eq!(oracle.value().round_dp(8), dec!(2));
+    }
+
+    #[test]
+    fn test_zero_half_life() {
+        let mut oracle = EmaOracle::new(0);
+        oracle.update(0, dec!(1));
+        oracle.update(1, dec!(3));
+
+        // A zero half-life follows the last price as soon as time passes.
+        assert_eq!(oracle.value_at(2), dec!(3));
+    }
+
+    #[test]
+    fn test_observe_pool() {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+        let mut oracle = EmaOracle::new(60);
+        oracle.observe(&pool, 0);
+
+        // A trade moves the spot price but not the oracle right away.
+        pool.swap_a(dec!(50));
+        oracle.observe(&pool, 1);
+        assert_eq!(oracle.value().round_dp(8), dec!(2));
+        assert_eq!(oracle.last_price(), dec!(8));
+        assert_eq!(oracle.value_at(61).round_dp(8), dec!(5));
+    }
+
+    #[test]
+    fn test_with_oracle_updates_on_trades() {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+        let mut pool = WithOracle::new(pool, EmaOracle::new(60), 0);
+
+        // A trade through the pool feeds the oracle without any call by hand.
+        pool.set_timestamp(1);
+        pool.swap_exact_in(Side::B, dec!(100));
+        assert_eq!(pool.oracle().last_timestamp(), 1);
+        assert_eq!(pool.oracle().last_price(), pool.spot_price());
+        assert_eq!(pool.oracle().value().round_dp(8), dec!(2));
+
+        // The next trade folds in the price the previous one set.
+        pool.set_timestamp(61);
+        pool.swap_exact_in(Side::A, dec!(1));
+        assert_eq!(pool.oracle().value().round_dp(8), dec!(1.25));
+    }
+}
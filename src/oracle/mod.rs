@@ -0,0 +1,173 @@
+// Price oracles
+
+use crate::{pidmm::PidTerms, LiquidityPool, Side};
+use alloc::vec::Vec;
+use rust_decimal::Decimal;
+
+pub mod ema;
+pub mod twap;
+
+/// A price oracle fed with the state of a pool.
+pub trait Oracle {
+    /// Records the current prices of a pool at the given timestamp.
+    fn observe<P: LiquidityPool + ?Sized>(&mut self, pool: &P, timestamp: u64);
+}
+
+/// Pool with a price oracle
+///
+/// Wraps any pool to record its prices into an [`Oracle`] after every change to its state, so
+/// that no swap, deposit or withdrawal can be missed. Changes are recorded at the timestamp of
+/// the wrapper's clock, which the caller advances with [`WithOracle::set_timestamp`], typically
+/// once per block.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WithOracle<P, O> {
+    pool: P,
+    oracle: O,
+    timestamp: u64,
+}
+
+impl<P: LiquidityPool, O: Oracle> WithOracle<P, O> {
+    /// Wraps a pool, recording its current prices at `timestamp`.
+    pub fn new(pool: P, mut oracle: O, timestamp: u64) -> Self {
+        oracle.observe(&pool, timestamp);
+        Self {
+            pool,
+            oracle,
+            timestamp,
+        }
+    }
+
+    /// Returns the oracle.
+    pub fn oracle(&self) -> &O {
+        &self.oracle
+    }
+
+    /// Returns the timestamp changes are currently recorded at.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Advances the clock, so that the following changes are recorded at `timestamp`.
+    ///
+    /// Timestamps older than the current one are ignored.
+    pub fn set_timestamp(&mut self, timestamp: u64) {
+        self.timestamp = self.timestamp.max(timestamp);
+    }
+
+    /// Returns the wrapped pool.
+    pub fn inner(&self) -> &P {
+        &self.pool
+    }
+
+    /// Unwraps the pool and its oracle.
+    pub fn into_inner(self) -> (P, O) {
+        (self.pool, self.oracle)
+    }
+
+    /// Records the prices of the pool after a change.
+    fn record(&mut self) {
+        self.oracle.observe(&self.pool, self.timestamp);
+    }
+}
+
+impl<P: LiquidityPool, O: Oracle> LiquidityPool for WithOracle<P, O> {
+    fn set_reserves(&mut self, reserves_a: Decimal, reserves_b: Decimal) {
+        self.pool.set_reserves(reserves_a, reserves_b);
+        self.record();
+    }
+
+    fn reserves(&self) -> (Decimal, Decimal) {
+        self.pool.reserves()
+    }
+
+    fn price_a(&self, amount_a: Decimal) -> Decimal {
+        self.pool.price_a(amount_a)
+    }
+
+    fn price_b(&self, amount_b: Decimal) -> Decimal {
+        self.pool.price_b(amount_b)
+    }
+
+    fn price(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.price(side, amount)
+    }
+
+    fn cost(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.cost(side, amount)
+    }
+
+    fn amount_out(&self, side: Side, amount_in: Decimal) -> Decimal {
+        self.pool.amount_out(side, amount_in)
+    }
+
+    fn spot_price(&self) -> Decimal {
+        self.pool.spot_price()
+    }
+
+    fn effective_price(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.effective_price(side, amount)
+    }
+
+    fn marginal_price_after(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.marginal_price_after(side, amount)
+    }
+
+    fn amount_to_marginal_price(&self, side: Side, price: Decimal) -> Decimal {
+        self.pool.amount_to_marginal_price(side, price)
+    }
+
+    fn price_impact(&self, side: Side, amount: Decimal) -> Decimal {
+        self.pool.price_impact(side, amount)
+    }
+
+    fn add_liquidity(&mut self, a: Decimal, b: Decimal) {
+        self.pool.add_liquidity(a, b);
+        self.record();
+    }
+
+    fn remove_liquidity(&mut self, a: Decimal, b: Decimal) {
+        self.pool.remove_liquidity(a, b);
+        self.record();
+    }
+
+    fn swap_a(&mut self, amount_a: Decimal) -> Decimal {
+        let amount_in = self.pool.swap_a(amount_a);
+        self.record();
+        amount_in
+    }
+
+    fn swap_b(&mut self, amount_b: Decimal) -> Decimal {
+        let amount_in = self.pool.swap_b(amount_b);
+        self.record();
+        amount_in
+    }
+
+    fn swap(&mut self, side: Side, amount: Decimal) -> Decimal {
+        let amount_in = self.pool.swap(side, amount);
+        self.record();
+        amount_in
+    }
+
+    fn swap_exact_in(&mut self, side: Side, amount_in: Decimal) -> Decimal {
+        let amount_out = self.pool.swap_exact_in(side, amount_in);
+        self.record();
+        amount_out
+    }
+
+    fn parameters(&self) -> Vec<(&'static str, Decimal)> {
+        self.pool.parameters()
+    }
+
+    fn set_parameter(&mut self, name: &str, value: Decimal) -> bool {
+        let changed = self.pool.set_parameter(name, value);
+        if changed {
+            self.record();
+        }
+        changed
+    }
+
+    fn pid_terms(&self) -> Option<PidTerms> {
+        self.pool.pid_terms()
+    }
+}
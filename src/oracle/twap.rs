@@ -0,0 +1,470 @@
+use super::Oracle;
+use crate::{LiquidityPool, Side};
+use alloc::vec::Vec;
+use rust_decimal::{Decimal, MathematicalOps};
+
+/// Cumulative prices recorded at a point in time.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Observation {
+    /// Caller-supplied timestamp of the observation.
+    pub timestamp: u64,
+    /// Sum of the price of token A in terms of token B, weighted by elapsed time.
+    ///
+    /// Each sum sticks at `Decimal::MAX` once it overflows, after which the averages over
+    /// windows reaching it are unavailable.
+    pub price_a_cumulative: Decimal,
+    /// Sum of the price of token B in terms of token A, weighted by elapsed time.
+    pub price_b_cumulative: Decimal,
+    /// Sum of the natural logarithm of the price of token A, weighted by elapsed time.
+    pub log_price_cumulative: Decimal,
+}
+
+/// Time-weighted average price oracle
+///
+/// The oracle accumulates the spot prices of a pool over time, in the style of Uniswap v2/v3.
+/// Each state change of the pool is recorded with a caller-supplied timestamp, either by hand or
+/// on every change by wrapping the pool in a [`WithOracle`](super::WithOracle), and the most
+/// recent observations are kept in a ring buffer whose cardinality bounds how far back the
+/// averages can reach. Because the average is weighted by the time each price was in effect,
+/// moving the price for a short moment has little effect on it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "TwapOracleState"))]
+pub struct TwapOracle {
+    observations: Vec<Observation>,
+    cardinality: usize,
+    index: usize,
+    price_a: Decimal,
+    price_b: Decimal,
+}
+
+/// The serialized fields of a [`TwapOracle`], checked before they are restored.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct TwapOracleState {
+    observations: Vec<Observation>,
+    cardinality: usize,
+    index: usize,
+    price_a: Decimal,
+    price_b: Decimal,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<TwapOracleState> for TwapOracle {
+    type Error = &'static str;
+
+    fn try_from(state: TwapOracleState) -> Result<Self, Self::Error> {
+        if state.cardinality == 0 {
+            return Err("the cardinality must be at least one");
+        }
+        if state.observations.len() > state.cardinality {
+            return Err("more observations than the cardinality");
+        }
+        if state.index >= state.observations.len().max(1) {
+            return Err("the latest observation is out of range");
+        }
+        let mut observations = state.observations;
+        observations.reserve(state.cardinality - observations.len());
+        Ok(Self {
+            observations,
+            cardinality: state.cardinality,
+            index: state.index,
+            price_a: state.price_a,
+            price_b: state.price_b,
+        })
+    }
+}
+
+impl Default for TwapOracle {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl TwapOracle {
+    /// Creates a new oracle keeping up to `cardinality` observations.
+    pub fn new(cardinality: usize) -> Self {
+        let cardinality = cardinality.max(1);
+        Self {
+            observations: Vec::with_capacity(cardinality),
+            cardinality,
+            index: 0,
+            price_a: Decimal::ZERO,
+            price_b: Decimal::ZERO,
+        }
+    }
+
+    /// Returns the maximum number of observations kept by the oracle.
+    pub fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    /// Increases the number of observations kept by the oracle.
+    ///
+    /// Existing observations are preserved. Shrinking is not supported.
+    pub fn grow(&mut self, cardinality: usize) {
+        if cardinality <= self.cardinality {
+            return;
+        }
+        if self.observations.len() == self.cardinality {
+            self.observations
+                .rotate_left((self.index + 1) % self.cardinality);
+            self.index = self.observations.len() - 1;
+        }
+        self.observations
+            .reserve(cardinality - self.observations.len());
+        self.cardinality = cardinality;
+    }
+
+    /// Returns the observations in chronological order.
+    pub fn observations(&self) -> impl Iterator<Item = &Observation> {
+        let start = if self.observations.len() == self.cardinality {
+            (self.index + 1) % self.cardinality
+        } else {
+            0
+        };
+        self.observations[start..]
+            .iter()
+            .chain(self.observations[..start].iter())
+    }
+
+    /// Returns the most recent observation, if any.
+    pub fn latest(&self) -> Option<&Observation> {
+        self.observations.get(self.index)
+    }
+
+    /// Records the current spot prices of a pool at the given timestamp.
+    pub fn observe<P: LiquidityPool + ?Sized>(&mut self, pool: &P, timestamp: u64) {
+        self.update(
+            timestamp,
+            pool.spot_price(),
+            pool.price(Side::B, Decimal::ZERO),
+        );
+    }
+
+    /// Records new spot prices taking effect at the given timestamp.
+    ///
+    /// The prices in effect since the previous update are accumulated up to `timestamp`.
+    /// Updates older than the latest observation are ignored, and several updates sharing a
+    /// timestamp only keep the last prices.
+    pub fn update(&mut self, timestamp: u64, price_a: Decimal, price_b: Decimal) {
+        match self.latest().copied() {
+            None => self.observations.push(Observation {
+                timestamp,
+                ..Observation::default()
+            }),
+            Some(latest) if timestamp < latest.timestamp => return,
+            Some(latest) if timestamp == latest.timestamp => {}
+            Some(latest) => {
+                let observation = self.accumulate(&latest, timestamp);
+                self.index = (self.index + 1) % self.cardinality;
+                if self.index == self.observations.len() {
+                    self.observations.push(observation);
+                } else {
+                    self.observations[self.index] = observation;
+                }
+            }
+        }
+        self.price_a = price_a;
+        self.price_b = price_b;
+    }
+
+    /// Computes the cumulative prices at the given timestamp.
+    ///
+    /// Returns `None` if the timestamp is older than the oldest observation.
+    pub fn cumulative_at(&self, timestamp: u64) -> Option<Observation> {
+        let latest = self.latest()?;
+        if timestamp >= latest.timestamp {
+            return Some(self.accumulate(latest, timestamp));
+        }
+        let mut before: Option<&Observation> = None;
+        for observation in self.observations() {
+            if observation.timestamp > timestamp {
+                let before = before?;
+                return Some(Self::interpolate(before, observation, timestamp));
+            }
+            before = Some(observation);
+        }
+        None
+    }
+
+    /// Computes the arithmetic mean prices of token A and token B over the `window` ending at
+    /// `now`.
+    ///
+    /// Returns `None` if the window is empty, reaches past the oldest observation, or its
+    /// cumulative prices overflowed.
+    pub fn twap(&self, now: u64, window: u64) -> Option<(Decimal, Decimal)> {
+        let (start, end) = self.window(now, window)?;
+        let elapsed = Decimal::from(window);
+        let price_a = difference(start.price_a_cumulative, end.price_a_cumulative)?;
+        let price_b = difference(start.price_b_cumulative, end.price_b_cumulative)?;
+        Some((price_a.checked_div(elapsed)?, price_b.checked_div(elapsed)?))
+    }
+
+    /// Computes the geometric mean prices of token A and token B over the `window` ending at
+    /// `now`.
+    ///
+    /// Unlike the arithmetic mean, the geometric mean of token B is exactly the inverse of the
+    /// geometric mean of token A. Returns `None` if the window is empty, reaches past the
+    /// oldest observation, or its cumulative prices overflowed.
+    pub fn geometric_twap(&self, now: u64, window: u64) -> Option<(Decimal, Decimal)> {
+        let (start, end) = self.window(now, window)?;
+        let mean_log_price = difference(start.log_price_cumulative, end.log_price_cumulative)?
+            .checked_div(Decimal::from(window))?;
+        let price_a = mean_log_price.checked_exp()?;
+        let price_b = Decimal::ONE.checked_div(price_a)?;
+        Some((price_a, price_b))
+    }
+
+    /// Returns the cumulative prices at both ends of a window.
+    fn window(&self, now: u64, window: u64) -> Option<(Observation, Observation)> {
+        if window == 0 {
+            return None;
+        }
+        let start = self.cumulative_at(now.checked_sub(window)?)?;
+        let end = self.cumulative_at(now)?;
+        Some((start, end))
+    }
+
+    /// Extends an observation with the current prices up to the given timestamp.
+    fn accumulate(&self, observation: &Observation, timestamp: u64) -> Observation {
+        let elapsed = Decimal::from(timestamp - observation.timestamp);
+        // The logarithm is undefined for an empty pool, so it contributes nothing.
+        let log_price = self.price_a.checked_ln().unwrap_or(Decimal::ZERO);
+        Observation {
+            timestamp,
+            price_a_cumulative: extend(observation.price_a_cumulative, self.price_a, elapsed),
+            price_b_cumulative: extend(observation.price_b_cumulative, self.price_b, elapsed),
+            log_price_cumulative: extend(observation.log_price_cumulative, log_price, elapsed),
+        }
+    }
+
+    /// Linearly interpolates the cumulative prices between two observations.
+    ///
+    /// Prices are constant between observations, so the interpolation is exact.
+    fn interpolate(before: &Observation, after: &Observation, timestamp: u64) -> Observation {
+        // An observation is known exactly even when the next one overflowed.
+        if timestamp == before.timestamp {
+            return *before;
+        }
+        let ratio = Decimal::from(timestamp - before.timestamp)
+            / Decimal::from(after.timestamp - before.timestamp);
+        let lerp = |from: Decimal, to: Decimal| {
+            difference(from, to)
+                .and_then(|difference| difference.checked_mul(ratio))
+                .and_then(|step| from.checked_add(step))
+                .unwrap_or(OVERFLOWED)
+        };
+        Observation {
+            timestamp,
+            price_a_cumulative: lerp(before.price_a_cumulative, after.price_a_cumulative),
+            price_b_cumulative: lerp(before.price_b_cumulative, after.price_b_cumulative),
+            log_price_cumulative: lerp(before.log_price_cumulative, after.log_price_cumulative),
+        }
+    }
+}
+
+/// The value a cumulative price sticks at once it overflows.
+const OVERFLOWED: Decimal = Decimal::MAX;
+
+/// Adds a price weighted by the elapsed time to a cumulative price, sticking at
+/// [`OVERFLOWED`] on overflow.
+fn extend(cumulative: Decimal, price: Decimal, elapsed: Decimal) -> Decimal {
+    if cumulative == OVERFLOWED {
+        return OVERFLOWED;
+    }
+    price
+        .checked_mul(elapsed)
+        .and_then(|weighted| cumulative.checked_add(weighted))
+        .unwrap_or(OVERFLOWED)
+}
+
+/// Returns the difference between two cumulative prices, or `None` if either overflowed.
+fn difference(start: Decimal, end: Decimal) -> Option<Decimal> {
+    if start == OVERFLOWED || end == OVERFLOWED {
+        return None;
+    }
+    end.checked_sub(start)
+}
+
+impl Oracle for TwapOracle {
+    fn observe<P: LiquidityPool + ?Sized>(&mut self, pool: &P, timestamp: u64) {
+        TwapOracle::observe(self, pool, timestamp);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpmm::ConstantProductMarketMaker, oracle::WithOracle};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_twap_constant_price() {
+        let mut oracle = TwapOracle::new(4);
+        oracle.update(0, dec!(2), dec!(0.5));
+
+        // A price that never changes averages to itself.
+        assert_eq!(oracle.twap(100, 50), Some((dec!(2), dec!(0.5))));
+    }
+
+    #[test]
+    fn test_twap_weights_by_time() {
+        let mut oracle = TwapOracle::new(4);
+        oracle.update(0, dec!(1), dec!(1));
+        oracle.update(30, dec!(4), dec!(0.25));
+
+        // The price was 1 for 30 seconds and 4 for 10 seconds.
+        let (price_a, price_b) = oracle.twap(40, 40).unwrap();
+        assert_eq!(price_a, dec!(1.75));
+        assert_eq!(price_b, dec!(0.8125));
+    }
+
+    #[test]
+    fn test_twap_interpolates_between_observations() {
+        let mut oracle = TwapOracle::new(4);
+        oracle.update(0, dec!(1), dec!(1));
+        oracle.update(10, dec!(3), dec!(1));
+        oracle.update(20, dec!(5), dec!(1));
+
+        // The window starts halfway through the second observation.
+        let (price_a, _) = oracle.twap(20, 5).unwrap();
+        assert_eq!(price_a, dec!(3));
+        let (price_a, _) = oracle.twap(25, 10).unwrap();
+        assert_eq!(price_a, dec!(4));
+    }
+
+    #[test]
+    fn test_geometric_twap() {
+        let mut oracle = TwapOracle::new(4);
+        oracle.update(0, dec!(1), dec!(1));
+        oracle.update(10, dec!(4), dec!(0.25));
+
+        // The geometric mean of 1 and 4 over equal durations is 2.
+        let (price_a, price_b) = oracle.geometric_twap(20, 20).unwrap();
+        assert_eq!(price_a.round_dp(6), dec!(2));
+        assert_eq!(price_b.round_dp(6), dec!(0.5));
+    }
+
+    #[test]
+    fn test_ring_buffer_limits_history() {
+        let mut oracle = TwapOracle::new(2);
+        oracle.update(0, dec!(1), dec!(1));
+        oracle.update(10, dec!(2), dec!(1));
+        oracle.update(20, dec!(3), dec!(1));
+
+        // Only the last two observations are kept.
+        let timestamps: Vec<u64> = oracle.observations().map(|o| o.timestamp).collect();
+        assert_eq!(timestamps, vec![10, 20]);
+        assert_eq!(oracle.twap(20, 15), None);
+        assert_eq!(oracle.twap(20, 10), Some((dec!(2), dec!(1))));
+    }
+
+    #[test]
+    fn test_grow_preserves_history() {
+        let mut oracle = TwapOracle::new(2);
+        oracle.update(0, dec!(1), dec!(1));
+        oracle.update(10, dec!(2), dec!(1));
+        oracle.update(20, dec!(3), dec!(1));
+        oracle.grow(4);
+        oracle.update(30, dec!(4), dec!(1));
+        oracle.update(40, dec!(5), dec!(1));
+
+        // Growing keeps the existing observations in order.
+        let timestamps: Vec<u64> = oracle.observations().map(|o| o.timestamp).collect();
+        assert_eq!(timestamps, vec![10, 20, 30, 40]);
+        assert_eq!(oracle.twap(40, 30), Some((dec!(3), dec!(1))));
+    }
+
+    #[test]
+    fn test_ignores_stale_updates() {
+        let mut oracle = TwapOracle::new(4);
+        oracle.update(10, dec!(1), dec!(1));
+        oracle.update(5, dec!(100), dec!(1));
+
+        // An update older than the latest observation is dropped.
+        assert_eq!(oracle.twap(20, 10), Some((dec!(1), dec!(1))));
+    }
+
+    #[test]
+    fn test_observe_pool() {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+        let mut oracle = TwapOracle::new(8);
+        oracle.observe(&pool, 0);
+
+        // Move the price for a short moment.
+        pool.swap_a(dec!(50));
+        oracle.observe(&pool, 90);
+        pool.swap_b(dec!(200));
+        oracle.observe(&pool, 91);
+
+        // The brief price spike barely moves the average.
+        let (price_a, _) = oracle.twap(100, 100).unwrap();
+        assert_eq!(price_a.round_dp(2), dec!(2.06));
+    }
+
+    #[test]
+    fn test_with_oracle_records_every_change() {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(100), dec!(200));
+        let mut pool = WithOracle::new(pool, TwapOracle::new(8), 0);
+
+        // Trade through the pool without feeding the oracle by hand.
+        pool.set_timestamp(90);
+        pool.swap_a(dec!(50));
+        pool.set_timestamp(91);
+        pool.swap(Side::B, dec!(200));
+
+        // Every swap was recorded, as when observing by hand.
+        let timestamps: Vec<u64> = pool.oracle().observations().map(|o| o.timestamp).collect();
+        assert_eq!(timestamps, vec![0, 90, 91]);
+        let (price_a, _) = pool.oracle().twap(100, 100).unwrap();
+        assert_eq!(price_a.round_dp(2), dec!(2.06));
+    }
+
+    #[test]
+    fn test_overflow_makes_averages_unavailable() {
+        // A price half the largest decimal over 100 seconds fills half the accumulator.
+        let price = Decimal::MAX / dec!(200);
+        let mut oracle = TwapOracle::new(4);
+        oracle.update(0, price, Decimal::ONE);
+        oracle.update(100, price, Decimal::ONE);
+        assert_eq!(oracle.twap(100, 100), Some((price, Decimal::ONE)));
+
+        // The next 200 seconds overflow it without panicking, and no average reaches past it.
+        oracle.update(300, price, Decimal::ONE);
+        assert_eq!(oracle.latest().unwrap().price_a_cumulative, Decimal::MAX);
+        assert_eq!(oracle.twap(300, 100), None);
+        assert_eq!(oracle.twap(200, 200), None);
+        assert_eq!(oracle.twap(100, 100), Some((price, Decimal::ONE)));
+
+        // Pools with such a price keep trading through the wrapper.
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.set_reserves(Decimal::ONE, price);
+        let mut pool = WithOracle::new(pool, TwapOracle::new(4), 0);
+        pool.set_timestamp(300);
+        pool.add_liquidity(Decimal::ONE, price);
+        assert_eq!(pool.oracle().twap(300, 300), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_rejects_invalid_state() {
+        let mut oracle = TwapOracle::new(2);
+        oracle.update(0, dec!(1), dec!(1));
+        oracle.update(10, dec!(2), dec!(0.5));
+        let json = serde_json::to_string(&oracle).unwrap();
+
+        // A valid oracle round-trips.
+        let restored: TwapOracle = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.twap(10, 10), oracle.twap(10, 10));
+
+        // An empty ring buffer, or an index past its end, is refused rather than panicking later.
+        let empty = json.replace("\"cardinality\":2", "\"cardinality\":0");
+        assert!(serde_json::from_str::<TwapOracle>(&empty).is_err());
+        let index = json.replace("\"index\":1", "\"index\":5");
+        assert!(serde_json::from_str::<TwapOracle>(&index).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+// Pool factory and registry
+
+use crate::{
+    batch::CloneablePool,
+    cpmm::ConstantProductMarketMaker,
+    csmm::ConstantSumMarketMaker,
+    pidmm::{PIDController, PIDMarketMaker},
+    router::{PoolId, Router},
+    LiquidityPool,
+};
+use alloc::{
+    boxed::Box,
+    collections::BTreeMap,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::fmt;
+use rust_decimal::Decimal;
+
+/// The kind of curve a pool trades along.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PoolKind {
+    /// A [`ConstantProductMarketMaker`].
+    ConstantProduct,
+    /// A [`ConstantSumMarketMaker`].
+    ConstantSum,
+    /// A [`PIDMarketMaker`].
+    Pid,
+    /// A curve defined outside of this crate, identified by name.
+    Custom(String),
+}
+
+/// The parameters needed to create a pool of a given kind.
+///
+/// Serialized configurations name their kind in a `kind` field, next to its parameters, in the
+/// same way as the agents and price processes of a scenario.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "kind", rename_all = "snake_case"))]
+pub enum PoolConfig {
+    /// Creates a [`ConstantProductMarketMaker`].
+    ConstantProduct,
+    /// Creates a [`ConstantSumMarketMaker`].
+    ConstantSum,
+    /// Creates a [`PIDMarketMaker`] with the given target and controller gains.
+    Pid {
+        target: Decimal,
+        kp: Decimal,
+        ki: Decimal,
+        kd: Decimal,
+    },
+}
+
+impl PoolConfig {
+    /// Returns the kind of pool created by this configuration.
+    pub fn kind(&self) -> PoolKind {
+        match self {
+            PoolConfig::ConstantProduct => PoolKind::ConstantProduct,
+            PoolConfig::ConstantSum => PoolKind::ConstantSum,
+            PoolConfig::Pid { .. } => PoolKind::Pid,
+        }
+    }
+
+    /// Creates an empty pool from this configuration.
+    pub fn build(&self) -> Box<dyn CloneablePool> {
+        match self {
+            PoolConfig::ConstantProduct => Box::<ConstantProductMarketMaker>::default(),
+            PoolConfig::ConstantSum => Box::<ConstantSumMarketMaker>::default(),
+            PoolConfig::Pid { target, kp, ki, kd } => {
+                let mut pool = PIDMarketMaker::default();
+                pool.target = *target;
+                pool.pid = PIDController::new(*kp, *ki, *kd);
+                Box::new(pool)
+            }
+        }
+    }
+}
+
+/// A pool registered with a [`PoolRegistry`], along with the tokens it trades.
+pub struct RegisteredPool {
+    pub token_a: String,
+    pub token_b: String,
+    pub kind: PoolKind,
+    pub pool: Box<dyn CloneablePool>,
+}
+
+/// Errors returned when registering a pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryError {
+    /// A pool of the same kind already trades the pair.
+    PoolExists(PoolId),
+    /// A pool cannot trade a token against itself.
+    IdenticalTokens,
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::PoolExists(id) => write!(f, "pool {} already trades the pair", id),
+            RegistryError::IdenticalTokens => write!(f, "a pool needs two distinct tokens"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RegistryError {}
+
+/// Pool registry
+///
+/// The registry creates pools from a kind and its parameters and keeps a single canonical pool
+/// per token pair and kind. Pairs are looked up regardless of the order of their tokens, while
+/// each pool keeps the orientation it was created with. A registry can be turned into a
+/// [`Router`] to swap across its pools, keeping the same pool identifiers.
+#[derive(Default)]
+pub struct PoolRegistry {
+    pools: Vec<RegisteredPool>,
+    index: BTreeMap<(String, String, PoolKind), PoolId>,
+}
+
+impl PoolRegistry {
+    /// Creates a new empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty pool trading `token_a` against `token_b` from the given configuration.
+    pub fn create(
+        &mut self,
+        token_a: &str,
+        token_b: &str,
+        config: &PoolConfig,
+    ) -> Result<PoolId, RegistryError> {
+        self.register(token_a, token_b, config.kind(), config.build())
+    }
+
+    /// Registers an existing pool trading `token_a` against `token_b` under a custom kind.
+    ///
+    /// The built-in kinds are reserved for pools created from a [`PoolConfig`], so that a
+    /// lookup by kind always returns a pool of that curve.
+    pub fn insert<P>(
+        &mut self,
+        token_a: &str,
+        token_b: &str,
+        name: &str,
+        pool: P,
+    ) -> Result<PoolId, RegistryError>
+    where
+        P: LiquidityPool + Clone + 'static,
+    {
+        self.insert_boxed(token_a, token_b, name, Box::new(pool))
+    }
+
+    /// Registers an existing boxed pool trading `token_a` against `token_b` under a custom kind.
+    pub fn insert_boxed(
+        &mut self,
+        token_a: &str,
+        token_b: &str,
+        name: &str,
+        pool: Box<dyn CloneablePool>,
+    ) -> Result<PoolId, RegistryError> {
+        let kind = PoolKind::Custom(name.to_string());
+        self.register(token_a, token_b, kind, pool)
+    }
+
+    /// Registers a pool under the given kind.
+    fn register(
+        &mut self,
+        token_a: &str,
+        token_b: &str,
+        kind: PoolKind,
+        pool: Box<dyn CloneablePool>,
+    ) -> Result<PoolId, RegistryError> {
+        if token_a == token_b {
+            return Err(RegistryError::IdenticalTokens);
+        }
+        let key = Self::key(token_a, token_b, kind.clone());
+        if let Some(&id) = self.index.get(&key) {
+            return Err(RegistryError::PoolExists(id));
+        }
+        let id = self.pools.len();
+        self.pools.push(RegisteredPool {
+            token_a: token_a.to_string(),
+            token_b: token_b.to_string(),
+            kind,
+            pool,
+        });
+        self.index.insert(key, id);
+        Ok(id)
+    }
+
+    /// Returns the identifier of the pool of the given kind trading the pair, if any.
+    pub fn find(&self, token_x: &str, token_y: &str, kind: PoolKind) -> Option<PoolId> {
+        self.index.get(&Self::key(token_x, token_y, kind)).copied()
+    }
+
+    /// Returns the pool of the given kind trading the pair, if any.
+    pub fn get(&self, token_x: &str, token_y: &str, kind: PoolKind) -> Option<&RegisteredPool> {
+        self.find(token_x, token_y, kind).map(|id| &self.pools[id])
+    }
+
+    /// Returns the pool of the given kind trading the pair mutably, if any.
+    pub fn get_mut(
+        &mut self,
+        token_x: &str,
+        token_y: &str,
+        kind: PoolKind,
+    ) -> Option<&mut RegisteredPool> {
+        self.find(token_x, token_y, kind)
+            .map(|id| &mut self.pools[id])
+    }
+
+    /// Returns the pool with the given identifier.
+    pub fn pool(&self, id: PoolId) -> Option<&RegisteredPool> {
+        self.pools.get(id)
+    }
+
+    /// Returns the pool with the given identifier, mutably.
+    pub fn pool_mut(&mut self, id: PoolId) -> Option<&mut RegisteredPool> {
+        self.pools.get_mut(id)
+    }
+
+    /// Iterates over the pools of every kind trading the pair.
+    pub fn pair<'a>(
+        &'a self,
+        token_x: &str,
+        token_y: &str,
+    ) -> impl Iterator<Item = &'a RegisteredPool> + 'a {
+        let (first, second) = Self::sorted(token_x, token_y);
+        let (first, second) = (first.to_string(), second.to_string());
+        self.index
+            .iter()
+            .filter(move |((a, b, _), _)| *a == first && *b == second)
+            .map(move |(_, &id)| &self.pools[id])
+    }
+
+    /// Iterates over all the pools in the order they were registered.
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredPool> {
+        self.pools.iter()
+    }
+
+    /// Iterates mutably over all the pools in the order they were registered.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut RegisteredPool> {
+        self.pools.iter_mut()
+    }
+
+    /// Returns the number of registered pools.
+    pub fn len(&self) -> usize {
+        self.pools.len()
+    }
+
+    /// Returns `true` if no pool is registered.
+    pub fn is_empty(&self) -> bool {
+        self.pools.is_empty()
+    }
+
+    /// Orders the tokens of a pair canonically.
+    fn sorted<'a>(token_x: &'a str, token_y: &'a str) -> (&'a str, &'a str) {
+        if token_x <= token_y {
+            (token_x, token_y)
+        } else {
+            (token_y, token_x)
+        }
+    }
+
+    /// Builds the canonical index key of a pair and kind.
+    fn key(token_x: &str, token_y: &str, kind: PoolKind) -> (String, String, PoolKind) {
+        let (first, second) = Self::sorted(token_x, token_y);
+        (first.to_string(), second.to_string(), kind)
+    }
+}
+
+impl From<PoolRegistry> for Router {
+    fn from(registry: PoolRegistry) -> Self {
+        let mut router = Router::new();
+        for registered in registry.pools {
+            router.add_boxed_pool(registered.token_a, registered.token_b, registered.pool);
+        }
+        router
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_create_and_lookup() {
+        let mut registry = PoolRegistry::new();
+        let cpmm = registry
+            .create("$UGAR", "CUB$", &PoolConfig::ConstantProduct)
+            .unwrap();
+        let pid = PoolConfig::Pid {
+            target: dec!(2),
+            kp: dec!(0.1),
+            ki: dec!(0),
+            kd: dec!(0),
+        };
+        let pidmm = registry.create("$UGAR", "CUB$", &pid).unwrap();
+
+        // Pools are found regardless of the order of the tokens.
+        assert_eq!(
+            registry.find("CUB$", "$UGAR", PoolKind::ConstantProduct),
+            Some(cpmm)
+        );
+        assert_eq!(registry.find("$UGAR", "CUB$", PoolKind::Pid), Some(pidmm));
+        assert_eq!(registry.find("$UGAR", "CUB$", PoolKind::ConstantSum), None);
+
+        // The pool keeps the orientation it was created with.
+        let registered = registry.get("CUB$", "$UGAR", PoolKind::Pid).unwrap();
+        assert_eq!(registered.token_a, "$UGAR");
+        assert_eq!(registry.pair("CUB$", "$UGAR").count(), 2);
+    }
+
+    #[test]
+    fn test_one_pool_per_pair_and_kind() {
+        let mut registry = PoolRegistry::new();
+        let id = registry
+            .create("$UGAR", "CUB$", &PoolConfig::ConstantProduct)
+            .unwrap();
+
+        // A second pool of the same kind for the pair is rejected.
+        assert_eq!(
+            registry.create("CUB$", "$UGAR", &PoolConfig::ConstantProduct),
+            Err(RegistryError::PoolExists(id))
+        );
+        assert_eq!(
+            registry.create("$UGAR", "$UGAR", &PoolConfig::ConstantSum),
+            Err(RegistryError::IdenticalTokens)
+        );
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn test_custom_pool() {
+        let mut registry = PoolRegistry::new();
+        let kind = PoolKind::Custom("curve".to_string());
+        let id = registry
+            .insert("GEM", "CUB$", "curve", ConstantSumMarketMaker::default())
+            .unwrap();
+
+        // Inserted pools are registered under their custom kind, apart from the built-in ones.
+        assert_eq!(registry.pool(id).unwrap().kind, kind);
+        assert!(registry.get("GEM", "CUB$", kind).is_some());
+        assert!(registry.get("GEM", "CUB$", PoolKind::ConstantSum).is_none());
+
+        // A built-in kind can still be created for the pair, but a name is only taken once.
+        let sum = registry
+            .create("GEM", "CUB$", &PoolConfig::ConstantSum)
+            .unwrap();
+        assert_eq!(registry.pool(sum).unwrap().kind, PoolKind::ConstantSum);
+        assert_eq!(
+            registry.insert(
+                "CUB$",
+                "GEM",
+                "curve",
+                ConstantProductMarketMaker::default()
+            ),
+            Err(RegistryError::PoolExists(id))
+        );
+    }
+
+    #[test]
+    fn test_into_router() {
+        let mut registry = PoolRegistry::new();
+        let config = PoolConfig::ConstantProduct;
+        registry.create("$UGAR", "CUB$", &config).unwrap();
+        registry.create("CUB$", "GEM", &config).unwrap();
+        for registered in registry.iter_mut() {
+            registered.pool.add_liquidity(dec!(1000), dec!(1000));
+        }
+
+        // The router swaps across the registered pools.
+        let mut router = Router::from(registry);
+        let quote = router.swap("$UGAR", "GEM", dec!(10), dec!(0), 2).unwrap();
+        assert_eq!(quote.path.len(), 2);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_config_serde() {
+        let config = PoolConfig::Pid {
+            target: dec!(2),
+            kp: dec!(0.1),
+            ki: dec!(0.01),
+            kd: dec!(0),
+        };
+
+        // Configurations are tagged with their kind.
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(json.starts_with(r#"{"kind":"pid","#));
+        let cpmm: PoolConfig = serde_json::from_str(r#"{"kind":"constant_product"}"#).unwrap();
+        assert_eq!(cpmm, PoolConfig::ConstantProduct);
+
+        // Configurations round-trip and build identical pools.
+        let restored: PoolConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, config);
+        let mut pool = restored.build();
+        pool.set_reserves(dec!(100), dec!(200));
+        let mut original = config.build();
+        original.set_reserves(dec!(100), dec!(200));
+        assert_eq!(pool.price_a(dec!(10)), original.price_a(dec!(10)));
+    }
+}
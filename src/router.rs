@@ -0,0 +1,741 @@
+// Multi-hop swap routing
+
+use crate::{batch::CloneablePool, LiquidityPool, Side};
+use alloc::{boxed::Box, string::String, vec, vec::Vec};
+use core::fmt;
+use rust_decimal::Decimal;
+
+/// Identifies a pool registered with a [`Router`].
+pub type PoolId = usize;
+
+/// Number of increments an order is divided into when splitting it across routes.
+pub const SPLIT_PARTS: usize = 100;
+
+/// A pool registered with a router, along with the tokens it trades.
+pub struct RoutedPool {
+    pub token_a: String,
+    pub token_b: String,
+    pub pool: Box<dyn CloneablePool>,
+}
+
+/// A single swap along a path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hop {
+    /// The pool traded against.
+    pub pool: PoolId,
+    /// The side of the pool taken out by the swap.
+    pub side: Side,
+}
+
+/// A quote for swapping along a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quote {
+    /// The swaps making up the path, in order.
+    pub path: Vec<Hop>,
+    /// The amounts flowing through the path, starting with the input and ending with the
+    /// output.
+    pub amounts: Vec<Decimal>,
+}
+
+impl Quote {
+    /// Returns the amount paid into the first pool.
+    pub fn amount_in(&self) -> Decimal {
+        self.amounts.first().copied().unwrap_or(Decimal::ZERO)
+    }
+
+    /// Returns the amount taken out of the last pool.
+    pub fn amount_out(&self) -> Decimal {
+        self.amounts.last().copied().unwrap_or(Decimal::ZERO)
+    }
+}
+
+/// A quote for an order split across several routes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitQuote {
+    /// The quote of each route for the amount allocated to it.
+    pub quotes: Vec<Quote>,
+}
+
+impl SplitQuote {
+    /// Returns the total amount paid into the routes.
+    pub fn amount_in(&self) -> Decimal {
+        self.quotes.iter().map(Quote::amount_in).sum()
+    }
+
+    /// Returns the total amount taken out of the routes.
+    pub fn amount_out(&self) -> Decimal {
+        self.quotes.iter().map(Quote::amount_out).sum()
+    }
+
+    /// Returns the amount allocated to each route.
+    pub fn allocation(&self) -> Vec<Decimal> {
+        self.quotes.iter().map(Quote::amount_in).collect()
+    }
+}
+
+/// Errors returned when routing a swap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouterError {
+    /// No path connects the two tokens.
+    NoPath,
+    /// The path refers to an unknown pool, trades against a pool twice or does not chain tokens
+    /// correctly.
+    InvalidPath,
+    /// The pool of the hop at the given index in the path could not fill its swap.
+    Unfilled(usize),
+    /// The swap would return less than the minimum amount requested.
+    InsufficientOutput {
+        amount_out: Decimal,
+        min_amount_out: Decimal,
+    },
+}
+
+impl fmt::Display for RouterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouterError::NoPath => write!(f, "no path between the tokens"),
+            RouterError::InvalidPath => write!(f, "invalid path"),
+            RouterError::Unfilled(hop) => write!(f, "hop {} could not be filled", hop),
+            RouterError::InsufficientOutput {
+                amount_out,
+                min_amount_out,
+            } => write!(
+                f,
+                "insufficient output: {} is less than the minimum of {}",
+                amount_out, min_amount_out
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RouterError {}
+
+/// Swap router
+///
+/// The router holds pools of any kind behind the [`CloneablePool`] trait, each trading a pair
+/// of named tokens. It finds the paths connecting two tokens through the pools, quotes them hop
+/// by hop with exact input amounts, and executes the best one. Swaps are staged on copies of the
+/// pools, each hop paying in what the previous one actually returned, and only committed once
+/// the final output meets the minimum, so a swap either executes every hop or none of them.
+#[derive(Default)]
+pub struct Router {
+    pools: Vec<RoutedPool>,
+}
+
+impl Router {
+    /// Creates a new router without pools.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a pool trading `token_a` against `token_b`.
+    ///
+    /// Returns the identifier of the pool.
+    pub fn add_pool<P>(
+        &mut self,
+        token_a: impl Into<String>,
+        token_b: impl Into<String>,
+        pool: P,
+    ) -> PoolId
+    where
+        P: LiquidityPool + Clone + 'static,
+    {
+        self.add_boxed_pool(token_a, token_b, Box::new(pool))
+    }
+
+    /// Registers a boxed pool trading `token_a` against `token_b`.
+    ///
+    /// Returns the identifier of the pool.
+    pub fn add_boxed_pool(
+        &mut self,
+        token_a: impl Into<String>,
+        token_b: impl Into<String>,
+        pool: Box<dyn CloneablePool>,
+    ) -> PoolId {
+        self.pools.push(RoutedPool {
+            token_a: token_a.into(),
+            token_b: token_b.into(),
+            pool,
+        });
+        self.pools.len() - 1
+    }
+
+    /// Returns the registered pool with the given identifier.
+    pub fn pool(&self, id: PoolId) -> Option<&RoutedPool> {
+        self.pools.get(id)
+    }
+
+    /// Returns the registered pool with the given identifier, mutably.
+    pub fn pool_mut(&mut self, id: PoolId) -> Option<&mut RoutedPool> {
+        self.pools.get_mut(id)
+    }
+
+    /// Returns all the registered pools.
+    pub fn pools(&self) -> &[RoutedPool] {
+        &self.pools
+    }
+
+    /// Finds every path of at most `max_hops` swaps from `token_in` to `token_out`.
+    ///
+    /// Paths never visit a token twice, except for cycles returning to `token_in` when it is also
+    /// `token_out`.
+    pub fn find_paths(&self, token_in: &str, token_out: &str, max_hops: usize) -> Vec<Vec<Hop>> {
+        let mut paths = Vec::new();
+        let mut path = Vec::new();
+        let mut visited = vec![token_in];
+        self.search(token_out, max_hops, &mut visited, &mut path, &mut paths);
+        paths
+    }
+
+    /// Extends the current path depth-first, collecting the paths reaching `token_out`.
+    fn search<'a>(
+        &'a self,
+        token_out: &str,
+        max_hops: usize,
+        visited: &mut Vec<&'a str>,
+        path: &mut Vec<Hop>,
+        paths: &mut Vec<Vec<Hop>>,
+    ) {
+        let token = visited[visited.len() - 1];
+        if token == token_out && !path.is_empty() {
+            paths.push(path.clone());
+            return;
+        }
+        if path.len() == max_hops {
+            return;
+        }
+        for (id, routed) in self.pools.iter().enumerate() {
+            let (side, next) = if routed.token_b == token {
+                (Side::A, routed.token_a.as_str())
+            } else if routed.token_a == token {
+                (Side::B, routed.token_b.as_str())
+            } else {
+                continue;
+            };
+            if path.iter().any(|hop| hop.pool == id)
+                || (visited.contains(&next) && next != token_out)
+            {
+                continue;
+            }
+            visited.push(next);
+            path.push(Hop { pool: id, side });
+            self.search(token_out, max_hops, visited, path, paths);
+            path.pop();
+            visited.pop();
+        }
+    }
+
+    /// Quotes swapping `amount_in` along a path, hop by hop.
+    ///
+    /// A path may only trade against each pool once, so that every hop is quoted against the
+    /// state it will execute on.
+    pub fn quote(&self, path: &[Hop], amount_in: Decimal) -> Result<Quote, RouterError> {
+        if path.is_empty() {
+            return Err(RouterError::InvalidPath);
+        }
+        for (i, hop) in path.iter().enumerate() {
+            if path[..i].iter().any(|other| other.pool == hop.pool) {
+                return Err(RouterError::InvalidPath);
+            }
+        }
+        let mut amounts = vec![amount_in];
+        let mut token = None;
+        for hop in path {
+            let routed = self.pools.get(hop.pool).ok_or(RouterError::InvalidPath)?;
+            let (token_in, token_out) = match hop.side {
+                Side::A => (&routed.token_b, &routed.token_a),
+                Side::B => (&routed.token_a, &routed.token_b),
+            };
+            if token.is_some_and(|token| token != token_in) {
+                return Err(RouterError::InvalidPath);
+            }
+            token = Some(token_out);
+            let amount = amounts[amounts.len() - 1];
+            amounts.push(routed.pool.amount_out(hop.side, amount));
+        }
+        Ok(Quote {
+            path: path.to_vec(),
+            amounts,
+        })
+    }
+
+    /// Quotes every path of at most `max_hops` swaps and returns the one with the largest
+    /// output.
+    pub fn best_quote(
+        &self,
+        token_in: &str,
+        token_out: &str,
+        amount_in: Decimal,
+        max_hops: usize,
+    ) -> Result<Quote, RouterError> {
+        self.find_paths(token_in, token_out, max_hops)
+            .iter()
+            .filter_map(|path| self.quote(path, amount_in).ok())
+            .max_by_key(|quote| quote.amount_out())
+            .ok_or(RouterError::NoPath)
+    }
+
+    /// Swaps `amount_in` of `token_in` for `token_out` along the best path of at most
+    /// `max_hops` swaps.
+    ///
+    /// Returns the amounts actually swapped. Fails without touching any pool if a hop cannot be
+    /// filled or the output would be less than `min_amount_out`.
+    pub fn swap(
+        &mut self,
+        token_in: &str,
+        token_out: &str,
+        amount_in: Decimal,
+        min_amount_out: Decimal,
+        max_hops: usize,
+    ) -> Result<Quote, RouterError> {
+        let quote = self.best_quote(token_in, token_out, amount_in, max_hops)?;
+        self.execute(quote, min_amount_out)
+    }
+
+    /// Swaps `amount_in` along the given path.
+    ///
+    /// Returns the amounts actually swapped. Fails without touching any pool if a hop cannot be
+    /// filled or the output would be less than `min_amount_out`.
+    pub fn swap_path(
+        &mut self,
+        path: &[Hop],
+        amount_in: Decimal,
+        min_amount_out: Decimal,
+    ) -> Result<Quote, RouterError> {
+        let quote = self.quote(path, amount_in)?;
+        self.execute(quote, min_amount_out)
+    }
+
+    /// Quotes splitting `amount_in` across routes to maximize the total output.
+    ///
+    /// The order is divided into `parts` increments, each allocated to the route whose output
+    /// grows the most from it, the last one taking whatever rounding left over. Since the output of a route grows ever more slowly with its input,
+    /// this equalizes the marginal prices across routes. Routes must not share pools, so that
+    /// each can be quoted on its own.
+    pub fn quote_split(
+        &self,
+        paths: &[Vec<Hop>],
+        amount_in: Decimal,
+        parts: usize,
+    ) -> Result<SplitQuote, RouterError> {
+        if paths.is_empty() {
+            return Err(RouterError::NoPath);
+        }
+        let pools: Vec<PoolId> = paths.iter().flatten().map(|hop| hop.pool).collect();
+        for (i, pool) in pools.iter().enumerate() {
+            if pools[..i].contains(pool) {
+                return Err(RouterError::InvalidPath);
+            }
+        }
+        let mut quotes = paths
+            .iter()
+            .map(|path| self.quote(path, Decimal::ZERO))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parts = parts.max(1);
+        let mut allocated = Decimal::ZERO;
+        for part in 1..=parts {
+            let increment = if part == parts {
+                amount_in - allocated
+            } else {
+                amount_in / Decimal::from(parts)
+            };
+            allocated += increment;
+            let mut best: Option<(usize, Decimal, Quote)> = None;
+            for (i, quote) in quotes.iter().enumerate() {
+                let next = self.quote(&quote.path, quote.amount_in() + increment)?;
+                let gain = next.amount_out() - quote.amount_out();
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, best_gain, _)| gain > *best_gain)
+                {
+                    best = Some((i, gain, next));
+                }
+            }
+            if let Some((i, _, next)) = best {
+                quotes[i] = next;
+            }
+        }
+        Ok(SplitQuote { quotes })
+    }
+
+    /// Quotes splitting `amount_in` of `token_in` for `token_out` across the paths of at most
+    /// `max_hops` swaps.
+    ///
+    /// Candidate routes are taken from the best single-route quotes, skipping routes sharing a
+    /// pool with a better one.
+    pub fn best_split(
+        &self,
+        token_in: &str,
+        token_out: &str,
+        amount_in: Decimal,
+        max_hops: usize,
+    ) -> Result<SplitQuote, RouterError> {
+        let mut quotes: Vec<Quote> = self
+            .find_paths(token_in, token_out, max_hops)
+            .iter()
+            .filter_map(|path| self.quote(path, amount_in).ok())
+            .collect();
+        quotes.sort_by_key(|quote| core::cmp::Reverse(quote.amount_out()));
+        let mut paths: Vec<Vec<Hop>> = Vec::new();
+        for quote in quotes {
+            let shares_pool = quote
+                .path
+                .iter()
+                .any(|hop| paths.iter().flatten().any(|other| other.pool == hop.pool));
+            if !shares_pool {
+                paths.push(quote.path);
+            }
+        }
+        self.quote_split(&paths, amount_in, SPLIT_PARTS)
+    }
+
+    /// Swaps `amount_in` of `token_in` for `token_out`, split across the paths of at most
+    /// `max_hops` swaps.
+    ///
+    /// Returns the amounts actually swapped on each route. Fails without touching any pool if a
+    /// hop cannot be filled or the total output would be less than `min_amount_out`.
+    pub fn swap_split(
+        &mut self,
+        token_in: &str,
+        token_out: &str,
+        amount_in: Decimal,
+        min_amount_out: Decimal,
+        max_hops: usize,
+    ) -> Result<SplitQuote, RouterError> {
+        let split = self.best_split(token_in, token_out, amount_in, max_hops)?;
+        check_output(split.amount_out(), min_amount_out)?;
+        let mut staged = Vec::new();
+        let mut quotes = Vec::with_capacity(split.quotes.len());
+        for quote in &split.quotes {
+            if quote.amount_out() > Decimal::ZERO {
+                quotes.push(self.stage(quote, &mut staged)?);
+            }
+        }
+        let split = SplitQuote { quotes };
+        check_output(split.amount_out(), min_amount_out)?;
+        self.commit(staged);
+        Ok(split)
+    }
+
+    /// Executes a quote, committing its swaps only if the output meets the minimum.
+    fn execute(&mut self, quote: Quote, min_amount_out: Decimal) -> Result<Quote, RouterError> {
+        check_output(quote.amount_out(), min_amount_out)?;
+        let mut staged = Vec::new();
+        let executed = self.stage(&quote, &mut staged)?;
+        check_output(executed.amount_out(), min_amount_out)?;
+        self.commit(staged);
+        Ok(executed)
+    }
+
+    /// Swaps along the path of a quote on copies of its pools, feeding each hop the amount the
+    /// previous one returned.
+    ///
+    /// Returns the amounts actually swapped, and adds the copies to `staged`.
+    fn stage(
+        &self,
+        quote: &Quote,
+        staged: &mut Vec<(PoolId, Box<dyn CloneablePool>)>,
+    ) -> Result<Quote, RouterError> {
+        let mut amounts = vec![quote.amount_in()];
+        for (index, hop) in quote.path.iter().enumerate() {
+            let routed = self.pools.get(hop.pool).ok_or(RouterError::InvalidPath)?;
+            let mut pool = routed.pool.clone();
+            let amount_out = pool.swap_exact_in(hop.side, amounts[amounts.len() - 1]);
+            if amount_out <= Decimal::ZERO {
+                return Err(RouterError::Unfilled(index));
+            }
+            amounts.push(amount_out);
+            staged.push((hop.pool, pool));
+        }
+        Ok(Quote {
+            path: quote.path.clone(),
+            amounts,
+        })
+    }
+
+    /// Replaces the pools with their staged copies.
+    fn commit(&mut self, staged: Vec<(PoolId, Box<dyn CloneablePool>)>) {
+        for (id, pool) in staged {
+            self.pools[id].pool = pool;
+        }
+    }
+}
+
+/// Checks an output against the minimum requested.
+fn check_output(amount_out: Decimal, min_amount_out: Decimal) -> Result<(), RouterError> {
+    if amount_out <= Decimal::ZERO || amount_out < min_amount_out {
+        return Err(RouterError::InsufficientOutput {
+            amount_out,
+            min_amount_out,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpmm::ConstantProductMarketMaker,
+        events::{Observed, Observer, PendingSwap},
+        pidmm::PIDMarketMaker,
+    };
+    use rust_decimal_macros::dec;
+
+    fn cpmm(reserves_a: Decimal, reserves_b: Decimal) -> ConstantProductMarketMaker {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(reserves_a, reserves_b);
+        pool
+    }
+
+    #[test]
+    fn test_find_paths() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(200)));
+        router.add_pool("CUB$", "GEM", cpmm(dec!(100), dec!(100)));
+        router.add_pool("$UGAR", "GEM", cpmm(dec!(100), dec!(100)));
+
+        // There is a direct path and a path through CUB$.
+        let paths = router.find_paths("$UGAR", "GEM", 2);
+        assert_eq!(paths.len(), 2);
+        assert!(paths.contains(&vec![Hop {
+            pool: 2,
+            side: Side::B
+        }]));
+        assert!(paths.contains(&vec![
+            Hop {
+                pool: 0,
+                side: Side::B
+            },
+            Hop {
+                pool: 1,
+                side: Side::B
+            },
+        ]));
+
+        // Limiting the number of hops drops the longer path.
+        assert_eq!(router.find_paths("$UGAR", "GEM", 1).len(), 1);
+    }
+
+    #[test]
+    fn test_multi_hop_swap() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(200)));
+        let mut pidmm = PIDMarketMaker::default();
+        pidmm.set_reserves(dec!(1000), dec!(1000));
+        router.add_pool("GEM", "CUB$", pidmm);
+
+        // Quote each hop: 10 $UGAR buy CUB$, which in turn buy GEM.
+        let quote = router.best_quote("$UGAR", "GEM", dec!(10), 3).unwrap();
+        assert_eq!(quote.amounts.len(), 3);
+        assert_eq!(quote.amounts[1].round_dp(2), dec!(18.18));
+        let amount_out = quote.amount_out();
+
+        // Execute the swap and check the pools moved accordingly.
+        let executed = router
+            .swap("$UGAR", "GEM", dec!(10), amount_out, 3)
+            .unwrap();
+        assert_eq!(executed, quote);
+        assert_eq!(
+            router.pool(0).unwrap().pool.reserves_rounded(),
+            (dec!(110), dec!(181.82))
+        );
+
+        // The amounts returned are the ones each hop actually swapped.
+        assert_eq!(
+            router.pool(0).unwrap().pool.reserves().1,
+            dec!(200) - executed.amounts[1]
+        );
+        assert_eq!(
+            router.pool(1).unwrap().pool.reserves().0,
+            dec!(1000) - executed.amount_out()
+        );
+    }
+
+    #[test]
+    fn test_min_amount_out() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(200)));
+        router.add_pool("CUB$", "GEM", cpmm(dec!(100), dec!(100)));
+
+        // Asking for more than the path can return leaves the pools untouched.
+        let error = router
+            .swap("$UGAR", "GEM", dec!(10), dec!(100), 2)
+            .unwrap_err();
+        assert!(matches!(error, RouterError::InsufficientOutput { .. }));
+        assert_eq!(
+            router.pool(0).unwrap().pool.reserves(),
+            (dec!(100), dec!(200))
+        );
+        assert_eq!(
+            router.pool(1).unwrap().pool.reserves(),
+            (dec!(100), dec!(100))
+        );
+    }
+
+    #[test]
+    fn test_unfilled_hop_rolls_back() {
+        /// Quotes normally but refuses every swap.
+        #[derive(Clone)]
+        struct Frozen;
+
+        impl Observer for Frozen {
+            fn before_swap(&mut self, _swap: &PendingSwap) -> bool {
+                false
+            }
+        }
+
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(200)));
+        let mut frozen = Observed::new(cpmm(dec!(100), dec!(100)));
+        frozen.observe(Frozen);
+        router.add_pool("CUB$", "GEM", frozen);
+        router.add_pool("GEM", "ORE", cpmm(dec!(100), dec!(100)));
+
+        // The middle hop quotes an output, so the path passes the minimum before executing.
+        let quote = router.best_quote("$UGAR", "ORE", dec!(10), 3).unwrap();
+        assert!(quote.amount_out() > Decimal::ZERO);
+
+        // It cannot fill, so the first hop is rolled back along with it.
+        let error = router
+            .swap("$UGAR", "ORE", dec!(10), Decimal::ZERO, 3)
+            .unwrap_err();
+        assert_eq!(error, RouterError::Unfilled(1));
+        assert_eq!(
+            router.pool(0).unwrap().pool.reserves(),
+            (dec!(100), dec!(200))
+        );
+        assert_eq!(
+            router.pool(1).unwrap().pool.reserves(),
+            (dec!(100), dec!(100))
+        );
+        assert_eq!(
+            router.pool(2).unwrap().pool.reserves(),
+            (dec!(100), dec!(100))
+        );
+    }
+
+    #[test]
+    fn test_best_quote() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "GEM", cpmm(dec!(10), dec!(10)));
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(1000), dec!(1000)));
+        router.add_pool("CUB$", "GEM", cpmm(dec!(1000), dec!(1000)));
+
+        // The deeper two-hop path beats the shallow direct pool.
+        let quote = router.best_quote("$UGAR", "GEM", dec!(5), 2).unwrap();
+        assert_eq!(quote.path.len(), 2);
+    }
+
+    #[test]
+    fn test_no_path() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(200)));
+
+        // Unknown tokens cannot be reached.
+        assert_eq!(
+            router.best_quote("$UGAR", "GEM", dec!(1), 3),
+            Err(RouterError::NoPath)
+        );
+
+        // Hops must chain tokens.
+        let path = [
+            Hop {
+                pool: 0,
+                side: Side::B,
+            },
+            Hop {
+                pool: 0,
+                side: Side::B,
+            },
+        ];
+        assert_eq!(router.quote(&path, dec!(1)), Err(RouterError::InvalidPath));
+    }
+
+    #[test]
+    fn test_split_equal_pools() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(100)));
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(100)));
+
+        // Identical pools share the order evenly.
+        let split = router.best_split("CUB$", "$UGAR", dec!(50), 1).unwrap();
+        assert_eq!(split.allocation(), vec![dec!(25), dec!(25)]);
+        assert_eq!(split.amount_in(), dec!(50));
+        assert_eq!(split.amount_out(), dec!(40));
+    }
+
+    #[test]
+    fn test_split_allocates_whole_order() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(100)));
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(100)));
+        let paths: Vec<Vec<Hop>> = (0..2)
+            .map(|pool| {
+                vec![Hop {
+                    pool,
+                    side: Side::A,
+                }]
+            })
+            .collect();
+
+        // Increments that do not divide the order exactly leave the remainder to the last one.
+        let split = router.quote_split(&paths, dec!(10), 3).unwrap();
+        assert_eq!(split.allocation().iter().sum::<Decimal>(), dec!(10));
+        let amount_in = dec!(1.0000000000000000000000000001);
+        let split = router.best_split("CUB$", "$UGAR", amount_in, 1).unwrap();
+        assert_eq!(split.allocation().iter().sum::<Decimal>(), amount_in);
+    }
+
+    #[test]
+    fn test_split_beats_single_route() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(200), dec!(200)));
+        let mut pidmm = PIDMarketMaker::default();
+        pidmm.set_reserves(dec!(300), dec!(300));
+        router.add_pool("$UGAR", "CUB$", pidmm);
+
+        // Splitting the order returns more than the best single route.
+        let single = router.best_quote("CUB$", "$UGAR", dec!(100), 1).unwrap();
+        let split = router.best_split("CUB$", "$UGAR", dec!(100), 1).unwrap();
+        assert_eq!(split.amount_in(), dec!(100));
+        assert!(split.amount_out() > single.amount_out());
+
+        // The marginal prices of both routes end up close to each other.
+        let marginal_prices: Vec<Decimal> = split
+            .quotes
+            .iter()
+            .map(|quote| {
+                let pool = &router.pool(quote.path[0].pool).unwrap().pool;
+                pool.marginal_price_after(Side::A, quote.amount_out())
+            })
+            .collect();
+        let spread = (marginal_prices[0] - marginal_prices[1]).abs();
+        assert!(spread < dec!(0.05));
+
+        // Executing the split moves both pools.
+        let executed = router
+            .swap_split("CUB$", "$UGAR", dec!(100), split.amount_out(), 1)
+            .unwrap();
+        assert_eq!(executed, split);
+        assert!(router.pool(0).unwrap().pool.reserves().0 < dec!(200));
+        assert!(router.pool(1).unwrap().pool.reserves().0 < dec!(300));
+    }
+
+    #[test]
+    fn test_split_rejects_shared_pools() {
+        let mut router = Router::new();
+        router.add_pool("$UGAR", "CUB$", cpmm(dec!(100), dec!(100)));
+        let path = vec![Hop {
+            pool: 0,
+            side: Side::A,
+        }];
+
+        // The same pool cannot be quoted twice.
+        assert_eq!(
+            router.quote_split(&[path.clone(), path], dec!(10), 10),
+            Err(RouterError::InvalidPath)
+        );
+    }
+}
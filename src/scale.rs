@@ -0,0 +1,228 @@
+// SCALE encoding of pools and controller state
+
+use crate::fixed::Fixed;
+use codec::{Decode, Encode, Error, Input, Output};
+use rust_decimal::Decimal;
+use scale_info::TypeInfo;
+
+/// A field of a type encoded with [`scale_codec!`].
+///
+/// `Decimal` does not implement the SCALE traits, so it is encoded as its 16 byte
+/// representation, while every other field, [`Fixed`] included, uses its own encoding.
+pub(crate) trait Field: Sized {
+    /// The type the field is described as in the type registry.
+    type Repr: TypeInfo + 'static;
+
+    /// Encodes the field.
+    fn encode_field<W: Output + ?Sized>(&self, dest: &mut W);
+
+    /// Decodes the field.
+    fn decode_field<I: Input>(input: &mut I) -> Result<Self, Error>;
+}
+
+impl Field for Decimal {
+    type Repr = [u8; 16];
+
+    fn encode_field<W: Output + ?Sized>(&self, dest: &mut W) {
+        dest.write(&self.serialize());
+    }
+
+    fn decode_field<I: Input>(input: &mut I) -> Result<Self, Error> {
+        let bytes = <[u8; 16]>::decode(input)?;
+        // Byte 2 holds the scale, which cannot exceed 28.
+        if bytes[2] > 28 {
+            return Err("Decimal scale out of range".into());
+        }
+        Ok(Decimal::deserialize(bytes))
+    }
+}
+
+impl<T: Field> Field for Option<T> {
+    type Repr = Option<T::Repr>;
+
+    fn encode_field<W: Output + ?Sized>(&self, dest: &mut W) {
+        match self {
+            None => dest.push_byte(0),
+            Some(value) => {
+                dest.push_byte(1);
+                value.encode_field(dest);
+            }
+        }
+    }
+
+    fn decode_field<I: Input>(input: &mut I) -> Result<Self, Error> {
+        match input.read_byte()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_field(input)?)),
+            _ => Err("Invalid Option discriminant".into()),
+        }
+    }
+}
+
+macro_rules! encoded_field {
+    ($($ty:ty),*) => {
+        $(
+            impl Field for $ty {
+                type Repr = Self;
+
+                fn encode_field<W: Output + ?Sized>(&self, dest: &mut W) {
+                    Encode::encode_to(self, dest)
+                }
+
+                fn decode_field<I: Input>(input: &mut I) -> Result<Self, Error> {
+                    Decode::decode(input)
+                }
+            }
+        )*
+    };
+}
+
+encoded_field!(bool, u64, Fixed);
+
+/// Implements `Encode`, `Decode` and `TypeInfo` for a struct, field by field.
+///
+/// Invoked next to the struct so that private fields can be reached. Fields are encoded in the
+/// order listed, which must cover every field of the struct. A struct generic over its
+/// [`Numeric`](crate::Numeric) backend names the type parameter after the struct.
+macro_rules! scale_codec {
+    ($name:ident $(<$param:ident>)? { $($field:ident: $ty:ty),* $(,)? }) => {
+        impl$(<$param>)? codec::Encode for $name$(<$param>)?
+        where
+            $($param: crate::Numeric + crate::scale::Field,)?
+        {
+            fn encode_to<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+                $(crate::scale::Field::encode_field(&self.$field, dest);)*
+            }
+        }
+
+        impl$(<$param>)? codec::Decode for $name$(<$param>)?
+        where
+            $($param: crate::Numeric + crate::scale::Field,)?
+        {
+            fn decode<I: codec::Input>(input: &mut I) -> Result<Self, codec::Error> {
+                Ok(Self {
+                    $($field: <$ty as crate::scale::Field>::decode_field(input)?,)*
+                })
+            }
+        }
+
+        impl$(<$param>)? scale_info::TypeInfo for $name$(<$param>)?
+        where
+            $($param: crate::Numeric + crate::scale::Field,)?
+        {
+            type Identity = Self;
+
+            fn type_info() -> scale_info::Type {
+                scale_info::Type::builder()
+                    .path(scale_info::Path::new(stringify!($name), module_path!()))
+                    .type_params(alloc::vec![$(scale_info::TypeParameter::new(
+                        stringify!($param),
+                        Some(scale_info::meta_type::<<$param as crate::scale::Field>::Repr>()),
+                    ))?])
+                    .composite(scale_info::build::Fields::named()$(.field(|f| {
+                        f.ty::<<$ty as crate::scale::Field>::Repr>()
+                            .name(stringify!($field))
+                            .type_name(stringify!($ty))
+                    }))*)
+            }
+        }
+
+        impl$(<$param>)? crate::scale::Field for $name$(<$param>)?
+        where
+            $($param: crate::Numeric + crate::scale::Field,)?
+        {
+            type Repr = Self;
+
+            fn encode_field<W: codec::Output + ?Sized>(&self, dest: &mut W) {
+                codec::Encode::encode_to(self, dest)
+            }
+
+            fn decode_field<I: codec::Input>(input: &mut I) -> Result<Self, codec::Error> {
+                codec::Decode::decode(input)
+            }
+        }
+    };
+}
+
+pub(crate) use scale_codec;
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        cpmm::ConstantProductMarketMaker, csmm::ConstantSumMarketMaker, ema::EmaOracle,
+        fixed::Fixed, pidmm::PIDMarketMaker, LiquidityPool, Numeric, Side,
+    };
+    use codec::{Decode, Encode};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use scale_info::{meta_type, Registry};
+
+    #[test]
+    fn test_constant_pools_round_trip() {
+        let mut cpmm = ConstantProductMarketMaker::default();
+        cpmm.add_liquidity(dec!(100.5), dec!(200));
+        let mut csmm = ConstantSumMarketMaker::default();
+        csmm.add_liquidity(dec!(10), dec!(20));
+
+        // Each reserve takes 16 bytes and decodes to the same quotes.
+        let encoded = cpmm.encode();
+        assert_eq!(encoded.len(), 32);
+        let decoded = ConstantProductMarketMaker::decode(&mut &encoded[..]).unwrap();
+        assert_eq!(decoded.price_a(dec!(10)), cpmm.price_a(dec!(10)));
+        let decoded = ConstantSumMarketMaker::decode(&mut &csmm.encode()[..]).unwrap();
+        assert_eq!(decoded.reserves(), csmm.reserves());
+    }
+
+    #[test]
+    fn test_pidmm_round_trip() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(100), dec!(100));
+        pool.swap_a(dec!(10));
+
+        // The controller state carries over, so both pools keep evolving identically.
+        let mut decoded = PIDMarketMaker::decode(&mut &pool.encode()[..]).unwrap();
+        assert_eq!(decoded.pid.last_terms(), pool.pid.last_terms());
+        assert_eq!(decoded.swap_b(dec!(5)), pool.swap_b(dec!(5)));
+        assert_eq!(decoded.price_a(dec!(10)), pool.price_a(dec!(10)));
+        assert_eq!(Side::decode(&mut &Side::B.encode()[..]), Ok(Side::B));
+    }
+
+    #[test]
+    fn test_oracle_round_trip() {
+        let mut oracle = EmaOracle::new(60);
+        oracle.update(0, dec!(2));
+        oracle.update(30, dec!(3));
+
+        // The decoded oracle keeps averaging from the same state.
+        let decoded = EmaOracle::decode(&mut &oracle.encode()[..]).unwrap();
+        assert_eq!(decoded.value_at(90), oracle.value_at(90));
+        assert_eq!(decoded.last_timestamp(), 30);
+    }
+
+    #[test]
+    fn test_fixed_round_trip() {
+        let mut pool = PIDMarketMaker::<Fixed>::default();
+        pool.set_reserves(Fixed::from_u64(100), Fixed::from_u64(100));
+
+        // Fixed-point pools encode their raw integers and decode bit for bit.
+        let decoded = PIDMarketMaker::<Fixed>::decode(&mut &pool.encode()[..]).unwrap();
+        assert_eq!(decoded.mid_price(), pool.mid_price());
+        assert_eq!(decoded.pid.last_terms(), pool.pid.last_terms());
+    }
+
+    #[test]
+    fn test_invalid_decimal() {
+        // A scale above 28 is rejected rather than decoded into an invalid decimal.
+        let mut bytes = [0u8; 32];
+        bytes[2] = 29;
+        assert!(ConstantProductMarketMaker::<Decimal>::decode(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    fn test_type_info() {
+        // The pools can be registered as runtime metadata.
+        let mut registry = Registry::new();
+        registry.register_type(&meta_type::<PIDMarketMaker>());
+        assert!(registry.types().count() > 1);
+    }
+}
@@ -0,0 +1,415 @@
+// Simulation agents
+
+use super::{metrics, Agent, Market, SECONDS_PER_YEAR};
+use crate::{arbitrage, LiquidityPool, Side};
+use rand::{rngs::StdRng, Rng};
+use rust_decimal::prelude::*;
+
+/// Draws a decimal uniformly between `low` and `high`.
+fn uniform(rng: &mut StdRng, low: Decimal, high: Decimal) -> Decimal {
+    let unit = Decimal::from_f64(rng.gen_range(0.0..1.0)).unwrap_or(Decimal::ZERO);
+    low + (high - low) * unit
+}
+
+/// Draws a random side.
+fn side(rng: &mut StdRng) -> Side {
+    if rng.gen() {
+        Side::A
+    } else {
+        Side::B
+    }
+}
+
+/// Returns the reserves of a pool on the given side.
+fn reserves_out<P: LiquidityPool + ?Sized>(pool: &P, side: Side) -> Decimal {
+    let (reserves_a, reserves_b) = pool.reserves();
+    match side {
+        Side::A => reserves_a,
+        Side::B => reserves_b,
+    }
+}
+
+/// Computes the annualized return of the fees a pool earned over the last `window` records,
+/// relative to the current value of its reserves.
+fn trailing_fee_apr(market: &Market<'_>, pool: usize, window: usize) -> Decimal {
+    let history = market.history();
+    let start = history.len().saturating_sub(window);
+    let (Some(first), Some(current)) = (history.get(start), market.pools().get(pool)) else {
+        return Decimal::ZERO;
+    };
+    let elapsed = market.timestamp().saturating_sub(first.timestamp);
+    let (reserves_a, reserves_b) = current.reserves();
+    let spot_price = current.spot_price();
+    let value = reserves_a * spot_price + reserves_b;
+    if elapsed == 0 || value <= Decimal::ZERO {
+        return Decimal::ZERO;
+    }
+    let fees: Decimal = history[start..]
+        .iter()
+        .map(|record| {
+            let (fees_a, fees_b) = metrics::step_fees(record, pool);
+            fees_a * spot_price + fees_b
+        })
+        .sum();
+    fees / value * Decimal::from(SECONDS_PER_YEAR) / Decimal::from(elapsed)
+}
+
+/// Noise trader taking a uniformly distributed amount out of a random side of a pool each step.
+#[derive(Debug, Clone)]
+pub struct UniformNoise {
+    pub pool: usize,
+    pub min_amount: Decimal,
+    pub max_amount: Decimal,
+}
+
+impl UniformNoise {
+    /// Creates a noise trader taking between `min_amount` and `max_amount` out of a pool.
+    pub fn new(pool: usize, min_amount: Decimal, max_amount: Decimal) -> Self {
+        Self {
+            pool,
+            min_amount,
+            max_amount,
+        }
+    }
+}
+
+impl Agent for UniformNoise {
+    fn name(&self) -> &str {
+        "uniform noise"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        let side = side(market.rng());
+        let amount = uniform(market.rng(), self.min_amount, self.max_amount);
+        market.swap(self.pool, side, amount);
+    }
+}
+
+/// Noise trader arriving as a Poisson process.
+///
+/// Each step the number of trades is drawn from a Poisson distribution with mean `rate`, and
+/// each trade takes an exponentially distributed amount with mean `mean_amount` out of a random
+/// side of the pool.
+#[derive(Debug, Clone)]
+pub struct PoissonNoise {
+    pub pool: usize,
+    pub rate: Decimal,
+    pub mean_amount: Decimal,
+}
+
+impl PoissonNoise {
+    /// Creates a noise trader making `rate` trades of `mean_amount` per step on average.
+    pub fn new(pool: usize, rate: Decimal, mean_amount: Decimal) -> Self {
+        Self {
+            pool,
+            rate,
+            mean_amount,
+        }
+    }
+
+    /// Draws the number of trades of a step, by Knuth's multiplication method.
+    fn arrivals(&self, rng: &mut StdRng) -> u32 {
+        let threshold = (-self.rate.to_f64().unwrap_or(0.0)).exp();
+        let mut product: f64 = rng.gen_range(0.0..1.0);
+        let mut count = 0;
+        while product > threshold {
+            product *= rng.gen_range(0.0..1.0);
+            count += 1;
+        }
+        count
+    }
+}
+
+impl Agent for PoissonNoise {
+    fn name(&self) -> &str {
+        "poisson noise"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        for _ in 0..self.arrivals(market.rng()) {
+            let side = side(market.rng());
+            let unit: f64 = market.rng().gen_range(0.0..1.0);
+            let scale = Decimal::from_f64(-(1.0 - unit).ln()).unwrap_or(Decimal::ZERO);
+            market.swap(self.pool, side, self.mean_amount * scale);
+        }
+    }
+}
+
+/// Momentum trader following the recent trend of a pool's price.
+///
+/// When the spot price moved by more than `threshold` over the last `lookback` steps, the
+/// trader takes `amount` of the token whose price rose out of the pool.
+#[derive(Debug, Clone)]
+pub struct Momentum {
+    pub pool: usize,
+    pub lookback: usize,
+    pub threshold: Decimal,
+    pub amount: Decimal,
+}
+
+impl Momentum {
+    /// Creates a momentum trader looking back `lookback` steps.
+    pub fn new(pool: usize, lookback: usize, threshold: Decimal, amount: Decimal) -> Self {
+        Self {
+            pool,
+            lookback,
+            threshold,
+            amount,
+        }
+    }
+}
+
+impl Agent for Momentum {
+    fn name(&self) -> &str {
+        "momentum"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        let history = market.history();
+        let Some(past) = history
+            .len()
+            .checked_sub(self.lookback.max(1))
+            .and_then(|index| history[index].pools.get(self.pool))
+        else {
+            return;
+        };
+        if past.spot_price <= Decimal::ZERO {
+            return;
+        }
+        let change = market.pools()[self.pool].spot_price() / past.spot_price - Decimal::ONE;
+        if change > self.threshold {
+            market.swap(self.pool, Side::A, self.amount);
+        } else if change < -self.threshold {
+            market.swap(self.pool, Side::B, self.amount);
+        }
+    }
+}
+
+/// Arbitrageur bringing a pool's price to the reference price of the simulation.
+///
+/// Each step the trader executes the profit-maximizing trade against the reference price, as
+/// found by [`arbitrage::against_price`], if its profit reaches `min_profit`.
+#[derive(Debug, Clone)]
+pub struct Arbitrageur {
+    pub pool: usize,
+    pub min_profit: Decimal,
+}
+
+impl Arbitrageur {
+    /// Creates an arbitrageur trading any profitable gap.
+    pub fn new(pool: usize) -> Self {
+        Self {
+            pool,
+            min_profit: Decimal::ZERO,
+        }
+    }
+}
+
+impl Agent for Arbitrageur {
+    fn name(&self) -> &str {
+        "arbitrageur"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        let Some(reference_price) = market.reference_price() else {
+            return;
+        };
+        let Some(trade) = arbitrage::against_price(&market.pools()[self.pool], reference_price)
+        else {
+            return;
+        };
+        if trade.profit >= self.min_profit {
+            market.swap(self.pool, trade.side, trade.amount);
+        }
+    }
+}
+
+/// Liquidity provider entering and leaving a pool depending on the fees it earns.
+///
+/// The provider watches the annualized fee return of the pool over the last `window` steps. It
+/// deposits `share` of the current reserves once the return reaches `enter_apr`, and withdraws
+/// its part of the reserves once the return falls below `exit_apr`. Its part is the fraction of
+/// the reserves it held right after depositing.
+#[derive(Debug, Clone)]
+pub struct LiquidityProvider {
+    pub pool: usize,
+    pub share: Decimal,
+    pub enter_apr: Decimal,
+    pub exit_apr: Decimal,
+    pub window: usize,
+    position: Option<Decimal>,
+}
+
+impl LiquidityProvider {
+    /// Creates a liquidity provider outside of the pool.
+    pub fn new(
+        pool: usize,
+        share: Decimal,
+        enter_apr: Decimal,
+        exit_apr: Decimal,
+        window: usize,
+    ) -> Self {
+        Self {
+            pool,
+            share,
+            enter_apr,
+            exit_apr,
+            window,
+            position: None,
+        }
+    }
+
+    /// Returns the fraction of the reserves held by the provider, if it is in the pool.
+    pub fn position(&self) -> Option<Decimal> {
+        self.position
+    }
+}
+
+impl Agent for LiquidityProvider {
+    fn name(&self) -> &str {
+        "liquidity provider"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        let apr = trailing_fee_apr(market, self.pool, self.window);
+        let (reserves_a, reserves_b) = market.pools()[self.pool].reserves();
+        match self.position {
+            None if apr >= self.enter_apr => {
+                market.add_liquidity(self.pool, reserves_a * self.share, reserves_b * self.share);
+                self.position = Some(self.share / (Decimal::ONE + self.share));
+            }
+            Some(fraction) if apr < self.exit_apr => {
+                market.remove_liquidity(self.pool, reserves_a * fraction, reserves_b * fraction);
+                self.position = None;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whale trading a large part of a pool's reserves at random times.
+///
+/// Each step, with probability `probability`, the whale takes `fraction` of the reserves of a
+/// random side out of the pool.
+#[derive(Debug, Clone)]
+pub struct Whale {
+    pub pool: usize,
+    pub probability: Decimal,
+    pub fraction: Decimal,
+}
+
+impl Whale {
+    /// Creates a whale trading `fraction` of the reserves with the given probability per step.
+    pub fn new(pool: usize, probability: Decimal, fraction: Decimal) -> Self {
+        Self {
+            pool,
+            probability,
+            fraction,
+        }
+    }
+}
+
+impl Agent for Whale {
+    fn name(&self) -> &str {
+        "whale"
+    }
+
+    fn act(&mut self, market: &mut Market<'_>) {
+        if uniform(market.rng(), Decimal::ZERO, Decimal::ONE) >= self.probability {
+            return;
+        }
+        let side = side(market.rng());
+        let amount = reserves_out(&market.pools()[self.pool], side) * self.fraction;
+        market.swap(self.pool, side, amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::Event;
+    use crate::{cpmm::ConstantProductMarketMaker, fee::WithFee, sim::Simulation};
+    use rust_decimal_macros::dec;
+
+    fn simulation(fee: Decimal) -> Simulation {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.set_reserves(dec!(1000), dec!(1000));
+        let mut simulation = Simulation::new(11);
+        simulation.add_pool(WithFee::new(pool, fee));
+        simulation
+    }
+
+    #[test]
+    fn test_arbitrageur_tracks_reference() {
+        let mut simulation = simulation(Decimal::ZERO);
+        simulation.add_agent(UniformNoise::new(0, dec!(1), dec!(20)));
+        simulation.add_agent(Arbitrageur::new(0));
+        simulation.set_reference_price(Some(dec!(1.5)));
+        simulation.run(10);
+
+        // The arbitrageur trades last, leaving the pool at the reference price.
+        let spot_price = simulation.pools()[0].spot_price();
+        assert_eq!(spot_price.round_dp(6), dec!(1.5));
+        assert!(simulation.accounts()[1].value(dec!(1.5)) > Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_poisson_arrivals() {
+        let mut simulation = simulation(Decimal::ZERO);
+        simulation.add_agent(PoissonNoise::new(0, dec!(3), dec!(1)));
+        simulation.run(500);
+
+        // About three trades arrive per step.
+        let trades: usize = simulation.records().iter().map(|r| r.trades.len()).sum();
+        assert!((1350..1650).contains(&trades), "{}", trades);
+    }
+
+    #[test]
+    fn test_momentum_follows_trend() {
+        let mut simulation = simulation(Decimal::ZERO);
+        simulation.add_agent(Momentum::new(0, 2, dec!(0.01), dec!(10)));
+        simulation.run(1);
+        simulation.pool_mut(0).unwrap().swap(Side::A, dec!(50));
+        simulation.run(3);
+
+        // A rising price of token A makes the trader buy token A, pushing it further.
+        let trades = &simulation.records()[2].trades;
+        assert_eq!(trades.len(), 1);
+        assert!(matches!(trades[0].event, Event::Swap { side: Side::A, .. }));
+    }
+
+    #[test]
+    fn test_liquidity_provider_follows_fees() {
+        let mut simulation = simulation(dec!(0.01));
+        simulation.add_agent(LiquidityProvider::new(
+            0,
+            dec!(0.5),
+            dec!(0.5),
+            dec!(0.1),
+            10,
+        ));
+        simulation.add_agent(UniformNoise::new(0, dec!(10), dec!(20)));
+        simulation.run(5);
+
+        // Busy trading pays well above the entry return, so the provider deposits.
+        let reserves = simulation.records()[5].pools[0].reserves_a;
+        assert!(reserves > dec!(1400), "{}", reserves);
+        let deposited = -simulation.accounts()[0].token_a;
+        assert!(deposited > dec!(490));
+    }
+
+    #[test]
+    fn test_whale_shocks() {
+        let mut simulation = simulation(Decimal::ZERO);
+        simulation.add_agent(Whale::new(0, dec!(0.1), dec!(0.3)));
+        simulation.run(100);
+
+        // Roughly one step in ten sees 30% of a side taken out.
+        let shocks = simulation
+            .records()
+            .iter()
+            .filter(|record| !record.trades.is_empty())
+            .count();
+        assert!((3..25).contains(&shocks), "{}", shocks);
+    }
+}
@@ -0,0 +1,343 @@
+// Time series export
+
+use super::{metrics, Record, Simulation};
+use crate::{events::Event, Side};
+use rust_decimal::Decimal;
+use std::io::{self, Write};
+
+/// A value of an exported column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field {
+    Integer(u64),
+    Decimal(Decimal),
+    Text(String),
+    /// A value the row does not have, written as an empty cell or `null`.
+    Missing,
+}
+
+impl From<Option<Decimal>> for Field {
+    fn from(value: Option<Decimal>) -> Self {
+        value.map_or(Field::Missing, Field::Decimal)
+    }
+}
+
+/// A row of an exported time series.
+///
+/// The columns of each kind of row are fixed, so that the files written for different runs
+/// share the same schema.
+pub trait Row {
+    /// The names of the columns, in order.
+    const COLUMNS: &'static [&'static str];
+
+    /// Returns the values of the row, one per column.
+    fn fields(&self) -> Vec<Field>;
+}
+
+/// The state of a pool at the end of a step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolRow {
+    pub step: u64,
+    pub timestamp: u64,
+    pub pool: usize,
+    pub reserves_a: Decimal,
+    pub reserves_b: Decimal,
+    pub spot_price: Decimal,
+    pub reference_price: Option<Decimal>,
+    /// The number of swaps executed by the agents during the step.
+    pub swaps: u64,
+    /// The amount of token A swapped in either direction during the step.
+    pub volume_a: Decimal,
+    /// The amount of token B swapped in either direction during the step.
+    pub volume_b: Decimal,
+    pub fees_a: Decimal,
+    pub fees_b: Decimal,
+    pub pid_error: Option<Decimal>,
+    pub pid_proportional: Option<Decimal>,
+    pub pid_integral: Option<Decimal>,
+    pub pid_derivative: Option<Decimal>,
+    pub pid_output: Option<Decimal>,
+}
+
+impl Row for PoolRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "step",
+        "timestamp",
+        "pool",
+        "reserves_a",
+        "reserves_b",
+        "spot_price",
+        "reference_price",
+        "swaps",
+        "volume_a",
+        "volume_b",
+        "fees_a",
+        "fees_b",
+        "pid_error",
+        "pid_proportional",
+        "pid_integral",
+        "pid_derivative",
+        "pid_output",
+    ];
+
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field::Integer(self.step),
+            Field::Integer(self.timestamp),
+            Field::Integer(self.pool as u64),
+            Field::Decimal(self.reserves_a),
+            Field::Decimal(self.reserves_b),
+            Field::Decimal(self.spot_price),
+            self.reference_price.into(),
+            Field::Integer(self.swaps),
+            Field::Decimal(self.volume_a),
+            Field::Decimal(self.volume_b),
+            Field::Decimal(self.fees_a),
+            Field::Decimal(self.fees_b),
+            self.pid_error.into(),
+            self.pid_proportional.into(),
+            self.pid_integral.into(),
+            self.pid_derivative.into(),
+            self.pid_output.into(),
+        ]
+    }
+}
+
+/// The account of an agent at the end of a step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AgentRow {
+    pub step: u64,
+    pub timestamp: u64,
+    pub agent: usize,
+    pub name: String,
+    pub token_a: Decimal,
+    pub token_b: Decimal,
+    /// The value of the account in token B, at the reference price or, without one, at the
+    /// spot price of the first pool.
+    pub pnl: Option<Decimal>,
+}
+
+impl Row for AgentRow {
+    const COLUMNS: &'static [&'static str] = &[
+        "step",
+        "timestamp",
+        "agent",
+        "name",
+        "token_a",
+        "token_b",
+        "pnl",
+    ];
+
+    fn fields(&self) -> Vec<Field> {
+        vec![
+            Field::Integer(self.step),
+            Field::Integer(self.timestamp),
+            Field::Integer(self.agent as u64),
+            Field::Text(self.name.clone()),
+            Field::Decimal(self.token_a),
+            Field::Decimal(self.token_b),
+            self.pnl.into(),
+        ]
+    }
+}
+
+/// Builds the rows of every pool at every step, ordered by step then pool.
+pub fn pool_rows(records: &[Record]) -> Vec<PoolRow> {
+    let mut rows = Vec::new();
+    for record in records {
+        for (pool, state) in record.pools.iter().enumerate() {
+            let mut swaps = 0;
+            let (mut volume_a, mut volume_b) = (Decimal::ZERO, Decimal::ZERO);
+            for trade in record.trades.iter().filter(|trade| trade.pool == pool) {
+                if let Event::Swap {
+                    side,
+                    amount_out,
+                    amount_in,
+                } = trade.event
+                {
+                    swaps += 1;
+                    let (amount_a, amount_b) = match side {
+                        Side::A => (amount_out, amount_in),
+                        Side::B => (amount_in, amount_out),
+                    };
+                    volume_a += amount_a;
+                    volume_b += amount_b;
+                }
+            }
+            let (fees_a, fees_b) = metrics::step_fees(record, pool);
+            let terms = state.pid_terms;
+            rows.push(PoolRow {
+                step: record.step,
+                timestamp: record.timestamp,
+                pool,
+                reserves_a: state.reserves_a,
+                reserves_b: state.reserves_b,
+                spot_price: state.spot_price,
+                reference_price: record.reference_price,
+                swaps,
+                volume_a,
+                volume_b,
+                fees_a,
+                fees_b,
+                pid_error: terms.map(|terms| terms.error),
+                pid_proportional: terms.map(|terms| terms.proportional),
+                pid_integral: terms.map(|terms| terms.integral),
+                pid_derivative: terms.map(|terms| terms.derivative),
+                pid_output: terms.map(|terms| terms.output),
+            });
+        }
+    }
+    rows
+}
+
+/// Builds the rows of every agent at every step of a simulation, ordered by step then agent.
+pub fn agent_rows(simulation: &Simulation) -> Vec<AgentRow> {
+    let mut rows = Vec::new();
+    for record in simulation.records() {
+        let price = record
+            .reference_price
+            .or_else(|| record.pools.first().map(|state| state.spot_price));
+        for (agent, (account, actor)) in record.accounts.iter().zip(simulation.agents()).enumerate()
+        {
+            rows.push(AgentRow {
+                step: record.step,
+                timestamp: record.timestamp,
+                agent,
+                name: actor.name().to_string(),
+                token_a: account.token_a,
+                token_b: account.token_b,
+                pnl: price.map(|price| account.value(price)),
+            });
+        }
+    }
+    rows
+}
+
+/// Writes rows as CSV, with a header line naming the columns.
+pub fn write_csv<R: Row, W: Write>(mut writer: W, rows: &[R]) -> io::Result<()> {
+    writeln!(writer, "{}", R::COLUMNS.join(","))?;
+    for row in rows {
+        let cells: Vec<String> = row
+            .fields()
+            .into_iter()
+            .map(|field| match field {
+                Field::Integer(value) => value.to_string(),
+                Field::Decimal(value) => value.normalize().to_string(),
+                Field::Text(text) if text.contains([',', '"', '\n']) => {
+                    format!("\"{}\"", text.replace('"', "\"\""))
+                }
+                Field::Text(text) => text,
+                Field::Missing => String::new(),
+            })
+            .collect();
+        writeln!(writer, "{}", cells.join(","))?;
+    }
+    writer.flush()
+}
+
+/// Writes rows as JSON Lines, one object per row keyed by the column names.
+///
+/// Decimals are written as JSON numbers and missing values as `null`.
+pub fn write_jsonl<R: Row, W: Write>(mut writer: W, rows: &[R]) -> io::Result<()> {
+    for row in rows {
+        let members: Vec<String> = R::COLUMNS
+            .iter()
+            .zip(row.fields())
+            .map(|(column, field)| {
+                let value = match field {
+                    Field::Integer(value) => value.to_string(),
+                    Field::Decimal(value) => value.normalize().to_string(),
+                    Field::Text(text) => json_string(&text),
+                    Field::Missing => "null".to_string(),
+                };
+                format!("{}:{}", json_string(column), value)
+            })
+            .collect();
+        writeln!(writer, "{{{}}}", members.join(","))?;
+    }
+    writer.flush()
+}
+
+/// Quotes a string for JSON.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpmm::ConstantProductMarketMaker,
+        pidmm::PIDMarketMaker,
+        sim::agents::{Arbitrageur, UniformNoise},
+        LiquidityPool,
+    };
+    use rust_decimal::prelude::ToPrimitive;
+    use rust_decimal_macros::dec;
+
+    fn simulation() -> Simulation {
+        let mut cpmm = ConstantProductMarketMaker::default();
+        cpmm.set_reserves(dec!(100), dec!(100));
+        let mut pidmm = PIDMarketMaker::default();
+        pidmm.set_reserves(dec!(100), dec!(100));
+        let mut simulation = Simulation::new(2);
+        simulation.set_reference_price(Some(dec!(1.1)));
+        simulation.add_pool(cpmm);
+        simulation.add_pool(pidmm);
+        simulation.add_agent(Arbitrageur::new(0));
+        simulation.add_agent(UniformNoise::new(1, dec!(1), dec!(2)));
+        simulation.run(3);
+        simulation
+    }
+
+    #[test]
+    fn test_csv() {
+        let simulation = simulation();
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &pool_rows(simulation.records())).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+
+        // A header, then one line per pool and step, with the PID terms of the PIDMM only.
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0].split(',').count(), PoolRow::COLUMNS.len());
+        assert!(lines[0].starts_with("step,timestamp,pool,reserves_a"));
+        assert!(lines[1].starts_with("0,0,0,100,100,1,1.1,0,0,0,0,0,,,,,"));
+        assert!(lines[3].starts_with("1,1,0,"));
+        assert!(lines[3].contains(",1,"));
+        assert!(!lines[4].ends_with(",,,,,"));
+    }
+
+    #[test]
+    fn test_jsonl() {
+        let simulation = simulation();
+        let mut jsonl = Vec::new();
+        write_jsonl(&mut jsonl, &agent_rows(&simulation)).unwrap();
+        let jsonl = String::from_utf8(jsonl).unwrap();
+
+        // Each line is a JSON object with the agent's profit at the reference price.
+        let rows: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(rows.len(), 8);
+        assert_eq!(rows[0]["name"], "arbitrageur");
+        assert_eq!(rows[1]["name"], "uniform noise");
+        assert_eq!(rows[0]["pnl"], 0);
+        let account = simulation.accounts()[0];
+        let pnl = account.value(dec!(1.1)).to_f64().unwrap();
+        assert!((rows[6]["pnl"].as_f64().unwrap() - pnl).abs() < 1e-9);
+        assert_eq!(json_string("a \"b\"\n"), r#""a \"b\"\n""#);
+    }
+}
@@ -0,0 +1,440 @@
+// Simulation metrics
+
+use super::{Record, Simulation, SECONDS_PER_YEAR};
+use crate::{events::Event, Side};
+use rust_decimal::{Decimal, MathematicalOps};
+use rust_decimal_macros::dec;
+
+/// Band around the target, as a fraction of it, within which a controlled price is settled.
+pub const SETTLING_BAND: Decimal = dec!(0.02);
+
+/// How closely a pool with a controller followed its target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Tracking {
+    /// The root mean square of the gap between the spot price and the target, over all steps.
+    pub rms_error: Decimal,
+    /// The largest move of the spot price past the target, as a fraction of the target.
+    ///
+    /// Moves past the target are measured away from the side the price started on, or either
+    /// way if it started on the target.
+    pub overshoot: Decimal,
+    /// The step from which the spot price stayed within [`SETTLING_BAND`] of the target, if it
+    /// settled before the end of the run.
+    pub settling_step: Option<u64>,
+}
+
+/// Metrics of a pool over a simulation run.
+///
+/// Tokens are valued in token B at the reference price, or at the pool's spot price when the
+/// simulation has no reference price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolMetrics {
+    /// The value of the reserves net of the fees they collected, relative to the value of
+    /// holding the initial reserves and later deposits instead, minus one.
+    ///
+    /// A value of `-0.05` means providing liquidity lost 5% against holding, before fees.
+    pub impermanent_loss: Decimal,
+    /// The value the pool gave away to swaps at the reference price, before fees.
+    ///
+    /// This is the loss against a portfolio rebalancing to the same reserves at the reference
+    /// price. Trades at worse than the reference price, such as noise trades, reduce it. `None`
+    /// if the simulation has no reference price.
+    pub loss_versus_rebalancing: Option<Decimal>,
+    /// The value of the fees collected by the pool.
+    pub fees: Decimal,
+    /// The fees relative to the initial value of the reserves, annualized.
+    pub fee_apr: Decimal,
+    /// The standard deviation of the log returns of the spot price between steps.
+    pub volatility: Decimal,
+    /// How closely the spot price followed the `target` parameter, for pools that have one.
+    pub tracking: Option<Tracking>,
+}
+
+/// Returns the price of token A in terms of token B used to value the tokens of a pool at a
+/// step.
+fn valuation_price(record: &Record, pool: usize) -> Decimal {
+    record
+        .reference_price
+        .or_else(|| record.pools.get(pool).map(|state| state.spot_price))
+        .unwrap_or(Decimal::ZERO)
+}
+
+/// Computes the fees paid into a pool during a step, in token A and token B.
+///
+/// The fee rate is read from the pool's `fee` parameter, so pools without fees collect
+/// nothing.
+pub fn step_fees(record: &Record, pool: usize) -> (Decimal, Decimal) {
+    let fee = record
+        .pools
+        .get(pool)
+        .and_then(|state| state.parameter("fee"))
+        .unwrap_or(Decimal::ZERO);
+    let mut fees = (Decimal::ZERO, Decimal::ZERO);
+    if fee <= Decimal::ZERO {
+        return fees;
+    }
+    for trade in record.trades.iter().filter(|trade| trade.pool == pool) {
+        if let Event::Swap {
+            side, amount_in, ..
+        } = trade.event
+        {
+            let fee_in = amount_in * fee / (Decimal::ONE + fee);
+            match side {
+                Side::A => fees.1 += fee_in,
+                Side::B => fees.0 += fee_in,
+            }
+        }
+    }
+    fees
+}
+
+/// Computes the standard deviation of the log returns of a series of prices.
+///
+/// Non-positive prices are skipped.
+pub fn volatility<I: IntoIterator<Item = Decimal>>(prices: I) -> Decimal {
+    let prices: Vec<Decimal> = prices
+        .into_iter()
+        .filter(|price| *price > Decimal::ZERO)
+        .collect();
+    let returns: Vec<Decimal> = prices
+        .windows(2)
+        .map(|pair| (pair[1] / pair[0]).ln())
+        .collect();
+    if returns.is_empty() {
+        return Decimal::ZERO;
+    }
+    let count = Decimal::from(returns.len());
+    let mean = returns.iter().sum::<Decimal>() / count;
+    let variance = returns
+        .iter()
+        .map(|r| (*r - mean) * (*r - mean))
+        .sum::<Decimal>()
+        / count;
+    variance.sqrt().unwrap_or(Decimal::ZERO)
+}
+
+/// Computes how closely the spot price of a pool followed its `target` parameter.
+///
+/// Returns `None` if the pool has no target.
+pub fn tracking(records: &[Record], pool: usize) -> Option<Tracking> {
+    let samples: Vec<(u64, Decimal, Decimal)> = records
+        .iter()
+        .filter_map(|record| {
+            let state = record.pools.get(pool)?;
+            Some((record.step, state.spot_price, state.parameter("target")?))
+        })
+        .filter(|(_, _, target)| *target > Decimal::ZERO)
+        .collect();
+    let &(first_step, start, start_target) = samples.first()?;
+    let count = Decimal::from(samples.len());
+    let squares: Decimal = samples
+        .iter()
+        .map(|(_, price, target)| (*price - *target) * (*price - *target))
+        .sum();
+    let direction = match start.cmp(&start_target) {
+        core::cmp::Ordering::Less => Decimal::ONE,
+        core::cmp::Ordering::Equal => Decimal::ZERO,
+        core::cmp::Ordering::Greater => Decimal::NEGATIVE_ONE,
+    };
+    let overshoot = samples
+        .iter()
+        .map(|(_, price, target)| {
+            let gap = (*price - *target) / *target;
+            if direction.is_zero() {
+                gap.abs()
+            } else {
+                gap * direction
+            }
+        })
+        .max()
+        .unwrap_or(Decimal::ZERO)
+        .max(Decimal::ZERO);
+    let unsettled = samples
+        .iter()
+        .rposition(|(_, price, target)| (*price - *target).abs() > *target * SETTLING_BAND);
+    let settling_step = match unsettled {
+        None => Some(first_step),
+        Some(index) => samples.get(index + 1).map(|(step, _, _)| *step),
+    };
+    Some(Tracking {
+        rms_error: (squares / count).sqrt().unwrap_or(Decimal::ZERO),
+        overshoot,
+        settling_step,
+    })
+}
+
+/// Computes the metrics of a pool over the given records.
+///
+/// Returns `None` if the records are empty or the pool does not exist.
+pub fn pool_metrics(records: &[Record], pool: usize) -> Option<PoolMetrics> {
+    let first = records.first()?;
+    let last = records.last()?;
+    let initial = first.pools.get(pool)?;
+    let current = last.pools.get(pool)?;
+
+    let mut held = (initial.reserves_a, initial.reserves_b);
+    let mut fees = (Decimal::ZERO, Decimal::ZERO);
+    let mut loss_versus_rebalancing = first.reference_price.map(|_| Decimal::ZERO);
+    for record in &records[1..] {
+        let (fees_a, fees_b) = step_fees(record, pool);
+        fees = (fees.0 + fees_a, fees.1 + fees_b);
+        let fee = record.pools[pool].parameter("fee").unwrap_or_default();
+        for trade in record.trades.iter().filter(|trade| trade.pool == pool) {
+            match trade.event {
+                Event::Mint { amount_a, amount_b } => {
+                    held = (held.0 + amount_a, held.1 + amount_b);
+                }
+                Event::Burn { amount_a, amount_b } => {
+                    held = (held.0 - amount_a, held.1 - amount_b);
+                }
+                Event::Swap {
+                    side,
+                    amount_out,
+                    amount_in,
+                } => {
+                    let (Some(loss), Some(price)) =
+                        (&mut loss_versus_rebalancing, record.reference_price)
+                    else {
+                        continue;
+                    };
+                    let amount_in = amount_in / (Decimal::ONE + fee);
+                    *loss += match side {
+                        Side::A => amount_out * price - amount_in,
+                        Side::B => amount_out - amount_in * price,
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let price = valuation_price(last, pool);
+    let fees = fees.0 * price + fees.1;
+    let held = held.0 * price + held.1;
+    let value = current.reserves_a * price + current.reserves_b;
+    let impermanent_loss = if held > Decimal::ZERO {
+        (value - fees) / held - Decimal::ONE
+    } else {
+        Decimal::ZERO
+    };
+
+    let initial_value = initial.reserves_a * valuation_price(first, pool) + initial.reserves_b;
+    let elapsed = last.timestamp.saturating_sub(first.timestamp);
+    let fee_apr = if initial_value > Decimal::ZERO && elapsed > 0 {
+        fees / initial_value * Decimal::from(SECONDS_PER_YEAR) / Decimal::from(elapsed)
+    } else {
+        Decimal::ZERO
+    };
+
+    Some(PoolMetrics {
+        impermanent_loss,
+        loss_versus_rebalancing,
+        fees,
+        fee_apr,
+        volatility: volatility(
+            records
+                .iter()
+                .filter_map(|record| record.pools.get(pool))
+                .map(|state| state.spot_price),
+        ),
+        tracking: tracking(records, pool),
+    })
+}
+
+/// Computes the metrics of every pool of a simulation over the steps run so far.
+pub fn metrics(simulation: &Simulation) -> Vec<PoolMetrics> {
+    (0..simulation.pools().len())
+        .filter_map(|pool| pool_metrics(simulation.records(), pool))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpmm::ConstantProductMarketMaker,
+        fee::WithFee,
+        pidmm::PIDMarketMaker,
+        sim::{
+            agents::{Arbitrageur, UniformNoise},
+            price::GeometricBrownian,
+        },
+        LiquidityPool,
+    };
+
+    fn cpmm(fee: Decimal) -> Simulation {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.set_reserves(dec!(1000), dec!(1000));
+        let mut simulation = Simulation::new(21);
+        simulation.set_step_seconds(3600);
+        simulation.set_price_process(GeometricBrownian::new(dec!(1), dec!(0), dec!(0.02)));
+        simulation.add_pool(WithFee::new(pool, fee));
+        simulation.add_agent(Arbitrageur::new(0));
+        simulation
+    }
+
+    #[test]
+    fn test_arbitrage_losses() {
+        let mut simulation = cpmm(Decimal::ZERO);
+        simulation.run(200);
+        let metrics = metrics(&simulation);
+
+        // Arbitrage against a moving price loses against holding and rebalancing.
+        let pool = metrics[0];
+        assert!(pool.impermanent_loss < Decimal::ZERO);
+        let lvr = pool.loss_versus_rebalancing.unwrap();
+        assert!(lvr > Decimal::ZERO);
+
+        // The arbitrageur's profit is the pool's loss against rebalancing, marked to the final
+        // price.
+        let price = simulation.reference_price().unwrap();
+        assert_eq!(
+            simulation.accounts()[0].value(price).round_dp(6),
+            (lvr - marked_drift(&simulation)).round_dp(6)
+        );
+        assert_eq!((pool.fees, pool.fee_apr), (Decimal::ZERO, Decimal::ZERO));
+        assert!(pool.tracking.is_none());
+        assert!(pool.volatility > dec!(0.01) && pool.volatility < dec!(0.03));
+    }
+
+    /// Computes the change in value of the arbitrageur's past trades as the price moved on.
+    fn marked_drift(simulation: &Simulation) -> Decimal {
+        let price = simulation.reference_price().unwrap();
+        simulation.records()[1..]
+            .iter()
+            .flat_map(|record| {
+                let then = record.reference_price.unwrap();
+                record.trades.iter().map(move |trade| match trade.event {
+                    Event::Swap {
+                        side: Side::A,
+                        amount_out,
+                        ..
+                    } => amount_out * (then - price),
+                    Event::Swap {
+                        side: Side::B,
+                        amount_in,
+                        ..
+                    } => amount_in * (price - then),
+                    _ => Decimal::ZERO,
+                })
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_fees() {
+        let mut simulation = cpmm(dec!(0.003));
+        simulation.add_agent(UniformNoise::new(0, dec!(1), dec!(10)));
+        simulation.run(100);
+        let pool = metrics(&simulation)[0];
+
+        // Noise and arbitrage volume at 0.3% pay a few units over a hundred hours, which
+        // annualizes to a double digit return on the reserves.
+        assert!(pool.fees > dec!(2) && pool.fees < dec!(6), "{}", pool.fees);
+        let expected = pool.fees / dec!(2000) * dec!(87.6);
+        assert_eq!(pool.fee_apr.round_dp(6), expected.round_dp(6));
+        assert!(pool.fee_apr > dec!(0.1));
+    }
+
+    /// Creates the record of a step of a single pool with the given spot price and target.
+    fn tracked(step: u64, spot_price: Decimal, target: Decimal) -> Record {
+        let state = crate::sim::PoolState {
+            reserves_a: dec!(1),
+            reserves_b: dec!(1),
+            spot_price,
+            pid_terms: None,
+            parameters: vec![("target".to_string(), target)],
+            events: Vec::new(),
+        };
+        Record {
+            step,
+            timestamp: step,
+            reference_price: None,
+            pools: vec![state],
+            trades: Vec::new(),
+            accounts: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_tracking() {
+        // Gaps of 0, 4, 0 and 0 to a target of 2 have a mean square of 4.
+        let records: Vec<_> = [dec!(2), dec!(6), dec!(2), dec!(2)]
+            .into_iter()
+            .enumerate()
+            .map(|(index, price)| tracked(index as u64, price, dec!(2)))
+            .collect();
+        let result = tracking(&records, 0).unwrap();
+        assert_eq!(result.rms_error, dec!(2));
+
+        // Starting on the target, the move to 6 counts as a 200% overshoot.
+        assert_eq!(result.overshoot, dec!(2));
+        assert_eq!(result.settling_step, Some(2));
+
+        // Each step is measured against its own target: gaps of 0.5 throughout.
+        let records: Vec<_> = [
+            (dec!(1.5), dec!(2)),
+            (dec!(3.5), dec!(3)),
+            (dec!(2.5), dec!(3)),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(index, (price, target))| tracked(index as u64, price, target))
+        .collect();
+        let result = tracking(&records, 0).unwrap();
+        assert_eq!(result.rms_error, dec!(0.5));
+        assert_eq!(result.overshoot.round_dp(6), dec!(0.166667));
+        assert_eq!(result.settling_step, None);
+
+        // Pools without a target are not tracked.
+        let mut records = records;
+        for record in &mut records {
+            record.pools[0].parameters.clear();
+        }
+        assert!(tracking(&records, 0).is_none());
+    }
+
+    #[test]
+    fn test_tracking_pid_run() {
+        // A PIDMM 20% above its target, arbitraged against a reference price on the target.
+        let mut pool = PIDMarketMaker::default();
+        pool.target = dec!(2);
+        pool.set_reserves(dec!(1000), dec!(1200));
+        let mut simulation = Simulation::new(4);
+        simulation.set_price_process(GeometricBrownian::new(dec!(2), dec!(0), dec!(0)));
+        simulation.add_pool(pool);
+        simulation.add_agent(Arbitrageur::new(0));
+        simulation.run(10);
+        let tracking = metrics(&simulation)[0].tracking.unwrap();
+
+        // The price falls to about 2.09 after one step and 2.02 after two, within the 2% band
+        // of 1.96 to 2.04 from then on, without crossing the target by more than a hair.
+        let prices: Vec<_> = simulation
+            .records()
+            .iter()
+            .map(|record| record.pools[0].spot_price.round_dp(2))
+            .collect();
+        assert_eq!(prices[..3], [dec!(2.40), dec!(2.09), dec!(2.02)]);
+        assert_eq!(tracking.settling_step, Some(2));
+        assert!(tracking.overshoot < dec!(0.0001));
+    }
+
+    #[test]
+    fn test_tracking_settles() {
+        let records: Vec<_> = [0.5, 0.9, 1.1, 1.01, 0.99, 1.0]
+            .iter()
+            .enumerate()
+            .map(|(index, price)| {
+                tracked(index as u64, Decimal::try_from(*price).unwrap(), dec!(1))
+            })
+            .collect();
+
+        // Starting below the target, the price overshoots by 10% and settles from step 3.
+        let result = tracking(&records, 0).unwrap();
+        assert_eq!(result.overshoot, dec!(0.1));
+        assert_eq!(result.settling_step, Some(3));
+        assert_eq!(tracking(&records[..3], 0).unwrap().settling_step, None);
+    }
+}
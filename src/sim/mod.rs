@@ -0,0 +1,657 @@
+// Simulation engine
+
+pub mod agents;
+pub mod export;
+pub mod metrics;
+pub mod price;
+#[cfg(feature = "scenario")]
+pub mod replay;
+#[cfg(feature = "scenario")]
+pub mod scenario;
+#[cfg(feature = "scenario")]
+pub mod sweep;
+
+use crate::{
+    events::{Event, Observed},
+    pidmm::PidTerms,
+    LiquidityPool, Side,
+};
+use price::PriceProcess;
+use rand::{rngs::StdRng, SeedableRng};
+use rust_decimal::Decimal;
+
+/// Mixed into the seed of the price generator, so that it draws another stream than the
+/// agents.
+const PRICE_STREAM: u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// Number of seconds in a year of 365 days, used to annualize rates.
+pub const SECONDS_PER_YEAR: u64 = 31_536_000;
+
+/// A pool taking part in a simulation, logging the events of each step.
+pub type SimPool = Observed<Box<dyn LiquidityPool>>;
+
+/// The net tokens an agent has taken out of the pools since the start of a simulation.
+///
+/// Tokens paid into a pool count negatively, so an agent starts with an empty account and may
+/// go below zero on either token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Account {
+    pub token_a: Decimal,
+    pub token_b: Decimal,
+}
+
+impl Account {
+    /// Values the account in token B, given a price of token A in terms of token B.
+    pub fn value(&self, price: Decimal) -> Decimal {
+        self.token_a * price + self.token_b
+    }
+}
+
+/// An action taken by an agent on a pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Trade {
+    /// The index of the agent.
+    pub agent: usize,
+    /// The index of the pool.
+    pub pool: usize,
+    /// The swap, mint or burn executed.
+    pub event: Event,
+}
+
+/// The state of a pool at the end of a step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PoolState {
+    pub reserves_a: Decimal,
+    pub reserves_b: Decimal,
+    /// The price of token A in terms of token B.
+    pub spot_price: Decimal,
+    /// The terms of the last control signal, for pools with a controller.
+    pub pid_terms: Option<PidTerms>,
+    /// The named parameters of the pool.
+    pub parameters: Vec<(String, Decimal)>,
+    /// The events the pool emitted during the step.
+    pub events: Vec<Event>,
+}
+
+impl PoolState {
+    /// Returns the value of a named parameter, if the pool has it.
+    pub fn parameter(&self, name: &str) -> Option<Decimal> {
+        self.parameters
+            .iter()
+            .find(|(parameter, _)| parameter == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// The state of a simulation at the end of a step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Record {
+    pub step: u64,
+    pub timestamp: u64,
+    /// The external price of token A in terms of token B, if the simulation has one.
+    pub reference_price: Option<Decimal>,
+    /// The state of each pool, in the order the pools were added.
+    pub pools: Vec<PoolState>,
+    /// The actions taken by the agents during the step, in the order they were executed.
+    pub trades: Vec<Trade>,
+    /// The account of each agent, in the order the agents were added.
+    pub accounts: Vec<Account>,
+}
+
+/// A participant acting on the pools once per step.
+pub trait Agent {
+    /// Returns a short name describing the agent.
+    fn name(&self) -> &str;
+
+    /// Acts on the market for the current step.
+    fn act(&mut self, market: &mut Market<'_>);
+}
+
+/// The view of a simulation given to an agent while it acts.
+///
+/// Actions go through the market so that they are recorded as trades and settled against the
+/// agent's account.
+pub struct Market<'a> {
+    step: u64,
+    timestamp: u64,
+    agent: usize,
+    reference_price: Option<Decimal>,
+    history: &'a [Record],
+    rng: &'a mut StdRng,
+    pools: &'a mut [SimPool],
+    account: &'a mut Account,
+    trades: &'a mut Vec<Trade>,
+}
+
+impl Market<'_> {
+    /// Returns the current step.
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    /// Returns the timestamp of the current step, in seconds.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Returns the external price of token A in terms of token B, if the simulation has one.
+    pub fn reference_price(&self) -> Option<Decimal> {
+        self.reference_price
+    }
+
+    /// Returns the records of the previous steps.
+    pub fn history(&self) -> &[Record] {
+        self.history
+    }
+
+    /// Returns the random number generator of the simulation.
+    pub fn rng(&mut self) -> &mut StdRng {
+        self.rng
+    }
+
+    /// Returns the pools of the simulation.
+    pub fn pools(&self) -> &[SimPool] {
+        self.pools
+    }
+
+    /// Returns the account of the acting agent.
+    pub fn account(&self) -> &Account {
+        self.account
+    }
+
+    /// Takes `amount` out of a pool on the given side.
+    ///
+    /// Returns the amount of the other token paid into the pool, or zero if the pool could not
+    /// fill the trade, as when it asks for the whole reserve. Panics if the pool does not exist.
+    pub fn swap(&mut self, pool: usize, side: Side, amount: Decimal) -> Decimal {
+        if amount >= self.reserve(pool, side) {
+            return Decimal::ZERO;
+        }
+        let amount_in = self.settle(pool, |pool| pool.swap(side, amount));
+        if amount_in > Decimal::ZERO {
+            self.record(
+                pool,
+                Event::Swap {
+                    side,
+                    amount_out: amount,
+                    amount_in,
+                },
+            );
+        }
+        amount_in
+    }
+
+    /// Pays exactly `amount_in` of the other token into a pool for the token on the given side.
+    ///
+    /// Returns the amount taken out of the pool, or zero if the pool could not fill the trade.
+    /// Panics if the pool does not exist.
+    pub fn swap_exact_in(&mut self, pool: usize, side: Side, amount_in: Decimal) -> Decimal {
+        if self.pools[pool].amount_out(side, amount_in) >= self.reserve(pool, side) {
+            return Decimal::ZERO;
+        }
+        let reserves = self.pools[pool].reserves();
+        let amount_out = self.settle(pool, |pool| pool.swap_exact_in(side, amount_in));
+        if amount_out > Decimal::ZERO {
+            // The pool may charge slightly less than the amount offered.
+            let (reserves_a, reserves_b) = self.pools[pool].reserves();
+            let amount_in = match side {
+                Side::A => reserves_b - reserves.1,
+                Side::B => reserves_a - reserves.0,
+            };
+            self.record(
+                pool,
+                Event::Swap {
+                    side,
+                    amount_out,
+                    amount_in,
+                },
+            );
+        }
+        amount_out
+    }
+
+    /// Deposits liquidity into a pool. Panics if the pool does not exist.
+    pub fn add_liquidity(&mut self, pool: usize, amount_a: Decimal, amount_b: Decimal) {
+        self.settle(pool, |pool| pool.add_liquidity(amount_a, amount_b));
+        self.record(pool, Event::Mint { amount_a, amount_b });
+    }
+
+    /// Withdraws liquidity from a pool. Panics if the pool does not exist.
+    pub fn remove_liquidity(&mut self, pool: usize, amount_a: Decimal, amount_b: Decimal) {
+        self.settle(pool, |pool| pool.remove_liquidity(amount_a, amount_b));
+        self.record(pool, Event::Burn { amount_a, amount_b });
+    }
+
+    /// Returns the reserve of a pool on the given side.
+    fn reserve(&self, pool: usize, side: Side) -> Decimal {
+        let (reserves_a, reserves_b) = self.pools[pool].reserves();
+        match side {
+            Side::A => reserves_a,
+            Side::B => reserves_b,
+        }
+    }
+
+    /// Applies an action to a pool and settles the change in its reserves against the account.
+    fn settle<T>(&mut self, pool: usize, action: impl FnOnce(&mut SimPool) -> T) -> T {
+        let pool = &mut self.pools[pool];
+        let (before_a, before_b) = pool.reserves();
+        let result = action(pool);
+        let (after_a, after_b) = pool.reserves();
+        self.account.token_a += before_a - after_a;
+        self.account.token_b += before_b - after_b;
+        result
+    }
+
+    /// Records an action of the acting agent.
+    fn record(&mut self, pool: usize, event: Event) {
+        self.trades.push(Trade {
+            agent: self.agent,
+            pool,
+            event,
+        });
+    }
+}
+
+/// Simulation
+///
+/// A simulation advances a clock step by step. At each step the reference price advances, then
+/// every agent acts on the pools in the order the agents were added, then the state of the
+/// pools and the agents' accounts is recorded. All randomness is drawn from a single generator
+/// seeded at creation, so a simulation is fully determined by its seed, pools and agents.
+pub struct Simulation {
+    step: u64,
+    step_seconds: u64,
+    reference_price: Option<Decimal>,
+    price: Option<Box<dyn PriceProcess>>,
+    price_rng: StdRng,
+    rng: StdRng,
+    pools: Vec<SimPool>,
+    agents: Vec<Box<dyn Agent>>,
+    accounts: Vec<Account>,
+    records: Vec<Record>,
+    record_limit: Option<usize>,
+}
+
+impl Simulation {
+    /// Creates an empty simulation whose steps last one second.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            step: 0,
+            step_seconds: 1,
+            reference_price: None,
+            price: None,
+            price_rng: StdRng::seed_from_u64(seed ^ PRICE_STREAM),
+            rng: StdRng::seed_from_u64(seed),
+            pools: Vec::new(),
+            agents: Vec::new(),
+            accounts: Vec::new(),
+            records: Vec::new(),
+            record_limit: None,
+        }
+    }
+
+    /// Keeps only the records of the last `limit` steps, so that long runs use bounded memory.
+    ///
+    /// Older records are dropped as steps run, and agents only see the records kept in their
+    /// history. All records are kept by default.
+    pub fn set_record_limit(&mut self, limit: Option<usize>) -> &mut Self {
+        self.record_limit = limit.map(|limit| limit.max(1));
+        self
+    }
+
+    /// Sets the duration of a step, in seconds.
+    pub fn set_step_seconds(&mut self, step_seconds: u64) -> &mut Self {
+        self.step_seconds = step_seconds;
+        self
+    }
+
+    /// Sets the external price of token A in terms of token B that agents can trade against.
+    pub fn set_reference_price(&mut self, reference_price: Option<Decimal>) -> &mut Self {
+        self.reference_price = reference_price;
+        self
+    }
+
+    /// Drives the reference price with a price process, advanced at the start of each step.
+    ///
+    /// The process draws from its own generator, so that a seed yields the same price path
+    /// whatever the agents do.
+    pub fn set_price_process<P: PriceProcess + 'static>(&mut self, process: P) -> &mut Self {
+        self.set_boxed_price_process(Box::new(process))
+    }
+
+    /// Drives the reference price with a boxed price process.
+    pub fn set_boxed_price_process(&mut self, process: Box<dyn PriceProcess>) -> &mut Self {
+        self.reference_price = Some(process.price());
+        self.price = Some(process);
+        self
+    }
+
+    /// Returns the external price of token A in terms of token B, if the simulation has one.
+    pub fn reference_price(&self) -> Option<Decimal> {
+        self.reference_price
+    }
+
+    /// Adds a pool, returning its index.
+    pub fn add_pool<P: LiquidityPool + 'static>(&mut self, pool: P) -> usize {
+        self.add_boxed_pool(Box::new(pool))
+    }
+
+    /// Adds a boxed pool, returning its index.
+    pub fn add_boxed_pool(&mut self, pool: Box<dyn LiquidityPool>) -> usize {
+        self.pools.push(Observed::new(pool));
+        self.pools.len() - 1
+    }
+
+    /// Adds an agent with an empty account, returning its index.
+    pub fn add_agent<A: Agent + 'static>(&mut self, agent: A) -> usize {
+        self.add_boxed_agent(Box::new(agent))
+    }
+
+    /// Adds a boxed agent with an empty account, returning its index.
+    pub fn add_boxed_agent(&mut self, agent: Box<dyn Agent>) -> usize {
+        self.agents.push(agent);
+        self.accounts.push(Account::default());
+        self.agents.len() - 1
+    }
+
+    /// Returns the number of steps run so far.
+    pub fn step_count(&self) -> u64 {
+        self.step
+    }
+
+    /// Returns the timestamp of the current step, in seconds.
+    pub fn timestamp(&self) -> u64 {
+        self.step * self.step_seconds
+    }
+
+    /// Returns the duration of a step, in seconds.
+    pub fn step_seconds(&self) -> u64 {
+        self.step_seconds
+    }
+
+    /// Returns the pools of the simulation.
+    pub fn pools(&self) -> &[SimPool] {
+        &self.pools
+    }
+
+    /// Returns a pool of the simulation mutably, if it exists.
+    ///
+    /// Changes made outside of a step are logged with the next step's events.
+    pub fn pool_mut(&mut self, index: usize) -> Option<&mut SimPool> {
+        self.pools.get_mut(index)
+    }
+
+    /// Returns the agents of the simulation.
+    pub fn agents(&self) -> &[Box<dyn Agent>] {
+        &self.agents
+    }
+
+    /// Returns the account of each agent.
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    /// Returns the records of the steps run so far.
+    ///
+    /// The first record holds the state before the first step, at step zero, unless it was
+    /// dropped by the [record limit](Self::set_record_limit).
+    pub fn records(&self) -> &[Record] {
+        &self.records[self.first_kept()..]
+    }
+
+    /// Returns the index of the first record within the record limit.
+    fn first_kept(&self) -> usize {
+        match self.record_limit {
+            Some(limit) => self.records.len().saturating_sub(limit),
+            None => 0,
+        }
+    }
+
+    /// Runs a single step, returning its record.
+    pub fn step(&mut self) -> &Record {
+        if self.records.is_empty() {
+            let record = self.record(Vec::new());
+            self.records.push(record);
+        }
+        self.step += 1;
+        if let Some(process) = &mut self.price {
+            process.advance(&mut self.price_rng);
+            self.reference_price = Some(process.price());
+        }
+        let mut trades = Vec::new();
+        let history = self.first_kept();
+        for (agent, (actor, account)) in self
+            .agents
+            .iter_mut()
+            .zip(self.accounts.iter_mut())
+            .enumerate()
+        {
+            let mut market = Market {
+                step: self.step,
+                timestamp: self.step * self.step_seconds,
+                agent,
+                reference_price: self.reference_price,
+                history: &self.records[history..],
+                rng: &mut self.rng,
+                pools: &mut self.pools,
+                account,
+                trades: &mut trades,
+            };
+            actor.act(&mut market);
+        }
+        let record = self.record(trades);
+        self.records.push(record);
+        // Records beyond the limit are dropped in batches, so that each step moves the kept
+        // records at most once on average.
+        if let Some(limit) = self.record_limit {
+            if self.records.len() >= limit.saturating_mul(2) {
+                self.records.drain(..self.records.len() - limit);
+            }
+        }
+        &self.records[self.records.len() - 1]
+    }
+
+    /// Runs the given number of steps.
+    pub fn run(&mut self, steps: u64) {
+        for _ in 0..steps {
+            self.step();
+        }
+    }
+
+    /// Captures the state of the simulation, taking the events logged by the pools.
+    fn record(&mut self, trades: Vec<Trade>) -> Record {
+        let pools = self
+            .pools
+            .iter_mut()
+            .map(|pool| {
+                let (reserves_a, reserves_b) = pool.reserves();
+                PoolState {
+                    reserves_a,
+                    reserves_b,
+                    spot_price: pool.spot_price(),
+                    pid_terms: pool.pid_terms(),
+                    parameters: pool
+                        .parameters()
+                        .into_iter()
+                        .map(|(name, value)| (name.to_string(), value))
+                        .collect(),
+                    events: pool.take_events(),
+                }
+            })
+            .collect();
+        Record {
+            step: self.step,
+            timestamp: self.timestamp(),
+            reference_price: self.reference_price,
+            pools,
+            trades,
+            accounts: self.accounts.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpmm::ConstantProductMarketMaker, csmm::ConstantSumMarketMaker, pidmm::PIDMarketMaker,
+    };
+    use rand::Rng;
+    use rust_decimal_macros::dec;
+
+    /// Buys a random amount of a random side of the first pool.
+    struct Random;
+
+    impl Agent for Random {
+        fn name(&self) -> &str {
+            "random"
+        }
+
+        fn act(&mut self, market: &mut Market<'_>) {
+            let side = if market.rng().gen() { Side::A } else { Side::B };
+            let amount = Decimal::from(market.rng().gen_range(1..=10));
+            market.swap(0, side, amount);
+        }
+    }
+
+    fn simulation(seed: u64) -> Simulation {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.add_liquidity(dec!(1000), dec!(1000));
+        let mut simulation = Simulation::new(seed);
+        simulation.add_pool(pool);
+        simulation.add_agent(Random);
+        simulation.add_agent(Random);
+        simulation
+    }
+
+    #[test]
+    fn test_record_limit() {
+        let mut full = simulation(7);
+        let mut limited = simulation(7);
+        limited.set_record_limit(Some(10));
+        full.run(50);
+        limited.run(50);
+
+        // Only the last records are kept, identical to those of an unlimited run.
+        assert_eq!(limited.records().len(), 10);
+        assert_eq!(limited.records(), &full.records()[41..]);
+        assert_eq!(limited.records()[0].step, 41);
+        assert_eq!(limited.step_count(), 50);
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let mut first = simulation(7);
+        let mut second = simulation(7);
+        first.run(50);
+        second.run(50);
+
+        // The same seed replays the same trades.
+        assert_eq!(first.records(), second.records());
+        assert_eq!(first.records().len(), 51);
+
+        // Another seed takes another path.
+        let mut other = simulation(8);
+        other.run(50);
+        assert_ne!(first.pools()[0].reserves(), other.pools()[0].reserves());
+    }
+
+    #[test]
+    fn test_accounts_settle_trades() {
+        let mut simulation = simulation(1);
+        simulation.set_step_seconds(60);
+        simulation.run(20);
+
+        // The tokens taken out by the agents are the tokens missing from the pool.
+        let (reserves_a, reserves_b) = simulation.pools()[0].reserves();
+        let accounts = simulation.accounts();
+        assert_eq!(
+            accounts[0].token_a + accounts[1].token_a,
+            dec!(1000) - reserves_a
+        );
+        assert_eq!(
+            accounts[0].token_b + accounts[1].token_b,
+            dec!(1000) - reserves_b
+        );
+
+        // Each step records both agents' trades and the new reserves.
+        let record = &simulation.records()[20];
+        assert_eq!((record.step, record.timestamp), (20, 1200));
+        assert_eq!(record.trades.len(), 2);
+        assert_eq!(record.pools[0].reserves_a, reserves_a);
+        assert_eq!(record.pools[0].events.len(), 4);
+    }
+
+    #[test]
+    fn test_records_pid_terms() {
+        let mut pool = PIDMarketMaker::default();
+        pool.set_reserves(dec!(1000), dec!(1000));
+        let mut simulation = Simulation::new(3);
+        simulation.add_pool(pool);
+        simulation.add_agent(Random);
+
+        // The initial record holds the starting state, later ones the controller's terms.
+        let record = simulation.step().clone();
+        assert!(record.pools[0].pid_terms.is_some());
+        let initial = &simulation.records()[0];
+        assert_eq!(initial.step, 0);
+        assert!(initial.trades.is_empty());
+        assert_eq!(initial.pools[0].reserves_a, dec!(1000));
+    }
+
+    #[test]
+    fn test_price_path_ignores_agents() {
+        let process = price::GeometricBrownian::new(dec!(1), dec!(0), dec!(0.01));
+        let mut quiet = Simulation::new(9);
+        quiet.set_price_process(process.clone());
+        let mut busy = simulation(9);
+        busy.set_price_process(process);
+        quiet.run(20);
+        busy.run(20);
+
+        // The reference price follows the same path whatever the agents draw.
+        let path = |simulation: &Simulation| -> Vec<_> {
+            simulation
+                .records()
+                .iter()
+                .map(|record| record.reference_price)
+                .collect()
+        };
+        assert_eq!(path(&quiet), path(&busy));
+        assert_eq!(quiet.records()[0].reference_price, Some(dec!(1)));
+        assert_ne!(quiet.reference_price(), Some(dec!(1)));
+    }
+
+    #[test]
+    fn test_swaps_beyond_reserves_are_refused() {
+        /// Tries to empty the first pool on both sides.
+        struct Drain;
+
+        impl Agent for Drain {
+            fn name(&self) -> &str {
+                "drain"
+            }
+
+            fn act(&mut self, market: &mut Market<'_>) {
+                assert_eq!(market.swap(0, Side::A, dec!(1000)), Decimal::ZERO);
+                assert_eq!(market.swap(0, Side::B, dec!(1500)), Decimal::ZERO);
+            }
+        }
+
+        let mut pool = ConstantSumMarketMaker::default();
+        pool.add_liquidity(dec!(1000), dec!(1000));
+        let mut simulation = Simulation::new(0);
+        simulation.add_pool(pool);
+        simulation.add_agent(Drain);
+        let record = simulation.step();
+
+        // Neither trade happened.
+        assert!(record.trades.is_empty());
+        assert_eq!(record.accounts[0], Account::default());
+        assert_eq!(simulation.pools()[0].reserves(), (dec!(1000), dec!(1000)));
+    }
+}
@@ -0,0 +1,334 @@
+// External price processes
+
+use rand::{rngs::StdRng, Rng};
+use rust_decimal::prelude::*;
+use std::{fmt, io};
+
+/// An exogenous price of token A in terms of token B, advanced once per step.
+///
+/// Parameters of the processes are expressed per step, so that a drift of `0.001` moves the
+/// price by about 0.1% each step.
+pub trait PriceProcess {
+    /// Returns the current price.
+    fn price(&self) -> Decimal;
+
+    /// Advances the price by one step, drawing any randomness from `rng`.
+    fn advance(&mut self, rng: &mut StdRng);
+}
+
+/// Draws a standard normal variate, by the Box-Muller transform.
+fn normal(rng: &mut StdRng) -> f64 {
+    let u: f64 = 1.0 - rng.gen_range(0.0..1.0);
+    let v: f64 = rng.gen_range(0.0..1.0);
+    (-2.0 * u.ln()).sqrt() * (2.0 * core::f64::consts::PI * v).cos()
+}
+
+/// Converts a decimal parameter to a float for sampling.
+fn float(value: Decimal) -> f64 {
+    value.to_f64().unwrap_or(0.0)
+}
+
+/// Multiplies a price by `exp(log_return)`, keeping it unchanged if the result is not a
+/// decimal.
+fn grow(price: Decimal, log_return: f64) -> Decimal {
+    Decimal::from_f64(log_return.exp())
+        .and_then(|factor| price.checked_mul(factor))
+        .map_or(price, |price| price.round_dp(12))
+}
+
+/// Geometric Brownian motion
+///
+/// The log price moves each step by a normal variate with mean `drift - volatility^2 / 2` and
+/// standard deviation `volatility`, so that the price grows by `drift` per step on average.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeometricBrownian {
+    pub price: Decimal,
+    pub drift: Decimal,
+    pub volatility: Decimal,
+}
+
+impl GeometricBrownian {
+    /// Creates a process starting at `price`.
+    pub fn new(price: Decimal, drift: Decimal, volatility: Decimal) -> Self {
+        Self {
+            price,
+            drift,
+            volatility,
+        }
+    }
+
+    /// Draws the log return of a step.
+    fn log_return(&self, rng: &mut StdRng) -> f64 {
+        let volatility = float(self.volatility);
+        float(self.drift) - volatility * volatility / 2.0 + volatility * normal(rng)
+    }
+}
+
+impl PriceProcess for GeometricBrownian {
+    fn price(&self) -> Decimal {
+        self.price
+    }
+
+    fn advance(&mut self, rng: &mut StdRng) {
+        self.price = grow(self.price, self.log_return(rng));
+    }
+}
+
+/// Jump diffusion
+///
+/// Merton's model: a geometric Brownian motion to which jumps are added. The number of jumps in
+/// a step is Poisson distributed with mean `jump_intensity`, and each jump moves the log price
+/// by a normal variate with mean `jump_mean` and standard deviation `jump_volatility`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JumpDiffusion {
+    pub diffusion: GeometricBrownian,
+    pub jump_intensity: Decimal,
+    pub jump_mean: Decimal,
+    pub jump_volatility: Decimal,
+}
+
+impl JumpDiffusion {
+    /// Adds jumps to a geometric Brownian motion.
+    pub fn new(
+        diffusion: GeometricBrownian,
+        jump_intensity: Decimal,
+        jump_mean: Decimal,
+        jump_volatility: Decimal,
+    ) -> Self {
+        Self {
+            diffusion,
+            jump_intensity,
+            jump_mean,
+            jump_volatility,
+        }
+    }
+}
+
+impl PriceProcess for JumpDiffusion {
+    fn price(&self) -> Decimal {
+        self.diffusion.price
+    }
+
+    fn advance(&mut self, rng: &mut StdRng) {
+        let mut log_return = self.diffusion.log_return(rng);
+        // Knuth's multiplication method draws the number of jumps.
+        let threshold = (-float(self.jump_intensity)).exp();
+        let mut product: f64 = rng.gen_range(0.0..1.0);
+        while product > threshold {
+            log_return += float(self.jump_mean) + float(self.jump_volatility) * normal(rng);
+            product *= rng.gen_range(0.0..1.0);
+        }
+        self.diffusion.price = grow(self.diffusion.price, log_return);
+    }
+}
+
+/// Mean-reverting Ornstein-Uhlenbeck process
+///
+/// The log price reverts toward the log of `mean` by `reversion` of the gap each step, and is
+/// shaken by a normal variate with standard deviation `volatility`. Reverting in log space
+/// keeps the price positive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrnsteinUhlenbeck {
+    pub price: Decimal,
+    pub mean: Decimal,
+    pub reversion: Decimal,
+    pub volatility: Decimal,
+}
+
+impl OrnsteinUhlenbeck {
+    /// Creates a process starting at `price` and reverting toward `mean`.
+    pub fn new(price: Decimal, mean: Decimal, reversion: Decimal, volatility: Decimal) -> Self {
+        Self {
+            price,
+            mean,
+            reversion,
+            volatility,
+        }
+    }
+}
+
+impl PriceProcess for OrnsteinUhlenbeck {
+    fn price(&self) -> Decimal {
+        self.price
+    }
+
+    fn advance(&mut self, rng: &mut StdRng) {
+        let (price, mean) = (float(self.price), float(self.mean));
+        if price <= 0.0 || mean <= 0.0 {
+            return;
+        }
+        let gap = mean.ln() - price.ln();
+        let log_return = float(self.reversion) * gap + float(self.volatility) * normal(rng);
+        self.price = grow(self.price, log_return);
+    }
+}
+
+/// Errors returned when reading a price series.
+#[derive(Debug)]
+pub enum SeriesError {
+    /// The series could not be read.
+    Io(io::Error),
+    /// A line does not end with a positive price.
+    InvalidPrice { line: usize },
+    /// The series holds no price.
+    Empty,
+}
+
+impl fmt::Display for SeriesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeriesError::Io(error) => write!(f, "failed to read the price series: {}", error),
+            SeriesError::InvalidPrice { line } => write!(f, "invalid price on line {}", line),
+            SeriesError::Empty => write!(f, "the price series is empty"),
+        }
+    }
+}
+
+impl std::error::Error for SeriesError {}
+
+impl From<io::Error> for SeriesError {
+    fn from(error: io::Error) -> Self {
+        SeriesError::Io(error)
+    }
+}
+
+/// Replay of a recorded price series
+///
+/// Steps through the prices one per step, then holds the last one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Series {
+    prices: Vec<Decimal>,
+    index: usize,
+}
+
+impl Series {
+    /// Creates a replay of the given prices. Fails if there are none.
+    pub fn new(prices: Vec<Decimal>) -> Result<Self, SeriesError> {
+        if prices.is_empty() {
+            return Err(SeriesError::Empty);
+        }
+        Ok(Self { prices, index: 0 })
+    }
+
+    /// Reads prices from CSV, one per line, taking the last column of each line.
+    ///
+    /// A header line is skipped, as are empty lines, so that both a bare list of prices and a
+    /// `timestamp,price` export can be read.
+    pub fn from_csv<R: io::BufRead>(reader: R) -> Result<Self, SeriesError> {
+        let mut prices = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let field = line.rsplit(',').next().unwrap_or_default().trim();
+            if field.is_empty() {
+                continue;
+            }
+            match Decimal::from_str(field) {
+                Ok(price) if price > Decimal::ZERO => prices.push(price),
+                Err(_) if index == 0 => continue,
+                _ => return Err(SeriesError::InvalidPrice { line: index + 1 }),
+            }
+        }
+        Self::new(prices)
+    }
+
+    /// Returns `true` once the last price has been reached.
+    pub fn is_finished(&self) -> bool {
+        self.index + 1 >= self.prices.len()
+    }
+}
+
+impl PriceProcess for Series {
+    fn price(&self) -> Decimal {
+        self.prices[self.index]
+    }
+
+    fn advance(&mut self, _rng: &mut StdRng) {
+        if !self.is_finished() {
+            self.index += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rust_decimal_macros::dec;
+
+    /// Runs a process for the given number of steps, returning the log returns.
+    fn log_returns<P: PriceProcess>(process: &mut P, steps: usize) -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(5);
+        (0..steps)
+            .map(|_| {
+                let before = float(process.price());
+                process.advance(&mut rng);
+                (float(process.price()) / before).ln()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_geometric_brownian() {
+        let mut process = GeometricBrownian::new(dec!(100), dec!(0), dec!(0.01));
+        let returns = log_returns(&mut process, 10_000);
+
+        // The log returns have the configured volatility.
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance =
+            returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64;
+        assert!(
+            (variance.sqrt() - 0.01).abs() < 0.0005,
+            "{}",
+            variance.sqrt()
+        );
+        assert!(mean.abs() < 0.0005, "{}", mean);
+    }
+
+    #[test]
+    fn test_jump_diffusion() {
+        let diffusion = GeometricBrownian::new(dec!(100), dec!(0), dec!(0.001));
+        let mut process = JumpDiffusion::new(diffusion, dec!(0.05), dec!(-0.1), dec!(0.01));
+        let returns = log_returns(&mut process, 2_000);
+
+        // About one step in twenty jumps by around -10%.
+        let jumps = returns.iter().filter(|r| **r < -0.05).count();
+        assert!((60..140).contains(&jumps), "{}", jumps);
+    }
+
+    #[test]
+    fn test_ornstein_uhlenbeck_reverts() {
+        let mut process = OrnsteinUhlenbeck::new(dec!(150), dec!(100), dec!(0.1), dec!(0.001));
+        log_returns(&mut process, 200);
+
+        // The price is pulled back to the mean.
+        assert!(
+            (process.price() - dec!(100)).abs() < dec!(2),
+            "{}",
+            process.price()
+        );
+    }
+
+    #[test]
+    fn test_series_from_csv() {
+        let csv = "timestamp,price\n0,1.5\n60,1.6\n\n120,1.55\n";
+        let mut series = Series::from_csv(csv.as_bytes()).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // The prices are replayed in order, then the last one is held.
+        let mut prices = vec![series.price()];
+        for _ in 0..3 {
+            series.advance(&mut rng);
+            prices.push(series.price());
+        }
+        assert_eq!(prices, vec![dec!(1.5), dec!(1.6), dec!(1.55), dec!(1.55)]);
+        assert!(series.is_finished());
+
+        // Malformed lines are reported.
+        let error = Series::from_csv("1.5\nabc\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, SeriesError::InvalidPrice { line: 2 }));
+        assert!(matches!(
+            Series::from_csv("price\n".as_bytes()),
+            Err(SeriesError::Empty)
+        ));
+    }
+}
@@ -0,0 +1,462 @@
+// Trade log replay
+
+use crate::{LiquidityPool, Side};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// An action of a trade log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+    /// A swap taking `amount_out` of the side's token out of the pool.
+    Swap {
+        side: Side,
+        amount_out: Decimal,
+        /// The amount of the other token the trade recorded paying, if the log has it.
+        #[serde(default)]
+        amount_in: Option<Decimal>,
+    },
+    /// A deposit of liquidity.
+    Mint {
+        amount_a: Decimal,
+        amount_b: Decimal,
+    },
+    /// A withdrawal of liquidity.
+    Burn {
+        amount_a: Decimal,
+        amount_b: Decimal,
+    },
+}
+
+/// An entry of a trade log: an action and, if the log has them, the reserves recorded after
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(flatten)]
+    pub action: Action,
+    #[serde(default)]
+    pub reserves_a: Option<Decimal>,
+    #[serde(default)]
+    pub reserves_b: Option<Decimal>,
+}
+
+/// Errors returned when reading a trade log.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The log could not be read.
+    Io(io::Error),
+    /// A line of a JSON Lines log is not a valid entry.
+    Json {
+        line: usize,
+        error: serde_json::Error,
+    },
+    /// A line of a CSV log is not a valid entry.
+    InvalidEntry { line: usize },
+    /// The log file is neither `.csv` nor `.jsonl`.
+    UnknownFormat(PathBuf),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "failed to read the trade log: {}", error),
+            ReplayError::Json { line, error } => {
+                write!(f, "invalid entry on line {}: {}", line, error)
+            }
+            ReplayError::InvalidEntry { line } => write!(f, "invalid entry on line {}", line),
+            ReplayError::UnknownFormat(path) => {
+                write!(f, "{} is neither a .csv nor a .jsonl file", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+/// Reads a trade log from JSON Lines, one entry per line, skipping empty lines.
+///
+/// Each line is an object such as
+/// `{"kind":"swap","side":"A","amount_out":10,"amount_in":10.3,"reserves_a":990}`.
+pub fn read_jsonl<R: BufRead>(reader: R) -> Result<Vec<Entry>, ReplayError> {
+    let mut entries = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|error| ReplayError::Json {
+            line: index + 1,
+            error,
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Reads a trade log from CSV.
+///
+/// The first line names the columns, among `timestamp`, `kind`, `side`, `amount_out`,
+/// `amount_in`, `amount_a`, `amount_b`, `reserves_a` and `reserves_b`, in any order. Unknown
+/// columns are ignored, as are empty lines, and empty cells are missing values.
+pub fn read_csv<R: BufRead>(reader: R) -> Result<Vec<Entry>, ReplayError> {
+    let mut lines = reader.lines();
+    let Some(header) = lines.next().transpose()? else {
+        return Ok(Vec::new());
+    };
+    let columns: Vec<String> = header
+        .split(',')
+        .map(|column| column.trim().to_string())
+        .collect();
+    let mut entries = Vec::new();
+    for (index, line) in lines.enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let cells: HashMap<&str, &str> = columns
+            .iter()
+            .map(String::as_str)
+            .zip(line.split(',').map(str::trim))
+            .filter(|(_, cell)| !cell.is_empty())
+            .collect();
+        let entry = csv_entry(&cells).ok_or(ReplayError::InvalidEntry { line: index + 2 })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Builds an entry from the cells of a CSV line, keyed by column.
+fn csv_entry(cells: &HashMap<&str, &str>) -> Option<Entry> {
+    // A present cell must parse; an absent one is `None`.
+    let decimal = |column: &str| -> Option<Option<Decimal>> {
+        cells
+            .get(column)
+            .map_or(Some(None), |cell| Decimal::from_str(cell).ok().map(Some))
+    };
+    let action = match *cells.get("kind")? {
+        "swap" => Action::Swap {
+            side: match *cells.get("side")? {
+                "A" | "a" => Side::A,
+                "B" | "b" => Side::B,
+                _ => return None,
+            },
+            amount_out: decimal("amount_out")??,
+            amount_in: decimal("amount_in")?,
+        },
+        "mint" => Action::Mint {
+            amount_a: decimal("amount_a")??,
+            amount_b: decimal("amount_b")??,
+        },
+        "burn" => Action::Burn {
+            amount_a: decimal("amount_a")??,
+            amount_b: decimal("amount_b")??,
+        },
+        _ => return None,
+    };
+    let timestamp = match cells.get("timestamp") {
+        Some(cell) => Some(cell.parse().ok()?),
+        None => None,
+    };
+    Some(Entry {
+        timestamp,
+        action,
+        reserves_a: decimal("reserves_a")?,
+        reserves_b: decimal("reserves_b")?,
+    })
+}
+
+/// Loads a trade log from a `.csv` or `.jsonl` file.
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Entry>, ReplayError> {
+    let path = path.as_ref();
+    let reader = BufReader::new(fs::File::open(path)?);
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("csv") => read_csv(reader),
+        Some("jsonl") => read_jsonl(reader),
+        _ => Err(ReplayError::UnknownFormat(path.to_path_buf())),
+    }
+}
+
+/// The result of applying an entry of a log to a pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+    /// The position of the entry in the log.
+    pub index: usize,
+    /// `false` if the pool could not execute the swap, which then left it unchanged.
+    pub executed: bool,
+    /// The amount the pool charged for a swap.
+    pub amount_in: Option<Decimal>,
+    /// The amount the log recorded paying for a swap.
+    pub recorded_amount_in: Option<Decimal>,
+    pub reserves: (Decimal, Decimal),
+    pub recorded_reserves: (Option<Decimal>, Option<Decimal>),
+    /// The spot price of the pool after the entry.
+    pub spot_price: Decimal,
+}
+
+impl Outcome {
+    /// Returns the largest relative difference between the pool's outcome and the recorded
+    /// one, over the swap cost and the reserves. Returns `None` if the entry records neither.
+    ///
+    /// A swap the pool could not execute diverges by one, as the log records it happening.
+    pub fn divergence(&self) -> Option<Decimal> {
+        if !self.executed {
+            return Some(Decimal::ONE);
+        }
+        let relative = |value: Decimal, recorded: Decimal| {
+            if recorded == Decimal::ZERO {
+                value.abs()
+            } else {
+                ((value - recorded) / recorded).abs()
+            }
+        };
+        [
+            (self.amount_in, self.recorded_amount_in),
+            (Some(self.reserves.0), self.recorded_reserves.0),
+            (Some(self.reserves.1), self.recorded_reserves.1),
+        ]
+        .into_iter()
+        .filter_map(|(value, recorded)| Some(relative(value?, recorded?)))
+        .max()
+    }
+}
+
+/// The result of replaying a log against a pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+    /// The amount of token A taken out of the pool by swaps.
+    pub volume_a: Decimal,
+    /// The amount of token B taken out of the pool by swaps.
+    pub volume_b: Decimal,
+    /// The amount of token A paid into the pool for token B.
+    pub paid_a: Decimal,
+    /// The amount of token B paid into the pool for token A.
+    pub paid_b: Decimal,
+    /// The number of swaps the pool could not execute.
+    pub rejected: usize,
+}
+
+impl Report {
+    /// Returns the outcomes diverging from the log by more than `tolerance`, relatively.
+    pub fn divergences(&self, tolerance: Decimal) -> impl Iterator<Item = &Outcome> {
+        self.outcomes.iter().filter(move |outcome| {
+            outcome
+                .divergence()
+                .is_some_and(|divergence| divergence > tolerance)
+        })
+    }
+
+    /// Returns the average price paid for token A, in token B. Returns `None` if no token A was
+    /// bought.
+    pub fn average_price_a(&self) -> Option<Decimal> {
+        (self.volume_a > Decimal::ZERO).then(|| self.paid_b / self.volume_a)
+    }
+
+    /// Returns the average price paid for token B, in token A. Returns `None` if no token B was
+    /// bought.
+    pub fn average_price_b(&self) -> Option<Decimal> {
+        (self.volume_b > Decimal::ZERO).then(|| self.paid_a / self.volume_b)
+    }
+}
+
+/// Applies the entries of a log to a pool, in order.
+///
+/// The pool trades from its own state rather than the recorded reserves, so that once it
+/// diverges from the log the divergence carries through to later entries. Swaps taking as much
+/// as the whole reserve are not executed.
+pub fn replay<P: LiquidityPool + ?Sized>(pool: &mut P, entries: &[Entry]) -> Report {
+    let mut report = Report {
+        outcomes: Vec::with_capacity(entries.len()),
+        volume_a: Decimal::ZERO,
+        volume_b: Decimal::ZERO,
+        paid_a: Decimal::ZERO,
+        paid_b: Decimal::ZERO,
+        rejected: 0,
+    };
+    for (index, entry) in entries.iter().enumerate() {
+        let mut executed = true;
+        let (mut amount_in, mut recorded_amount_in) = (None, None);
+        match entry.action {
+            Action::Swap {
+                side,
+                amount_out,
+                amount_in: recorded,
+            } => {
+                let (reserves_a, reserves_b) = pool.reserves();
+                let reserve = match side {
+                    Side::A => reserves_a,
+                    Side::B => reserves_b,
+                };
+                let cost = if amount_out < reserve {
+                    pool.swap(side, amount_out)
+                } else {
+                    Decimal::ZERO
+                };
+                executed = cost > Decimal::ZERO;
+                if executed {
+                    match side {
+                        Side::A => {
+                            report.volume_a += amount_out;
+                            report.paid_b += cost;
+                        }
+                        Side::B => {
+                            report.volume_b += amount_out;
+                            report.paid_a += cost;
+                        }
+                    }
+                    amount_in = Some(cost);
+                } else {
+                    report.rejected += 1;
+                }
+                recorded_amount_in = recorded;
+            }
+            Action::Mint { amount_a, amount_b } => pool.add_liquidity(amount_a, amount_b),
+            Action::Burn { amount_a, amount_b } => pool.remove_liquidity(amount_a, amount_b),
+        }
+        report.outcomes.push(Outcome {
+            index,
+            executed,
+            amount_in,
+            recorded_amount_in,
+            reserves: pool.reserves(),
+            recorded_reserves: (entry.reserves_a, entry.reserves_b),
+            spot_price: pool.spot_price(),
+        });
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cpmm::ConstantProductMarketMaker, csmm::ConstantSumMarketMaker};
+    use rust_decimal_macros::dec;
+
+    /// Records a log of trades on a constant product pool.
+    fn log() -> Vec<Entry> {
+        let mut pool = ConstantProductMarketMaker::default();
+        pool.set_reserves(dec!(1000), dec!(1000));
+        let mut entries = Vec::new();
+        for (index, (side, amount_out)) in
+            [(Side::A, dec!(10)), (Side::B, dec!(25)), (Side::A, dec!(5))]
+                .into_iter()
+                .enumerate()
+        {
+            let amount_in = pool.swap(side, amount_out);
+            let (reserves_a, reserves_b) = pool.reserves();
+            entries.push(Entry {
+                timestamp: Some(index as u64),
+                action: Action::Swap {
+                    side,
+                    amount_out,
+                    amount_in: Some(amount_in),
+                },
+                reserves_a: Some(reserves_a),
+                reserves_b: Some(reserves_b),
+            });
+        }
+        entries
+    }
+
+    #[test]
+    fn test_replay() {
+        let entries = log();
+
+        // The curve that produced the log reproduces it exactly.
+        let mut cpmm = ConstantProductMarketMaker::default();
+        cpmm.set_reserves(dec!(1000), dec!(1000));
+        let report = replay(&mut cpmm, &entries);
+        assert_eq!(report.divergences(Decimal::ZERO).count(), 0);
+        assert_eq!(report.volume_a, dec!(15));
+        assert_eq!(report.rejected, 0);
+
+        // A constant sum pool prices the same flow differently, diverging from the first trade.
+        let mut csmm = ConstantSumMarketMaker::default();
+        csmm.set_reserves(dec!(1000), dec!(1000));
+        let replayed = replay(&mut csmm, &entries);
+        assert_eq!(replayed.divergences(dec!(0.001)).next().unwrap().index, 0);
+        assert!(replayed.average_price_a() < report.average_price_a());
+        assert_eq!(replayed.volume_b, dec!(25));
+
+        // Swaps the pool cannot fill are rejected.
+        let mut small = ConstantProductMarketMaker::default();
+        small.set_reserves(dec!(8), dec!(8));
+        let report = replay(&mut small, &entries);
+        assert!(!report.outcomes[0].executed);
+        assert_eq!(report.outcomes[0].divergence(), Some(Decimal::ONE));
+        assert_eq!(report.rejected, 2);
+    }
+
+    #[test]
+    fn test_read_jsonl() {
+        let jsonl = r#"{"kind":"swap","side":"A","amount_out":10,"amount_in":"10.2"}
+
+{"timestamp":5,"kind":"mint","amount_a":1,"amount_b":2,"reserves_a":991}
+"#;
+        let entries = read_jsonl(jsonl.as_bytes()).unwrap();
+        assert_eq!(
+            entries[0].action,
+            Action::Swap {
+                side: Side::A,
+                amount_out: dec!(10),
+                amount_in: Some(dec!(10.2)),
+            }
+        );
+        assert_eq!(entries[1].timestamp, Some(5));
+        assert_eq!(entries[1].reserves_a, Some(dec!(991)));
+        assert_eq!(entries[1].reserves_b, None);
+
+        let error = read_jsonl(r#"{"kind":"swap"}"#.as_bytes()).unwrap_err();
+        assert!(matches!(error, ReplayError::Json { line: 1, .. }));
+    }
+
+    #[test]
+    fn test_read_csv() {
+        let csv = "kind,side,amount_out,amount_in,amount_a,amount_b\n\
+                   swap,B,4,,,\n\
+                   burn,,,,1.5,2\n";
+        let entries = read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry {
+                    timestamp: None,
+                    action: Action::Swap {
+                        side: Side::B,
+                        amount_out: dec!(4),
+                        amount_in: None,
+                    },
+                    reserves_a: None,
+                    reserves_b: None,
+                },
+                Entry {
+                    timestamp: None,
+                    action: Action::Burn {
+                        amount_a: dec!(1.5),
+                        amount_b: dec!(2),
+                    },
+                    reserves_a: None,
+                    reserves_b: None,
+                },
+            ]
+        );
+
+        let error = read_csv("kind,side\nswap,C\n".as_bytes()).unwrap_err();
+        assert!(matches!(error, ReplayError::InvalidEntry { line: 2 }));
+    }
+}
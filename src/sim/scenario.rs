@@ -0,0 +1,431 @@
+// Declarative simulation scenarios
+
+use super::{
+    agents::{Arbitrageur, LiquidityProvider, Momentum, PoissonNoise, UniformNoise, Whale},
+    price::{
+        GeometricBrownian, JumpDiffusion, OrnsteinUhlenbeck, PriceProcess, Series, SeriesError,
+    },
+    Agent, Simulation,
+};
+use crate::{fee::WithFee, registry::PoolConfig, LiquidityPool};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::{self, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// A pool of a scenario, with its curve and initial reserves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolSpec {
+    pub curve: PoolConfig,
+    pub reserves_a: Decimal,
+    pub reserves_b: Decimal,
+    /// The swap fee, as a fraction of the cost of a swap. Pools charge no fee by default.
+    #[serde(default)]
+    pub fee: Decimal,
+}
+
+impl PoolSpec {
+    /// Creates the pool with its initial reserves.
+    pub fn build(&self) -> Box<dyn LiquidityPool> {
+        let mut pool = self.curve.build();
+        pool.set_reserves(self.reserves_a, self.reserves_b);
+        if self.fee > Decimal::ZERO {
+            return Box::new(WithFee::new(pool, self.fee));
+        }
+        pool
+    }
+}
+
+/// An agent of a scenario, trading on the pool with the given index.
+///
+/// See the agents of the same names in [`agents`](super::agents) for the meaning of the
+/// parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AgentConfig {
+    UniformNoise {
+        pool: usize,
+        min_amount: Decimal,
+        max_amount: Decimal,
+    },
+    PoissonNoise {
+        pool: usize,
+        rate: Decimal,
+        mean_amount: Decimal,
+    },
+    Momentum {
+        pool: usize,
+        lookback: usize,
+        threshold: Decimal,
+        amount: Decimal,
+    },
+    Arbitrageur {
+        pool: usize,
+        #[serde(default)]
+        min_profit: Decimal,
+    },
+    LiquidityProvider {
+        pool: usize,
+        share: Decimal,
+        enter_apr: Decimal,
+        exit_apr: Decimal,
+        window: usize,
+    },
+    Whale {
+        pool: usize,
+        probability: Decimal,
+        fraction: Decimal,
+    },
+}
+
+impl AgentConfig {
+    /// Returns the index of the pool the agent trades on.
+    pub fn pool(&self) -> usize {
+        match *self {
+            AgentConfig::UniformNoise { pool, .. }
+            | AgentConfig::PoissonNoise { pool, .. }
+            | AgentConfig::Momentum { pool, .. }
+            | AgentConfig::Arbitrageur { pool, .. }
+            | AgentConfig::LiquidityProvider { pool, .. }
+            | AgentConfig::Whale { pool, .. } => pool,
+        }
+    }
+
+    /// Creates the agent.
+    pub fn build(&self) -> Box<dyn Agent> {
+        match *self {
+            AgentConfig::UniformNoise {
+                pool,
+                min_amount,
+                max_amount,
+            } => Box::new(UniformNoise::new(pool, min_amount, max_amount)),
+            AgentConfig::PoissonNoise {
+                pool,
+                rate,
+                mean_amount,
+            } => Box::new(PoissonNoise::new(pool, rate, mean_amount)),
+            AgentConfig::Momentum {
+                pool,
+                lookback,
+                threshold,
+                amount,
+            } => Box::new(Momentum::new(pool, lookback, threshold, amount)),
+            AgentConfig::Arbitrageur { pool, min_profit } => {
+                Box::new(Arbitrageur { pool, min_profit })
+            }
+            AgentConfig::LiquidityProvider {
+                pool,
+                share,
+                enter_apr,
+                exit_apr,
+                window,
+            } => Box::new(LiquidityProvider::new(
+                pool, share, enter_apr, exit_apr, window,
+            )),
+            AgentConfig::Whale {
+                pool,
+                probability,
+                fraction,
+            } => Box::new(Whale::new(pool, probability, fraction)),
+        }
+    }
+}
+
+/// The process driving the reference price of a scenario.
+///
+/// See the processes of the same names in [`price`](super::price) for the meaning of the
+/// parameters.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PriceConfig {
+    GeometricBrownian {
+        price: Decimal,
+        drift: Decimal,
+        volatility: Decimal,
+    },
+    JumpDiffusion {
+        price: Decimal,
+        drift: Decimal,
+        volatility: Decimal,
+        jump_intensity: Decimal,
+        jump_mean: Decimal,
+        jump_volatility: Decimal,
+    },
+    OrnsteinUhlenbeck {
+        price: Decimal,
+        mean: Decimal,
+        reversion: Decimal,
+        volatility: Decimal,
+    },
+    /// Replays the prices of a CSV file.
+    Series { path: PathBuf },
+}
+
+impl PriceConfig {
+    /// Creates the process. Series are read relative to `base`, unless their path is absolute.
+    pub fn build(&self, base: &Path) -> Result<Box<dyn PriceProcess>, ScenarioError> {
+        Ok(match *self {
+            PriceConfig::GeometricBrownian {
+                price,
+                drift,
+                volatility,
+            } => Box::new(GeometricBrownian::new(price, drift, volatility)),
+            PriceConfig::JumpDiffusion {
+                price,
+                drift,
+                volatility,
+                jump_intensity,
+                jump_mean,
+                jump_volatility,
+            } => Box::new(JumpDiffusion::new(
+                GeometricBrownian::new(price, drift, volatility),
+                jump_intensity,
+                jump_mean,
+                jump_volatility,
+            )),
+            PriceConfig::OrnsteinUhlenbeck {
+                price,
+                mean,
+                reversion,
+                volatility,
+            } => Box::new(OrnsteinUhlenbeck::new(price, mean, reversion, volatility)),
+            PriceConfig::Series { ref path } => {
+                let file = fs::File::open(base.join(path))?;
+                Box::new(Series::from_csv(BufReader::new(file))?)
+            }
+        })
+    }
+}
+
+/// Errors returned when loading or building a scenario.
+#[derive(Debug)]
+pub enum ScenarioError {
+    /// A file could not be read.
+    Io(io::Error),
+    /// The scenario is not valid JSON.
+    Json(serde_json::Error),
+    /// The scenario is not valid TOML.
+    Toml(toml::de::Error),
+    /// The scenario file is neither `.json` nor `.toml`.
+    UnknownFormat(PathBuf),
+    /// The price series could not be read.
+    Series(SeriesError),
+    /// An agent trades on a pool the scenario does not define.
+    UnknownPool { agent: usize, pool: usize },
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(error) => write!(f, "failed to read the scenario: {}", error),
+            ScenarioError::Json(error) => write!(f, "invalid JSON scenario: {}", error),
+            ScenarioError::Toml(error) => write!(f, "invalid TOML scenario: {}", error),
+            ScenarioError::UnknownFormat(path) => {
+                write!(f, "{} is neither a .json nor a .toml file", path.display())
+            }
+            ScenarioError::Series(error) => write!(f, "{}", error),
+            ScenarioError::UnknownPool { agent, pool } => {
+                write!(f, "agent {} trades on unknown pool {}", agent, pool)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<io::Error> for ScenarioError {
+    fn from(error: io::Error) -> Self {
+        ScenarioError::Io(error)
+    }
+}
+
+impl From<SeriesError> for ScenarioError {
+    fn from(error: SeriesError) -> Self {
+        ScenarioError::Series(error)
+    }
+}
+
+fn default_step_seconds() -> u64 {
+    1
+}
+
+/// Scenario
+///
+/// A declarative description of a simulation run: its seed and length, the pools with their
+/// curves and initial reserves, the agents, and the process driving the reference price.
+/// Scenarios are read from JSON or TOML, so that experiments can be set up without writing
+/// Rust.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub seed: u64,
+    pub steps: u64,
+    /// The duration of a step, in seconds. Steps last one second by default.
+    #[serde(default = "default_step_seconds")]
+    pub step_seconds: u64,
+    #[serde(default)]
+    pub price: Option<PriceConfig>,
+    pub pools: Vec<PoolSpec>,
+    #[serde(default)]
+    pub agents: Vec<AgentConfig>,
+    /// The directory price series are read from, set to the scenario's own directory when it
+    /// is loaded from a file.
+    #[serde(skip)]
+    pub base: PathBuf,
+}
+
+impl Scenario {
+    /// Parses a scenario from JSON.
+    pub fn from_json(json: &str) -> Result<Self, ScenarioError> {
+        serde_json::from_str(json).map_err(ScenarioError::Json)
+    }
+
+    /// Parses a scenario from TOML.
+    pub fn from_toml(toml: &str) -> Result<Self, ScenarioError> {
+        toml::from_str(toml).map_err(ScenarioError::Toml)
+    }
+
+    /// Loads a scenario from a `.json` or `.toml` file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let mut scenario = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&contents)?,
+            Some("toml") => Self::from_toml(&contents)?,
+            _ => return Err(ScenarioError::UnknownFormat(path.to_path_buf())),
+        };
+        scenario.base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(scenario)
+    }
+
+    /// Creates the simulation described by the scenario, before its first step.
+    pub fn build(&self) -> Result<Simulation, ScenarioError> {
+        let mut simulation = Simulation::new(self.seed);
+        simulation.set_step_seconds(self.step_seconds);
+        if let Some(price) = &self.price {
+            simulation.set_boxed_price_process(price.build(&self.base)?);
+        }
+        for pool in &self.pools {
+            simulation.add_boxed_pool(pool.build());
+        }
+        for (agent, config) in self.agents.iter().enumerate() {
+            if config.pool() >= self.pools.len() {
+                return Err(ScenarioError::UnknownPool {
+                    agent,
+                    pool: config.pool(),
+                });
+            }
+            simulation.add_boxed_agent(config.build());
+        }
+        Ok(simulation)
+    }
+
+    /// Creates the simulation and runs all of its steps.
+    pub fn run(&self) -> Result<Simulation, ScenarioError> {
+        let mut simulation = self.build()?;
+        simulation.run(self.steps);
+        Ok(simulation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const TOML: &str = include_str!("../../scenarios/pid_vs_cpmm.toml");
+
+    #[test]
+    fn test_toml_scenario() {
+        let scenario = Scenario::from_toml(TOML).unwrap();
+        assert_eq!(scenario.pools.len(), 2);
+        assert_eq!(scenario.pools[1].fee, dec!(0.003));
+        assert!(matches!(
+            scenario.pools[0].curve,
+            PoolConfig::Pid { kp, .. } if kp == dec!(0.1)
+        ));
+
+        // The scenario runs reproducibly.
+        let first = scenario.run().unwrap();
+        let second = scenario.run().unwrap();
+        assert_eq!(first.records().len() as u64, scenario.steps + 1);
+        assert_eq!(first.records(), second.records());
+    }
+
+    #[test]
+    fn test_json_scenario() {
+        let json = r#"{
+            "seed": 1,
+            "steps": 10,
+            "price": {
+                "kind": "ornstein_uhlenbeck",
+                "price": 2, "mean": 2, "reversion": 0.1, "volatility": 0.01
+            },
+            "pools": [{ "curve": { "kind": "constant_sum" }, "reserves_a": 100, "reserves_b": 200 }],
+            "agents": [{ "kind": "arbitrageur", "pool": 0 }]
+        }"#;
+        let scenario = Scenario::from_json(json).unwrap();
+        assert_eq!(scenario.pools[0].curve, PoolConfig::ConstantSum);
+        assert_eq!(scenario.step_seconds, 1);
+        assert_eq!(scenario.run().unwrap().records().len(), 11);
+
+        // Agents must trade on a defined pool.
+        let mut scenario = scenario;
+        scenario.agents.push(AgentConfig::Arbitrageur {
+            pool: 1,
+            min_profit: Decimal::ZERO,
+        });
+        assert!(matches!(
+            scenario.build(),
+            Err(ScenarioError::UnknownPool { agent: 1, pool: 1 })
+        ));
+    }
+
+    /// A directory unique to the test that creates it, removed when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let nanos = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos();
+            let path =
+                std::env::temp_dir().join(format!("amm-{}-{}-{}", name, std::process::id(), nanos));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_series_relative_to_scenario() {
+        let temp = TempDir::new("scenario-test");
+        let directory = &temp.0;
+        fs::write(directory.join("prices.csv"), "price\n1\n1.1\n1.2\n").unwrap();
+        let path = directory.join("scenario.json");
+        fs::write(
+            &path,
+            r#"{ "seed": 0, "steps": 2, "price": { "kind": "series", "path": "prices.csv" },
+                 "pools": [{
+                     "curve": { "kind": "constant_product" }, "reserves_a": 10, "reserves_b": 10
+                 }] }"#,
+        )
+        .unwrap();
+
+        // The series is found next to the scenario and drives the reference price.
+        let simulation = Scenario::load(&path).unwrap().run().unwrap();
+        assert_eq!(simulation.reference_price(), Some(dec!(1.2)));
+        assert!(matches!(
+            Scenario::load(directory.join("prices.csv")),
+            Err(ScenarioError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,501 @@
+// Monte Carlo parameter sweeps
+
+use super::{
+    metrics::{self, PoolMetrics},
+    scenario::{Scenario, ScenarioError},
+};
+use crate::registry::PoolConfig;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    io::{self, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+/// A setting of a scenario varied by a sweep.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Axis {
+    /// Sets a parameter of a pool: its `fee`, its initial `reserves_a` or `reserves_b`, or, for
+    /// a PID curve, its `target`, `kp`, `ki` or `kd`.
+    Parameter {
+        pool: usize,
+        name: String,
+        values: Vec<Decimal>,
+    },
+    /// Replaces the curve of a pool.
+    Curve {
+        pool: usize,
+        curves: Vec<PoolConfig>,
+    },
+}
+
+impl Axis {
+    /// Returns the number of values the axis takes.
+    pub fn len(&self) -> usize {
+        match self {
+            Axis::Parameter { values, .. } => values.len(),
+            Axis::Curve { curves, .. } => curves.len(),
+        }
+    }
+
+    /// Returns `true` if the axis takes no value.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Describes the value at `index`, such as `kp[0]=0.1`.
+    pub fn label(&self, index: usize) -> String {
+        match self {
+            Axis::Parameter { pool, name, values } => {
+                format!("{}[{}]={}", name, pool, values[index].normalize())
+            }
+            Axis::Curve { pool, curves } => {
+                let curve = match &curves[index] {
+                    PoolConfig::ConstantProduct => "cpmm".to_string(),
+                    PoolConfig::ConstantSum => "csmm".to_string(),
+                    PoolConfig::Pid { target, kp, ki, kd } => format!(
+                        "pid({}/{}/{}/{})",
+                        target.normalize(),
+                        kp.normalize(),
+                        ki.normalize(),
+                        kd.normalize()
+                    ),
+                };
+                format!("curve[{}]={}", pool, curve)
+            }
+        }
+    }
+
+    /// Sets the value at `index` on a scenario.
+    pub fn apply(&self, index: usize, scenario: &mut Scenario) -> Result<(), SweepError> {
+        let pool = match self {
+            Axis::Parameter { pool, .. } | Axis::Curve { pool, .. } => *pool,
+        };
+        let spec = scenario
+            .pools
+            .get_mut(pool)
+            .ok_or(SweepError::UnknownPool(pool))?;
+        match self {
+            Axis::Parameter { name, values, .. } => {
+                let value = values[index];
+                match (name.as_str(), &mut spec.curve) {
+                    ("fee", _) => spec.fee = value,
+                    ("reserves_a", _) => spec.reserves_a = value,
+                    ("reserves_b", _) => spec.reserves_b = value,
+                    ("target", PoolConfig::Pid { target, .. }) => *target = value,
+                    ("kp", PoolConfig::Pid { kp, .. }) => *kp = value,
+                    ("ki", PoolConfig::Pid { ki, .. }) => *ki = value,
+                    ("kd", PoolConfig::Pid { kd, .. }) => *kd = value,
+                    _ => {
+                        return Err(SweepError::UnknownParameter {
+                            pool,
+                            name: name.clone(),
+                        })
+                    }
+                }
+            }
+            Axis::Curve { curves, .. } => spec.curve = curves[index].clone(),
+        }
+        Ok(())
+    }
+}
+
+/// Errors returned when loading or running a sweep.
+#[derive(Debug)]
+pub enum SweepError {
+    /// The sweep file or its scenario could not be loaded, or a run could not be built.
+    Scenario(ScenarioError),
+    /// An axis varies a pool the scenario does not define.
+    UnknownPool(usize),
+    /// An axis varies a parameter the pool does not have.
+    UnknownParameter { pool: usize, name: String },
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepError::Scenario(error) => write!(f, "{}", error),
+            SweepError::UnknownPool(pool) => write!(f, "the sweep varies unknown pool {}", pool),
+            SweepError::UnknownParameter { pool, name } => {
+                write!(f, "pool {} has no parameter {:?}", pool, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+impl From<ScenarioError> for SweepError {
+    fn from(error: ScenarioError) -> Self {
+        SweepError::Scenario(error)
+    }
+}
+
+impl From<io::Error> for SweepError {
+    fn from(error: io::Error) -> Self {
+        SweepError::Scenario(ScenarioError::Io(error))
+    }
+}
+
+/// The sample of a metric over the seeds of a combination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Distribution {
+    samples: Vec<Decimal>,
+}
+
+impl Distribution {
+    /// Collects samples. Returns `None` if there are none.
+    pub fn new<I: IntoIterator<Item = Decimal>>(samples: I) -> Option<Self> {
+        let mut samples: Vec<Decimal> = samples.into_iter().collect();
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort();
+        Some(Self { samples })
+    }
+
+    /// Returns the samples, in increasing order.
+    pub fn samples(&self) -> &[Decimal] {
+        &self.samples
+    }
+
+    /// Returns the mean of the samples.
+    pub fn mean(&self) -> Decimal {
+        self.samples.iter().sum::<Decimal>() / Decimal::from(self.samples.len())
+    }
+
+    /// Returns the nearest-rank percentile of the samples, for a `percent` between 0 and 100.
+    pub fn percentile(&self, percent: u32) -> Decimal {
+        let rank = (self.samples.len() * percent.min(100) as usize).div_ceil(100);
+        self.samples[rank.saturating_sub(1)]
+    }
+}
+
+/// The distributions of the metrics of a pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolSummary {
+    pub impermanent_loss: Distribution,
+    pub loss_versus_rebalancing: Option<Distribution>,
+    pub fees: Distribution,
+    pub fee_apr: Distribution,
+    pub volatility: Distribution,
+    /// The RMS tracking error, for pools with a `target` parameter.
+    pub tracking_error: Option<Distribution>,
+}
+
+impl PoolSummary {
+    /// Summarizes the metrics of a pool over several runs. Returns `None` if there are none.
+    pub fn new(runs: &[PoolMetrics]) -> Option<Self> {
+        Some(Self {
+            impermanent_loss: Distribution::new(runs.iter().map(|run| run.impermanent_loss))?,
+            loss_versus_rebalancing: Distribution::new(
+                runs.iter().filter_map(|run| run.loss_versus_rebalancing),
+            ),
+            fees: Distribution::new(runs.iter().map(|run| run.fees))?,
+            fee_apr: Distribution::new(runs.iter().map(|run| run.fee_apr))?,
+            volatility: Distribution::new(runs.iter().map(|run| run.volatility))?,
+            tracking_error: Distribution::new(
+                runs.iter()
+                    .filter_map(|run| run.tracking.map(|tracking| tracking.rms_error)),
+            ),
+        })
+    }
+}
+
+/// The result of the runs of one combination of settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// The label of each axis' value, in the order of the axes.
+    pub settings: Vec<String>,
+    pub runs: usize,
+    /// The summary of each pool of the scenario, in order.
+    pub pools: Vec<Option<PoolSummary>>,
+}
+
+/// A sweep as read from a file, naming its scenario by path.
+#[derive(Deserialize)]
+struct SweepFile {
+    scenario: PathBuf,
+    seeds: u64,
+    #[serde(default)]
+    threads: Option<usize>,
+    #[serde(default)]
+    axes: Vec<Axis>,
+}
+
+/// Monte Carlo parameter sweep
+///
+/// Runs a scenario for every combination of the values of its axes, each over `seeds`
+/// consecutive seeds starting at the scenario's own. Every combination is run over the same
+/// seeds, so that differences between them are not down to luck. Runs are spread over
+/// `threads` threads, each building its own simulation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sweep {
+    pub scenario: Scenario,
+    pub axes: Vec<Axis>,
+    pub seeds: u64,
+    pub threads: usize,
+}
+
+impl Sweep {
+    /// Creates a sweep of a scenario with no axis, over as many threads as are available.
+    pub fn new(scenario: Scenario, seeds: u64) -> Self {
+        Self {
+            scenario,
+            axes: Vec::new(),
+            seeds,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        }
+    }
+
+    /// Loads a sweep from a `.json` or `.toml` file, which names its scenario by a path
+    /// relative to the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SweepError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)?;
+        let file: SweepFile = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&contents).map_err(ScenarioError::Json)?,
+            Some("toml") => toml::from_str(&contents).map_err(ScenarioError::Toml)?,
+            _ => return Err(ScenarioError::UnknownFormat(path.to_path_buf()).into()),
+        };
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut sweep = Self::new(Scenario::load(base.join(file.scenario))?, file.seeds);
+        sweep.axes = file.axes;
+        if let Some(threads) = file.threads {
+            sweep.threads = threads;
+        }
+        Ok(sweep)
+    }
+
+    /// Returns the scenario of every combination of settings, with their labels.
+    pub fn combinations(&self) -> Result<Vec<(Vec<String>, Scenario)>, SweepError> {
+        let mut combinations = vec![(Vec::new(), self.scenario.clone())];
+        for axis in &self.axes {
+            let mut next = Vec::with_capacity(combinations.len() * axis.len());
+            for (settings, scenario) in &combinations {
+                for index in 0..axis.len() {
+                    let mut settings = settings.clone();
+                    settings.push(axis.label(index));
+                    let mut scenario = scenario.clone();
+                    axis.apply(index, &mut scenario)?;
+                    next.push((settings, scenario));
+                }
+            }
+            combinations = next;
+        }
+        Ok(combinations)
+    }
+
+    /// Runs every combination over every seed, and summarizes the metrics of each.
+    pub fn run(&self) -> Result<Vec<Summary>, SweepError> {
+        let combinations = self.combinations()?;
+        let seeds = self.seeds as usize;
+        let jobs = combinations.len() * seeds;
+        let results = Mutex::new((0..jobs).map(|_| None).collect::<Vec<_>>());
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..self.threads.clamp(1, jobs.max(1)) {
+                scope.spawn(|| loop {
+                    let job = next.fetch_add(1, Ordering::Relaxed);
+                    if job >= jobs {
+                        break;
+                    }
+                    let mut scenario = combinations[job / seeds].1.clone();
+                    scenario.seed = scenario.seed.wrapping_add((job % seeds) as u64);
+                    let result = scenario.run().map(|simulation| {
+                        (0..simulation.pools().len())
+                            .map(|pool| metrics::pool_metrics(simulation.records(), pool))
+                            .collect::<Vec<_>>()
+                    });
+                    results.lock().unwrap_or_else(|error| error.into_inner())[job] = Some(result);
+                });
+            }
+        });
+
+        let mut results = results
+            .into_inner()
+            .unwrap_or_else(|error| error.into_inner())
+            .into_iter()
+            .flatten();
+        let mut summaries = Vec::with_capacity(combinations.len());
+        for (settings, scenario) in combinations {
+            let mut runs = vec![Vec::with_capacity(seeds); scenario.pools.len()];
+            for _ in 0..seeds {
+                if let Some(result) = results.next() {
+                    for (pool, metrics) in result?.into_iter().enumerate() {
+                        runs[pool].extend(metrics);
+                    }
+                }
+            }
+            summaries.push(Summary {
+                settings,
+                runs: seeds,
+                pools: runs.iter().map(|runs| PoolSummary::new(runs)).collect(),
+            });
+        }
+        Ok(summaries)
+    }
+}
+
+/// Formats a distribution as its mean followed by its 5th and 95th percentiles.
+fn cell(distribution: Option<&Distribution>) -> String {
+    distribution.map_or("-".to_string(), |distribution| {
+        let round = |value: Decimal| value.round_dp(6).normalize();
+        format!(
+            "{} [{}, {}]",
+            round(distribution.mean()),
+            round(distribution.percentile(5)),
+            round(distribution.percentile(95))
+        )
+    })
+}
+
+/// Writes the summaries as a table with one row per combination and pool.
+///
+/// Each metric is shown as its mean followed by its 5th and 95th percentiles.
+pub fn write_table<W: Write>(mut writer: W, summaries: &[Summary]) -> io::Result<()> {
+    let mut rows = vec![[
+        "settings",
+        "pool",
+        "runs",
+        "impermanent loss",
+        "LVR",
+        "fees",
+        "fee APR",
+        "volatility",
+        "tracking error",
+    ]
+    .map(String::from)];
+    for summary in summaries {
+        for (pool, metrics) in summary.pools.iter().enumerate() {
+            let Some(metrics) = metrics else {
+                continue;
+            };
+            rows.push([
+                summary.settings.join(" "),
+                pool.to_string(),
+                summary.runs.to_string(),
+                cell(Some(&metrics.impermanent_loss)),
+                cell(metrics.loss_versus_rebalancing.as_ref()),
+                cell(Some(&metrics.fees)),
+                cell(Some(&metrics.fee_apr)),
+                cell(Some(&metrics.volatility)),
+                cell(metrics.tracking_error.as_ref()),
+            ]);
+        }
+    }
+
+    let mut widths = [0; 9];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in &rows {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        writeln!(writer, "{}", cells.join("  ").trim_end())?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn sweep() -> Sweep {
+        let mut scenario =
+            Scenario::from_toml(include_str!("../../scenarios/pid_vs_cpmm.toml")).unwrap();
+        scenario.steps = 50;
+        let mut sweep = Sweep::new(scenario, 4);
+        sweep.axes = vec![
+            Axis::Parameter {
+                pool: 0,
+                name: "kp".to_string(),
+                values: vec![dec!(0.05), dec!(0.2)],
+            },
+            Axis::Parameter {
+                pool: 1,
+                name: "fee".to_string(),
+                values: vec![dec!(0), dec!(0.003), dec!(0.01)],
+            },
+        ];
+        sweep
+    }
+
+    #[test]
+    fn test_distribution() {
+        let distribution = Distribution::new((1..=20).rev().map(Decimal::from)).unwrap();
+        assert_eq!(distribution.mean(), dec!(10.5));
+        assert_eq!(distribution.percentile(0), dec!(1));
+        assert_eq!(distribution.percentile(5), dec!(1));
+        assert_eq!(distribution.percentile(50), dec!(10));
+        assert_eq!(distribution.percentile(95), dec!(19));
+        assert_eq!(distribution.percentile(100), dec!(20));
+        assert_eq!(Distribution::new(Vec::new()), None);
+    }
+
+    #[test]
+    fn test_combinations() {
+        let combinations = sweep().combinations().unwrap();
+        assert_eq!(combinations.len(), 6);
+        let (settings, scenario) = &combinations[5];
+        assert_eq!(settings, &["kp[0]=0.2", "fee[1]=0.01"]);
+        assert_eq!(scenario.pools[1].fee, dec!(0.01));
+        assert!(matches!(scenario.pools[0].curve, PoolConfig::Pid { kp, .. } if kp == dec!(0.2)));
+
+        // Parameters are checked against the curve of the pool.
+        let mut sweep = sweep();
+        sweep.axes[1] = Axis::Parameter {
+            pool: 1,
+            name: "kp".to_string(),
+            values: vec![dec!(0.1)],
+        };
+        assert!(matches!(
+            sweep.combinations(),
+            Err(SweepError::UnknownParameter { pool: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_run() {
+        let mut sweep = sweep();
+        let summaries = sweep.run().unwrap();
+        assert_eq!(summaries.len(), 6);
+        for summary in &summaries {
+            assert_eq!(summary.runs, 4);
+            let pid = summary.pools[0].as_ref().unwrap();
+            assert_eq!(pid.impermanent_loss.samples().len(), 4);
+            assert!(pid.tracking_error.is_some());
+            assert!(summary.pools[1].as_ref().unwrap().tracking_error.is_none());
+        }
+
+        // Higher fees collect more, and the fee-free pool collects nothing.
+        let fees = |summary: &Summary| summary.pools[1].as_ref().unwrap().fees.mean();
+        assert_eq!(fees(&summaries[0]), Decimal::ZERO);
+        assert!(fees(&summaries[2]) > fees(&summaries[1]));
+
+        // The results do not depend on the number of threads.
+        sweep.threads = 1;
+        assert_eq!(sweep.run().unwrap(), summaries);
+
+        let mut table = Vec::new();
+        write_table(&mut table, &summaries).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        assert_eq!(table.lines().count(), 13);
+        let row: Vec<&str> = table.lines().nth(1).unwrap().split_whitespace().collect();
+        assert_eq!(row[..4], ["kp[0]=0.05", "fee[1]=0", "0", "4"]);
+    }
+}